@@ -0,0 +1,552 @@
+//! Implements the audio processing unit (APU) and its registers at `0xFF10..=0xFF3F`.
+//!
+//! The APU mixes four channels: two pulse channels, the first one with a frequency sweep, the
+//! wave channel playing back wave RAM and the noise channel. Each channel's timer runs off the
+//! T-state clock, while lengths, envelopes and the sweep are clocked by the frame sequencer at
+//! 512 Hz, on the falling edge of DIV bit 4:
+//!
+//! | step     | 0 | 1 | 2 | 3 | 4 | 5 | 6 | 7 |
+//! | -------- | - | - | - | - | - | - | - | - |
+//! | length   | x |   | x |   | x |   | x |   |
+//! | sweep    |   |   | x |   |   |   | x |   |
+//! | envelope |   |   |   |   |   |   |   | x |
+//!
+//! Clearing bit 7 of NR52 powers the APU off, clearing every register but wave RAM and the
+//! length counters and ignoring writes to them until it is powered on again.
+//!
+//! For debugging, channels can be muted or soloed in the mixer, and each channel's DAC output
+//! can be tapped before mixing. Neither is visible to the game.
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use noise::Noise;
+pub use output::{AudioOutput, HighPass, CLOCK_RATE};
+use pulse::Pulse;
+pub use vgm::VgmLog;
+use vgm::VgmLogger;
+use wav::Recorder;
+pub use wav::WavRecording;
+use wave::Wave;
+
+/// Noise channel 4
+mod noise;
+/// Resampling to the host sample rate
+mod output;
+/// Pulse channels 1 and 2
+mod pulse;
+/// Length counters and volume envelopes
+mod units;
+/// VGM logging of register writes
+mod vgm;
+/// WAV recording
+mod wav;
+/// Wave channel 3
+mod wave;
+
+/// Counter bit of the timer whose falling edge clocks the frame sequencer, DIV bit 4
+const FRAME_SEQUENCER_BIT: u16 = 1 << 12;
+
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+const NR52_POWER: u8 = 0b_1000_0000;
+
+/// Bits of `0xFF10..=0xFF2F` that always read as 1. Write-only and unused bits are among them.
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // unused, NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // unused, NR41-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // unused
+];
+
+/// One of the four sound channels
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Wave,
+    Noise,
+}
+
+impl Channel {
+    /// Every channel, in the bit order of NR51 and NR52
+    pub const ALL: [Self; 4] = [Self::Pulse1, Self::Pulse2, Self::Wave, Self::Noise];
+
+    const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Audio processing unit
+#[derive(Debug)]
+pub struct Apu {
+    powered: bool,
+    /// Last values written to `0xFF10..=0xFF2F`, for reading back
+    registers: [u8; 0x20],
+    pulse1: Pulse,
+    pulse2: Pulse,
+    wave: Wave,
+    noise: Noise,
+    /// Next step of the frame sequencer, `0..8`
+    frame_step: u8,
+    /// Frame sequencer bit of the timer counter as last seen
+    div_bit: bool,
+    /// T-states ticked since creation
+    clock: u64,
+    audio_output: Option<AudioOutput>,
+    recorder: Option<Recorder>,
+    vgm_logger: Option<VgmLogger>,
+    /// Channels left out of the mixer
+    muted: [bool; 4],
+    /// Channels the mixer is limited to, if any
+    soloed: [bool; 4],
+    /// Resamplers of each channel's DAC output
+    taps: [Option<AudioOutput>; 4],
+}
+
+impl Apu {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            powered: false,
+            registers: [0; 0x20],
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            frame_step: 0,
+            div_bit: false,
+            clock: 0,
+            audio_output: None,
+            recorder: None,
+            vgm_logger: None,
+            muted: [false; 4],
+            soloed: [false; 4],
+            taps: [None, None, None, None],
+        }
+    }
+
+    /// Starts resampling the mixer output with `output` from now on, or stops if `None`.
+    ///
+    /// # Return value
+    /// The output previously set up, with any samples not read yet.
+    pub fn set_audio_output(&mut self, mut output: Option<AudioOutput>) -> Option<AudioOutput> {
+        if let Some(output) = &mut output {
+            output.start_at(self.clock);
+        }
+        std::mem::replace(&mut self.audio_output, output)
+    }
+
+    /// Starts recording the mixer output from now on, replacing any recording in progress.
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Sample rate of the recording in Hz
+    /// * `high_pass` - Which console's output capacitor to model
+    pub fn start_recording(&mut self, sample_rate: u32, high_pass: HighPass) {
+        let mut output = AudioOutput::new(sample_rate, high_pass);
+        output.start_at(self.clock);
+        self.recorder = Some(Recorder::new(output));
+    }
+
+    /// Stops recording.
+    ///
+    /// # Return value
+    /// Everything recorded since `Apu::start_recording`, if recording.
+    pub fn stop_recording(&mut self) -> Option<WavRecording> {
+        self.recorder.take().map(Recorder::finish)
+    }
+
+    #[must_use]
+    pub const fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Where samples at the host rate can be read from, if set up.
+    pub const fn audio_output_mut(&mut self) -> Option<&mut AudioOutput> {
+        self.audio_output.as_mut()
+    }
+
+    /// Mutes or unmutes `channel` in the mixer. The channel keeps running, so NR52 and the
+    /// channel's tap are unaffected.
+    pub const fn set_muted(&mut self, channel: Channel, muted: bool) {
+        self.muted[channel.index()] = muted;
+    }
+
+    #[must_use]
+    pub const fn is_muted(&self, channel: Channel) -> bool {
+        self.muted[channel.index()]
+    }
+
+    /// Solos or unsolos `channel` in the mixer. While any channel is soloed, the mixer only
+    /// plays the soloed ones that aren't muted.
+    pub const fn set_soloed(&mut self, channel: Channel, soloed: bool) {
+        self.soloed[channel.index()] = soloed;
+    }
+
+    #[must_use]
+    pub const fn is_soloed(&self, channel: Channel) -> bool {
+        self.soloed[channel.index()]
+    }
+
+    /// Whether the mixer plays `channel`, given the muted and soloed channels
+    #[must_use]
+    pub fn is_audible(&self, channel: Channel) -> bool {
+        let index = channel.index();
+        !self.muted[index] && (self.soloed[index] || !self.soloed.contains(&true))
+    }
+
+    /// Starts resampling the DAC output of `channel` with `tap` from now on, or stops if `None`.
+    /// Both sides of the tap carry the channel's level before panning, volume, muting and
+    /// soloing, for oscilloscope views or exporting channels separately.
+    ///
+    /// # Return value
+    /// The tap previously set up, with any samples not read yet.
+    pub fn set_channel_tap(
+        &mut self,
+        channel: Channel,
+        mut tap: Option<AudioOutput>,
+    ) -> Option<AudioOutput> {
+        if let Some(tap) = &mut tap {
+            tap.start_at(self.clock);
+        }
+        std::mem::replace(&mut self.taps[channel.index()], tap)
+    }
+
+    /// Where the samples of `channel` at the host rate can be read from, if tapped.
+    pub const fn channel_tap_mut(&mut self, channel: Channel) -> Option<&mut AudioOutput> {
+        self.taps[channel.index()].as_mut()
+    }
+
+    /// Starts logging register writes to a VGM log from now on, replacing any log in progress.
+    ///
+    /// The log opens with the current state of the registers and wave RAM, without triggering
+    /// any channel, so what is already playing is only heard from its next trigger.
+    pub fn start_vgm_log(&mut self) {
+        let mut logger = VgmLogger::new(self.clock);
+
+        logger.write(self.clock, NR52, self.read_register(NR52) & NR52_POWER);
+        for (address, value) in (0xFF30..).zip(&self.wave.ram()) {
+            logger.write(self.clock, address, *value);
+        }
+        if self.powered {
+            for (address, value) in (0xFF10..NR52).zip(&self.registers) {
+                let value = match address {
+                    0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => value & 0b_0111_1111,
+                    _ => *value,
+                };
+                logger.write(self.clock, address, value);
+            }
+        }
+
+        self.vgm_logger = Some(logger);
+    }
+
+    /// Stops logging register writes.
+    ///
+    /// # Return value
+    /// Every write since `Apu::start_vgm_log`, if logging.
+    pub fn stop_vgm_log(&mut self) -> Option<VgmLog> {
+        let clock = self.clock;
+        self.vgm_logger.take().map(|logger| logger.finish(clock))
+    }
+
+    #[must_use]
+    pub const fn is_vgm_logging(&self) -> bool {
+        self.vgm_logger.is_some()
+    }
+
+    /// Reads one of the APU registers or wave RAM in `0xFF10..=0xFF3F`
+    #[must_use]
+    pub const fn read_register(&self, address: u16) -> u8 {
+        match address {
+            NR52 => {
+                let channels = [
+                    self.pulse1.enabled(),
+                    self.pulse2.enabled(),
+                    self.wave.enabled(),
+                    self.noise.enabled(),
+                ];
+                let mut value = READ_MASKS[(NR52 - 0xFF10) as usize];
+                if self.powered {
+                    value |= NR52_POWER;
+                }
+                let mut i = 0;
+                while i < channels.len() {
+                    if channels[i] {
+                        value |= 1 << i;
+                    }
+                    i += 1;
+                }
+                value
+            }
+            0xFF10..=0xFF2F => {
+                let index = (address - 0xFF10) as usize;
+                self.registers[index] | READ_MASKS[index]
+            }
+            0xFF30..=0xFF3F => self.wave.read_ram(address),
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes one of the APU registers or wave RAM in `0xFF10..=0xFF3F`
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        if let Some(logger) = &mut self.vgm_logger {
+            logger.write(self.clock, address, value);
+        }
+
+        match address {
+            NR52 => self.set_power(value & NR52_POWER != 0),
+            0xFF30..=0xFF3F => self.wave.write_ram(address, value),
+            // on DMG only the length counters can be written while powered off
+            0xFF10..=0xFF25 if !self.powered => match address {
+                0xFF11 => self.pulse1.write_length(value),
+                0xFF16 => self.pulse2.write_length(value),
+                0xFF1B => self.wave.write_length(value),
+                0xFF20 => self.noise.write_length(value),
+                _ => {}
+            },
+            0xFF10..=0xFF25 => {
+                self.registers[usize::from(address - 0xFF10)] = value;
+
+                // the frame sequencer steps that clock length are the even ones
+                let extra_clock = !self.frame_step.is_multiple_of(2);
+                match address {
+                    0xFF10..=0xFF14 => self.pulse1.write(address - 0xFF10, value, extra_clock),
+                    0xFF15..=0xFF19 => self.pulse2.write(address - 0xFF15, value, extra_clock),
+                    0xFF1A..=0xFF1E => self.wave.write(address - 0xFF1A, value, extra_clock),
+                    0xFF1F..=0xFF23 => self.noise.write(address - 0xFF1F, value, extra_clock),
+                    _ => {} // NR50 and NR51 are only read back by the mixer
+                }
+            }
+            _ => {}
+        }
+    }
+
+    const fn set_power(&mut self, on: bool) {
+        match (self.powered, on) {
+            (true, false) => {
+                self.registers = [0; 0x20];
+                self.pulse1 = self.pulse1.powered_off();
+                self.pulse2 = self.pulse2.powered_off();
+                self.wave = self.wave.powered_off();
+                self.noise = self.noise.powered_off();
+            }
+            (false, true) => self.frame_step = 0,
+            _ => {}
+        }
+        self.powered = on;
+    }
+
+    /// Advances the channels by `t_states` clock ticks, feeding the audio output, the
+    /// recording and the channel taps if set up.
+    pub fn tick(&mut self, t_states: u32) {
+        let sampled = self.audio_output.is_some() || self.recorder.is_some();
+        let tapped = self.taps.iter().any(Option::is_some);
+
+        let mut remaining = t_states;
+        // the first tick may follow a register write, so it's never skipped
+        let mut quiet = 0;
+        while remaining > 0 {
+            let span = match quiet.min(remaining) {
+                0 => {
+                    self.tick_once(sampled, tapped);
+                    1
+                }
+                span => {
+                    self.skip(span);
+                    span
+                }
+            };
+            remaining -= span;
+            quiet = self.quiet_ticks();
+        }
+
+        if let Some(output) = &mut self.audio_output {
+            output.advance(self.clock);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.output.advance(self.clock);
+            recorder.collect();
+        }
+        for tap in self.taps.iter_mut().flatten() {
+            tap.advance(self.clock);
+        }
+    }
+
+    fn tick_once(&mut self, sampled: bool, tapped: bool) {
+        if self.powered {
+            self.pulse1.tick();
+            self.pulse2.tick();
+            self.wave.tick();
+            self.noise.tick();
+        }
+        if sampled {
+            let level = self.output();
+            if let Some(output) = &mut self.audio_output {
+                output.set_level(self.clock, level);
+            }
+            if let Some(recorder) = &mut self.recorder {
+                recorder.output.set_level(self.clock, level);
+            }
+        }
+        if tapped {
+            let levels = self.channel_levels();
+            for (tap, level) in self.taps.iter_mut().zip(levels) {
+                if let Some(tap) = tap {
+                    tap.set_level(self.clock, [level; 2]);
+                }
+            }
+        }
+        self.clock += 1;
+    }
+
+    /// Ticks before the next one in which a channel steps, which can't change the output.
+    fn quiet_ticks(&self) -> u32 {
+        if !self.powered {
+            return u32::MAX;
+        }
+
+        let until_step = self
+            .pulse1
+            .until_step()
+            .min(self.pulse2.until_step())
+            .min(self.wave.until_step())
+            .min(self.noise.until_step());
+        until_step - 1
+    }
+
+    /// Advances by `t_states` quiet ticks, see `Apu::quiet_ticks`.
+    fn skip(&mut self, t_states: u32) {
+        if self.powered {
+            self.pulse1.skip(t_states);
+            self.pulse2.skip(t_states);
+            self.wave.skip(t_states);
+            self.noise.skip(t_states);
+        }
+        self.clock += u64::from(t_states);
+    }
+
+    /// Clock ticks until the one in which the frame sequencer steps, given the timer's counter.
+    #[must_use]
+    pub fn next_frame_sequencer_step(counter: u16) -> u32 {
+        // on the falling edge, when the counter wraps around to a multiple of twice the bit
+        let period = FRAME_SEQUENCER_BIT << 1;
+        u32::from(period - (counter & (period - 1)))
+    }
+
+    /// Clocks the frame sequencer on falling edges of DIV bit 4. To be called with the timer's
+    /// counter whenever it changes, including when DIV is reset by a write.
+    pub const fn clock_div(&mut self, counter: u16) {
+        let bit = counter & FRAME_SEQUENCER_BIT != 0;
+        if self.div_bit && !bit {
+            self.step_frame_sequencer();
+        }
+        self.div_bit = bit;
+    }
+
+    const fn step_frame_sequencer(&mut self) {
+        if !self.powered {
+            return;
+        }
+
+        if self.frame_step.is_multiple_of(2) {
+            self.pulse1.clock_length();
+            self.pulse2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.pulse1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.pulse1.clock_envelope();
+            self.pulse2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    /// Current output of each channel's DAC, in the order of `Channel::ALL`. Each DAC maps its
+    /// channel's digital output `0..=15` to `-1.0..=1.0`.
+    #[must_use]
+    pub fn channel_levels(&self) -> [f32; 4] {
+        [
+            dac(self.pulse1.output(), self.pulse1.dac_enabled()),
+            dac(self.pulse2.output(), self.pulse2.dac_enabled()),
+            dac(self.wave.output(), self.wave.dac_enabled()),
+            dac(self.noise.output(), self.noise.dac_enabled()),
+        ]
+    }
+
+    /// Current output of the mixer as `[left, right]`, each in `-1.0..=1.0`.
+    ///
+    /// NR51 routes the channel levels to either side and NR50 scales each side by
+    /// `(volume + 1) / 8`. Muted channels, and channels left out by a solo, are silent.
+    #[must_use]
+    pub fn output(&self) -> [f32; 2] {
+        let mut channels = self.channel_levels();
+        for (level, channel) in channels.iter_mut().zip(Channel::ALL) {
+            if !self.is_audible(channel) {
+                *level = 0.0;
+            }
+        }
+        let panning = self.registers[usize::from(NR51 - 0xFF10)];
+        let volume = self.registers[usize::from(NR50 - 0xFF10)];
+
+        let mix = |routing: u8, volume: u8| {
+            let sum: f32 = (0..4)
+                .filter(|channel| routing & (1 << channel) != 0)
+                .map(|channel| channels[channel])
+                .sum();
+            sum / 4.0 * f32::from(volume + 1) / 8.0
+        };
+
+        [
+            mix(panning >> 4, (volume >> 4) & 0b111),
+            mix(panning & 0x0F, volume & 0b111),
+        ]
+    }
+
+    /// Saves the registers and every channel. Audio outputs, recordings, taps and the mixer's
+    /// mute and solo settings are the host's and aren't saved.
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.powered);
+        state.bytes(&self.registers);
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        self.wave.save_state(state);
+        self.noise.save_state(state);
+        state.u8(self.frame_step);
+        state.bool(self.div_bit);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.powered = state.bool()?;
+        state.read_into(&mut self.registers)?;
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.wave.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.frame_step = state.u8()? % 8;
+        self.div_bit = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts a channel's digital output to an analog level. A disabled DAC outputs 0.
+fn dac(output: u8, enabled: bool) -> f32 {
+    match enabled {
+        true => f32::from(output) / 7.5 - 1.0,
+        false => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests;
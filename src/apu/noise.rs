@@ -0,0 +1,167 @@
+//! Noise channel 4, outputting the low bit of a linear feedback shift register.
+use super::units::{Envelope, LengthCounter};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+/// T-states between LFSR clocks for each divisor code of `NR43`, before the shift
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Noise channel
+#[derive(Debug)]
+pub(super) struct Noise {
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    shift: u8,
+    /// Whether the LFSR is shortened to 7 bits
+    short_mode: bool,
+    divisor_code: u8,
+    /// T-states left until the next LFSR clock
+    timer: u32,
+    lfsr: u16,
+}
+
+impl Noise {
+    pub(super) const fn new() -> Self {
+        Self {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0,
+        }
+    }
+
+    pub(super) const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) const fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Writes `NR41` to `NR44`, `register` being the offset from the unused `0xFF1F`.
+    ///
+    /// # Arguments
+    /// * `extra_clock` - Whether the last frame sequencer step clocked length
+    pub(super) const fn write(&mut self, register: u16, value: u8, extra_clock: bool) {
+        match register {
+            1 => self.length.load(value & 0b_0011_1111),
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.shift = value >> 4;
+                self.short_mode = value & 0b_0000_1000 != 0;
+                self.divisor_code = value & 0b111;
+            }
+            4 => {
+                let trigger = value & 0b_1000_0000 != 0;
+                let enable = value & 0b_0100_0000 != 0;
+                if self.length.write_control(enable, trigger, extra_clock) {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.enabled = self.dac_enabled();
+                    self.timer = self.period();
+                    self.lfsr = 0x7FFF;
+                    self.envelope.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes the length data of `NR41`, the only write that goes through while powered off.
+    pub(super) const fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0b_0011_1111);
+    }
+
+    const fn period(&self) -> u32 {
+        DIVISORS[self.divisor_code as usize] << self.shift
+    }
+
+    /// T-states until the one in which the channel next steps
+    pub(super) fn until_step(&self) -> u32 {
+        self.timer.max(1)
+    }
+
+    /// Advances the channel by `t_states` T-states, fewer than `until_step`.
+    pub(super) const fn skip(&mut self, t_states: u32) {
+        self.timer -= t_states;
+    }
+
+    /// Advances the channel by one T-state.
+    pub(super) const fn tick(&mut self) {
+        if self.timer <= 1 {
+            self.timer = self.period();
+            self.clock_lfsr();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Shifts the LFSR right, feeding the XOR of its two low bits into bit 14, and bit 6 too
+    /// in short mode.
+    const fn clock_lfsr(&mut self) {
+        let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        if self.short_mode {
+            self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+        }
+    }
+
+    pub(super) const fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) const fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Digital output, `0..=15`
+    pub(super) const fn output(&self) -> u8 {
+        match self.enabled && self.lfsr & 1 == 0 {
+            true => self.envelope.volume(),
+            false => 0,
+        }
+    }
+
+    /// State after the APU is powered off.
+    pub(super) const fn powered_off(&self) -> Self {
+        Self {
+            length: self.length.powered_off(),
+            ..Self::new()
+        }
+    }
+
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.u8(self.shift);
+        state.bool(self.short_mode);
+        state.u8(self.divisor_code);
+        state.u32(self.timer);
+        state.u16(self.lfsr);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.enabled = state.bool()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.shift = state.u8()? & 0x0F;
+        self.short_mode = state.bool()?;
+        self.divisor_code = state.u8()? & 0b111;
+        self.timer = state.u32()?;
+        self.lfsr = state.u16()? & 0x7FFF;
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+//! Band-limited resampling of the mixer output to a host sample rate.
+//!
+//! The mixer output is a step function of emulated time. Every step is added to the output as
+//! a windowed-sinc impulse at its exact fractional position, and the impulses are integrated
+//! into samples. That produces band-limited steps, so nothing above the host Nyquist frequency
+//! aliases back into the audible range. The capacitor of the console's output stage is then
+//! modelled by a high-pass filter.
+use std::{
+    collections::VecDeque,
+    convert::{TryFrom, TryInto},
+};
+
+/// T-states per second
+pub const CLOCK_RATE: u32 = 4_194_304;
+
+/// Taps of the impulse kernel. Output lags emulated time by half of them.
+const KERNEL_WIDTH: usize = 32;
+/// Fractional positions the kernel is precomputed for
+const KERNEL_PHASES: u32 = 64;
+/// Cutoff of the impulse kernel, in cycles per output sample
+const KERNEL_CUTOFF: f64 = 0.45;
+
+/// High-pass filter removing the DC offset of the DACs, as the capacitor on the console's
+/// audio output does.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HighPass {
+    /// No filtering
+    Off,
+    /// The DMG's capacitor
+    Dmg,
+    /// The CGB's capacitor, which charges faster
+    Cgb,
+}
+
+impl HighPass {
+    /// How much of the capacitor's charge is kept per T-state
+    const fn charge_factor(self) -> Option<f64> {
+        match self {
+            Self::Off => None,
+            Self::Dmg => Some(0.999_958),
+            Self::Cgb => Some(0.998_943),
+        }
+    }
+}
+
+/// Turns the mixer output into interleaved stereo samples at a host sample rate.
+///
+/// Samples accumulate as the APU is ticked until they are read with `AudioOutput::read_f32` or
+/// `AudioOutput::read_i16`.
+#[derive(Debug)]
+pub struct AudioOutput {
+    sample_rate: u32,
+    /// `KERNEL_PHASES` kernels, each summing to 1
+    kernels: Vec<[f32; KERNEL_WIDTH]>,
+    /// Pending impulses, `deltas[0]` being output sample `first_sample`
+    deltas: VecDeque<[f32; 2]>,
+    first_sample: u64,
+    /// Output samples up to this one are complete
+    end_sample: u64,
+    /// Mixer output as of the last step
+    level: [f32; 2],
+    /// Running sum of the impulses
+    integrator: [f32; 2],
+    /// Charge kept per output sample, if filtering
+    charge: Option<f32>,
+    capacitor: [f32; 2],
+}
+
+impl AudioOutput {
+    /// Creates an output producing `sample_rate` stereo samples per emulated second.
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Host sample rate in Hz, for example 44100 or 48000
+    /// * `high_pass` - Which console's output capacitor to model
+    ///
+    /// # Panics
+    /// If `sample_rate` is 0 or not below `CLOCK_RATE`.
+    #[must_use]
+    pub fn new(sample_rate: u32, high_pass: HighPass) -> Self {
+        assert!(
+            sample_rate > 0 && sample_rate < CLOCK_RATE,
+            "sample rate out of range"
+        );
+
+        let samples_per_clock = f64::from(CLOCK_RATE) / f64::from(sample_rate);
+        #[allow(clippy::cast_possible_truncation)]
+        let charge = high_pass
+            .charge_factor()
+            .map(|factor| factor.powf(samples_per_clock) as f32);
+
+        Self {
+            sample_rate,
+            kernels: (0..KERNEL_PHASES).map(kernel).collect(),
+            deltas: VecDeque::new(),
+            first_sample: 0,
+            end_sample: 0,
+            level: [0.0; 2],
+            integrator: [0.0; 2],
+            charge,
+            capacitor: [0.0; 2],
+        }
+    }
+
+    #[must_use]
+    pub const fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Stereo samples ready to be read
+    #[must_use]
+    pub fn samples_available(&self) -> usize {
+        to_index(self.end_sample - self.first_sample)
+    }
+
+    /// Output sample and kernel phase of emulated time `clock`, in T-states
+    fn position(&self, clock: u64) -> (u64, usize) {
+        let scaled = u128::from(clock) * u128::from(self.sample_rate);
+        let sample = u64::try_from(scaled / u128::from(CLOCK_RATE)).expect("clock in range");
+        let phase =
+            scaled % u128::from(CLOCK_RATE) * u128::from(KERNEL_PHASES) / u128::from(CLOCK_RATE);
+        (sample, to_index(phase))
+    }
+
+    /// Starts the output at emulated time `clock`, in T-states, dropping anything pending.
+    pub(super) fn start_at(&mut self, clock: u64) {
+        let (sample, _) = self.position(clock);
+        self.deltas.clear();
+        self.first_sample = sample;
+        self.end_sample = sample;
+    }
+
+    /// Records the mixer output `level` from emulated time `clock` onwards.
+    pub(super) fn set_level(&mut self, clock: u64, level: [f32; 2]) {
+        #[allow(clippy::float_cmp)] // only skips work when nothing changed
+        if level == self.level {
+            return;
+        }
+
+        let (sample, phase) = self.position(clock);
+        let start = to_index(sample - self.first_sample);
+        if self.deltas.len() < start + KERNEL_WIDTH {
+            self.deltas.resize(start + KERNEL_WIDTH, [0.0; 2]);
+        }
+
+        let steps = [level[0] - self.level[0], level[1] - self.level[1]];
+        for (delta, tap) in self.deltas.range_mut(start..).zip(&self.kernels[phase]) {
+            delta[0] += steps[0] * tap;
+            delta[1] += steps[1] * tap;
+        }
+        self.level = level;
+    }
+
+    /// Marks the output complete up to emulated time `clock`, in T-states.
+    pub(super) fn advance(&mut self, clock: u64) {
+        self.end_sample = self.position(clock).0;
+    }
+
+    /// Appends every ready sample to `buffer` as interleaved left and right `f32`s in
+    /// `-1.0..=1.0`.
+    ///
+    /// # Return value
+    /// Number of stereo samples appended.
+    pub fn read_f32(&mut self, buffer: &mut Vec<f32>) -> usize {
+        let count = self.samples_available();
+        buffer.reserve(count * 2);
+        for _ in 0..count {
+            buffer.extend_from_slice(&self.next_sample());
+        }
+        count
+    }
+
+    /// Appends every ready sample to `buffer` as interleaved left and right `i16`s.
+    ///
+    /// # Return value
+    /// Number of stereo samples appended.
+    pub fn read_i16(&mut self, buffer: &mut Vec<i16>) -> usize {
+        let count = self.samples_available();
+        buffer.reserve(count * 2);
+        for _ in 0..count {
+            #[allow(clippy::cast_possible_truncation)]
+            buffer.extend(
+                self.next_sample()
+                    .iter()
+                    .map(|sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16),
+            );
+        }
+        count
+    }
+
+    fn next_sample(&mut self) -> [f32; 2] {
+        let delta = self.deltas.pop_front().unwrap_or([0.0; 2]);
+        self.first_sample += 1;
+
+        let mut sample = [0.0; 2];
+        for side in 0..2 {
+            self.integrator[side] += delta[side];
+            sample[side] = match self.charge {
+                Some(charge) => {
+                    let out = self.integrator[side] - self.capacitor[side];
+                    self.capacitor[side] = self.integrator[side] - out * charge;
+                    out
+                }
+                None => self.integrator[side],
+            };
+        }
+        sample
+    }
+}
+
+/// Blackman-windowed sinc impulse delayed by `phase / KERNEL_PHASES` of a sample, normalised to
+/// sum to 1 so steps keep their exact height.
+fn kernel(phase: u32) -> [f32; KERNEL_WIDTH] {
+    let width = f64::from(u32::try_from(KERNEL_WIDTH).expect("kernel width fits in u32"));
+    let offset = f64::from(phase) / f64::from(KERNEL_PHASES);
+
+    let mut taps = [0.0; KERNEL_WIDTH];
+    for (k, tap) in (0u32..).zip(taps.iter_mut()) {
+        let t = f64::from(k) - offset - width / 2.0;
+        let x = 2.0 * KERNEL_CUTOFF * t;
+        let sinc = match x == 0.0 {
+            true => 1.0,
+            false => (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x),
+        };
+        let u = ((t + width / 2.0) / width).clamp(0.0, 1.0);
+        let window = 0.08f64.mul_add(
+            (4.0 * std::f64::consts::PI * u).cos(),
+            0.5f64.mul_add(-(2.0 * std::f64::consts::PI * u).cos(), 0.42),
+        );
+        *tap = sinc * window;
+    }
+
+    let sum: f64 = taps.iter().sum();
+    #[allow(clippy::cast_possible_truncation)]
+    taps.map(|tap| (tap / sum) as f32)
+}
+
+fn to_index(value: impl TryInto<usize>) -> usize {
+    value.try_into().ok().expect("index fits in usize")
+}
@@ -0,0 +1,271 @@
+//! Pulse channels 1 and 2. Channel 1 also has a frequency sweep unit.
+use super::units::{timer_period, Envelope, LengthCounter};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use std::convert::TryFrom;
+
+/// Waveforms for the four duty cycles of `NRx1`: 12.5%, 25%, 50% and 75%.
+const DUTY_PATTERNS: [u8; 4] = [0b_0000_0001, 0b_1000_0001, 0b_1000_0111, 0b_0111_1110];
+/// Highest 11-bit frequency. A sweep past it disables channel 1.
+const MAX_FREQUENCY: u16 = 0x07FF;
+
+/// Frequency sweep of channel 1, set by `NR10`.
+#[derive(Debug, Copy, Clone)]
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    enabled: bool,
+    shadow: u16,
+    /// Whether a subtraction was calculated since the last trigger. Clearing the negate bit
+    /// afterwards disables the channel.
+    negated: bool,
+}
+
+impl Sweep {
+    const fn new() -> Self {
+        Self {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            enabled: false,
+            shadow: 0,
+            negated: false,
+        }
+    }
+
+    const fn calculate(&mut self) -> u16 {
+        let delta = self.shadow >> self.shift;
+        match self.negate {
+            true => {
+                self.negated = true;
+                self.shadow - delta
+            }
+            false => self.shadow + delta,
+        }
+    }
+}
+
+/// Square wave channel
+#[derive(Debug)]
+pub(super) struct Pulse {
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    /// T-states left until the next duty step
+    timer: u16,
+}
+
+impl Pulse {
+    /// Creates channel 1 if `with_sweep`, channel 2 otherwise.
+    pub(super) const fn new(with_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: match with_sweep {
+                true => Some(Sweep::new()),
+                false => None,
+            },
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+        }
+    }
+
+    pub(super) const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) const fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Writes `NRx0` to `NRx4`, `register` being the offset from `NRx0`.
+    ///
+    /// # Arguments
+    /// * `extra_clock` - Whether the last frame sequencer step clocked length
+    pub(super) fn write(&mut self, register: u16, value: u8, extra_clock: bool) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.period = (value >> 4) & 0b111;
+                    sweep.shift = value & 0b111;
+                    let negate = value & 0b_0000_1000 != 0;
+                    if sweep.negated && sweep.negate && !negate {
+                        self.enabled = false;
+                    }
+                    sweep.negate = negate;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0b_0011_1111);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x0700) | u16::from(value),
+            4 => {
+                self.frequency = (self.frequency & 0x00FF) | (u16::from(value & 0b111) << 8);
+                let trigger = value & 0b_1000_0000 != 0;
+                let enable = value & 0b_0100_0000 != 0;
+                if self.length.write_control(enable, trigger, extra_clock) {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes the length data of `NRx1`, the only write that goes through while powered off.
+    pub(super) const fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0b_0011_1111);
+    }
+
+    const fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow = self.frequency;
+            sweep.timer = timer_period(sweep.period);
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            sweep.negated = false;
+            if sweep.shift != 0 && sweep.calculate() > MAX_FREQUENCY {
+                self.enabled = false;
+            }
+        }
+    }
+
+    const fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    /// T-states until the one in which the channel next steps
+    pub(super) fn until_step(&self) -> u32 {
+        u32::from(self.timer.max(1))
+    }
+
+    /// Advances the channel by `t_states` T-states, fewer than `until_step`.
+    pub(super) fn skip(&mut self, t_states: u32) {
+        self.timer -= u16::try_from(t_states).expect("skipped past a step");
+    }
+
+    /// Advances the channel by one T-state.
+    pub(super) const fn tick(&mut self) {
+        if self.timer <= 1 {
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) const fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) const fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocks the sweep unit on frame sequencer steps 2 and 6.
+    pub(super) const fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.timer = timer_period(sweep.period);
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        let frequency = sweep.calculate();
+        if frequency > MAX_FREQUENCY {
+            self.enabled = false;
+        } else if sweep.shift != 0 {
+            sweep.shadow = frequency;
+            self.frequency = frequency;
+            // the new frequency is checked for overflow again, but not used
+            if sweep.calculate() > MAX_FREQUENCY {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Digital output, `0..=15`
+    pub(super) const fn output(&self) -> u8 {
+        match self.enabled && DUTY_PATTERNS[self.duty as usize] & (0x80 >> self.duty_step) != 0 {
+            true => self.envelope.volume(),
+            false => 0,
+        }
+    }
+
+    /// State after the APU is powered off.
+    pub(super) const fn powered_off(&self) -> Self {
+        Self {
+            length: self.length.powered_off(),
+            ..Self::new(self.sweep.is_some())
+        }
+    }
+
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        if let Some(sweep) = &self.sweep {
+            state.bytes(&[sweep.period, sweep.shift, sweep.timer]);
+            state.bool(sweep.negate);
+            state.bool(sweep.enabled);
+            state.u16(sweep.shadow);
+            state.bool(sweep.negated);
+        }
+        state.u8(self.duty);
+        state.u8(self.duty_step);
+        state.u16(self.frequency);
+        state.u16(self.timer);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.enabled = state.bool()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        if let Some(sweep) = &mut self.sweep {
+            let fields = state.bytes(3)?;
+            *sweep = Sweep {
+                period: fields[0] & 0b_0000_0111,
+                shift: fields[1] & 0b_0000_0111,
+                timer: fields[2],
+                negate: state.bool()?,
+                enabled: state.bool()?,
+                shadow: state.u16()? & MAX_FREQUENCY,
+                negated: state.bool()?,
+            };
+        }
+        self.duty = state.u8()? & 0b11;
+        self.duty_step = state.u8()? & 0b111;
+        self.frequency = state.u16()? & MAX_FREQUENCY;
+        self.timer = state.u16()?;
+        Ok(())
+    }
+}
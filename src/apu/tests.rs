@@ -0,0 +1,574 @@
+use super::{
+    Apu, AudioOutput, Channel, HighPass, VgmLog, WavRecording, CLOCK_RATE, FRAME_SEQUENCER_BIT,
+    NR52_POWER,
+};
+use std::convert::TryFrom;
+
+/// APU powered on, with every channel routed to both sides at full volume
+fn setup() -> Apu {
+    let mut apu = Apu::new();
+    apu.write_register(0xFF26, NR52_POWER);
+    apu.write_register(0xFF24, 0x77);
+    apu.write_register(0xFF25, 0xFF);
+    apu
+}
+
+/// Runs the frame sequencer for `steps` steps by toggling DIV bit 4.
+fn step_frame_sequencer(apu: &mut Apu, steps: u32) {
+    for _ in 0..steps {
+        apu.clock_div(FRAME_SEQUENCER_BIT);
+        apu.clock_div(0);
+    }
+}
+
+/// Channel enable bits of NR52
+fn channels(apu: &Apu) -> u8 {
+    apu.read_register(0xFF26) & 0x0F
+}
+
+#[test]
+fn apu_register_read_masks() {
+    let mut apu = setup();
+    for address in 0xFF10..=0xFF25 {
+        apu.write_register(address, 0);
+    }
+
+    let expected: &[(u16, u8)] = &[
+        (0xFF10, 0x80),
+        (0xFF11, 0x3F),
+        (0xFF13, 0xFF),
+        (0xFF14, 0xBF),
+        (0xFF15, 0xFF),
+        (0xFF1A, 0x7F),
+        (0xFF1C, 0x9F),
+        (0xFF20, 0xFF),
+        (0xFF23, 0xBF),
+        (0xFF24, 0x00),
+        (0xFF26, 0xF0),
+        (0xFF27, 0xFF),
+    ];
+    for (address, value) in expected {
+        assert_eq!(*value, apu.read_register(*address), "0x{address:04X}");
+    }
+}
+
+#[test]
+fn apu_power_off_clears_registers() {
+    let mut apu = setup();
+    apu.write_register(0xFF12, 0xF0);
+    apu.write_register(0xFF14, 0x80);
+    apu.write_register(0xFF30, 0x12);
+    assert_eq!(0xF1, apu.read_register(0xFF26));
+
+    apu.write_register(0xFF26, 0);
+    assert_eq!(0x70, apu.read_register(0xFF26));
+    assert_eq!(0x00, apu.read_register(0xFF12));
+    assert_eq!(0x00, apu.read_register(0xFF24));
+
+    // registers ignore writes while powered off, wave RAM doesn't
+    apu.write_register(0xFF12, 0xF0);
+    assert_eq!(0x00, apu.read_register(0xFF12));
+    assert_eq!(0x12, apu.read_register(0xFF30));
+    apu.write_register(0xFF31, 0x34);
+    assert_eq!(0x34, apu.read_register(0xFF31));
+}
+
+#[test]
+fn apu_length_counter_written_while_off() {
+    let mut apu = Apu::new();
+    // 64 - 62 = 2 length clocks
+    apu.write_register(0xFF11, 62);
+    apu.write_register(0xFF26, NR52_POWER);
+    apu.write_register(0xFF12, 0xF0);
+    apu.write_register(0xFF14, 0xC0);
+    assert_eq!(0b0001, channels(&apu));
+
+    // steps 0 and 2 clock length
+    step_frame_sequencer(&mut apu, 2);
+    assert_eq!(0b0001, channels(&apu));
+    step_frame_sequencer(&mut apu, 1);
+    assert_eq!(0, channels(&apu));
+}
+
+#[test]
+fn apu_length_extra_clock_on_enable() {
+    let mut apu = setup();
+    apu.write_register(0xFF17, 0xF0);
+    apu.write_register(0xFF16, 63);
+    apu.write_register(0xFF19, 0x80);
+    assert_eq!(0b0010, channels(&apu));
+
+    // step 0 just clocked length, so enabling it clocks it once more, expiring the counter
+    step_frame_sequencer(&mut apu, 1);
+    apu.write_register(0xFF19, 0x40);
+    assert_eq!(0, channels(&apu));
+}
+
+#[test]
+fn apu_dac_off_disables_channel() {
+    let mut apu = setup();
+    apu.write_register(0xFF21, 0xF0);
+    apu.write_register(0xFF23, 0x80);
+    assert_eq!(0b1000, channels(&apu));
+
+    apu.write_register(0xFF21, 0x08);
+    assert_eq!(0b1000, channels(&apu));
+    apu.write_register(0xFF21, 0x00);
+    assert_eq!(0, channels(&apu));
+
+    // triggering with the DAC off doesn't enable the channel
+    apu.write_register(0xFF23, 0x80);
+    assert_eq!(0, channels(&apu));
+}
+
+#[test]
+fn apu_pulse_duty_and_envelope() {
+    let mut apu = setup();
+    // 50% duty, volume 15 decreasing every envelope clock, period of 8 T-states
+    apu.write_register(0xFF11, 0x80);
+    apu.write_register(0xFF12, 0xF1);
+    apu.write_register(0xFF13, 0xFE);
+    apu.write_register(0xFF14, 0x87);
+
+    let mut highs = 0;
+    for _ in 0..8 {
+        apu.tick(8);
+        if apu.pulse1.output() == 15 {
+            highs += 1;
+        }
+    }
+    assert_eq!(4, highs);
+
+    step_frame_sequencer(&mut apu, 8);
+    let max = (0..8)
+        .map(|_| {
+            apu.tick(8);
+            apu.pulse1.output()
+        })
+        .max();
+    assert_eq!(Some(14), max);
+}
+
+#[test]
+fn apu_sweep_overflow_disables_channel_1() {
+    let mut apu = setup();
+    // sweep period 1, adding shadow >> 1
+    apu.write_register(0xFF10, 0x11);
+    apu.write_register(0xFF12, 0xF0);
+    apu.write_register(0xFF13, 0x00);
+    apu.write_register(0xFF14, 0x85);
+    assert_eq!(0b0001, channels(&apu));
+
+    // 0x500 + 0x280 overflows on the first sweep clock, at step 2
+    step_frame_sequencer(&mut apu, 2);
+    assert_eq!(0b0001, channels(&apu));
+    step_frame_sequencer(&mut apu, 1);
+    assert_eq!(0, channels(&apu));
+}
+
+#[test]
+fn apu_sweep_steps_until_overflow() {
+    let mut apu = setup();
+    apu.write_register(0xFF10, 0x11);
+    apu.write_register(0xFF12, 0xF0);
+    apu.write_register(0xFF13, 0x00);
+    apu.write_register(0xFF14, 0x81);
+
+    // 0x100 sweeps up to 0x180, 0x240, 0x360, 0x510 and 0x798 on steps 2, 6, 10, 14 and 18,
+    // where the second overflow check against 0x798 + 0x3CC disables the channel
+    step_frame_sequencer(&mut apu, 18);
+    assert_eq!(0b0001, channels(&apu));
+    step_frame_sequencer(&mut apu, 1);
+    assert_eq!(0, channels(&apu));
+}
+
+#[test]
+fn apu_sweep_clocked_before_trigger() {
+    let mut apu = setup();
+    // the sweep timer is still 0 when channel 1 was never triggered
+    step_frame_sequencer(&mut apu, 8);
+    assert_eq!(0, channels(&apu));
+}
+
+#[test]
+fn apu_wave_playback() {
+    let mut apu = setup();
+    for (i, address) in (0u8..).zip(0xFF30..=0xFF3F) {
+        apu.write_register(address, i << 4 | 0x0F);
+    }
+    // 100% volume, period of 2 T-states per sample
+    apu.write_register(0xFF1A, 0x80);
+    apu.write_register(0xFF1C, 0x20);
+    apu.write_register(0xFF1D, 0xFF);
+    apu.write_register(0xFF1E, 0x87);
+    assert_eq!(0b0100, channels(&apu));
+    // wave RAM is locked while the channel plays
+    assert_eq!(0xFF, apu.read_register(0xFF30));
+
+    // the first sample played after a trigger is sample 1
+    apu.tick(2);
+    assert_eq!(0x0F, apu.wave.output());
+    apu.tick(2);
+    assert_eq!(0x01, apu.wave.output());
+
+    // 50% volume shifts samples right by one
+    apu.write_register(0xFF1C, 0x40);
+    assert_eq!(0x00, apu.wave.output());
+    apu.tick(2);
+    assert_eq!(0x07, apu.wave.output());
+}
+
+#[test]
+fn apu_noise_lfsr_periods() {
+    for (nr43, period) in &[(0x00, 0x7FFF), (0x08, 0x7F)] {
+        let mut apu = setup();
+        apu.write_register(0xFF21, 0xF0);
+        apu.write_register(0xFF22, *nr43);
+        apu.write_register(0xFF23, 0x80);
+
+        let mut outputs = Vec::new();
+        for _ in 0..period * 2 {
+            apu.tick(8);
+            outputs.push(apu.noise.output());
+        }
+        assert_eq!(outputs[..*period], outputs[*period..], "NR43 0x{nr43:02X}");
+        assert!(outputs.contains(&0) && outputs.contains(&15));
+    }
+}
+
+#[test]
+fn apu_mixer_panning_and_volume() {
+    let mut apu = setup();
+    apu.write_register(0xFF12, 0xF0);
+    apu.write_register(0xFF13, 0x00);
+    apu.write_register(0xFF14, 0x87);
+    // channel 1 on the left only, left at full volume and right at 1/8
+    apu.write_register(0xFF25, 0x10);
+    apu.write_register(0xFF24, 0x70);
+
+    let mut left_levels = Vec::new();
+    for _ in 0..16 {
+        apu.tick(1024);
+        let [left, right] = apu.output();
+        left_levels.push(left);
+        assert!(right.abs() < f32::EPSILON);
+    }
+    assert!(left_levels.contains(&0.25));
+    assert!(left_levels.contains(&-0.25));
+}
+
+#[test]
+fn apu_mute_and_solo() {
+    let mut apu = setup();
+    for (nrx2, nrx4) in &[(0xFF12, 0xFF14), (0xFF17, 0xFF19)] {
+        apu.write_register(*nrx2, 0xF0);
+        apu.write_register(*nrx4, 0x87);
+    }
+
+    let max_level = |apu: &mut Apu| {
+        let mut max = 0.0f32;
+        for _ in 0..64 {
+            apu.tick(256);
+            max = max.max(apu.output()[0].abs());
+        }
+        max
+    };
+    assert!((max_level(&mut apu) - 0.5).abs() < f32::EPSILON);
+
+    // muted channels are left out of the mix but keep running as far as the game can tell
+    apu.set_muted(Channel::Pulse1, true);
+    assert!(apu.is_muted(Channel::Pulse1));
+    assert!(!apu.is_audible(Channel::Pulse1));
+    assert!((max_level(&mut apu) - 0.25).abs() < f32::EPSILON);
+    assert_eq!(0xF3, apu.read_register(0xFF26));
+    apu.set_muted(Channel::Pulse2, true);
+    assert!(max_level(&mut apu) < f32::EPSILON);
+
+    // a solo silences the other channels, but doesn't unmute
+    apu.set_muted(Channel::Pulse1, false);
+    apu.set_soloed(Channel::Pulse2, true);
+    assert!(apu.is_soloed(Channel::Pulse2));
+    assert!(!apu.is_audible(Channel::Pulse1));
+    assert!(max_level(&mut apu) < f32::EPSILON);
+    apu.set_muted(Channel::Pulse2, false);
+    assert!((max_level(&mut apu) - 0.25).abs() < f32::EPSILON);
+
+    apu.set_soloed(Channel::Pulse2, false);
+    assert!(Channel::ALL.iter().all(|channel| apu.is_audible(*channel)));
+}
+
+#[test]
+fn apu_channel_taps() {
+    let mut apu = setup();
+    apu.write_register(0xFF12, 0xF0);
+    apu.write_register(0xFF14, 0x87);
+    apu.set_channel_tap(
+        Channel::Pulse1,
+        Some(AudioOutput::new(44_100, HighPass::Off)),
+    );
+    apu.set_channel_tap(
+        Channel::Pulse2,
+        Some(AudioOutput::new(44_100, HighPass::Off)),
+    );
+    // taps are taken before the mixer, so muting and panning don't change them
+    apu.set_muted(Channel::Pulse1, true);
+    apu.write_register(0xFF25, 0x00);
+    apu.tick(CLOCK_RATE / 10);
+
+    let mut pulse1 = Vec::new();
+    let count = apu
+        .channel_tap_mut(Channel::Pulse1)
+        .unwrap()
+        .read_f32(&mut pulse1);
+    assert_eq!(4409, count);
+    let settled = &pulse1[200..];
+    assert!(settled.iter().any(|sample| *sample > 0.9));
+    assert!(settled.iter().any(|sample| *sample < -0.9));
+    assert!(pulse1
+        .chunks(2)
+        .all(|sides| (sides[0] - sides[1]).abs() < f32::EPSILON));
+
+    // channel 2's DAC is off
+    let mut pulse2 = Vec::new();
+    apu.channel_tap_mut(Channel::Pulse2)
+        .unwrap()
+        .read_i16(&mut pulse2);
+    assert!(pulse2.iter().all(|sample| *sample == 0));
+    let recording = WavRecording::new(44_100, pulse2);
+    assert_eq!(2 * 4409, recording.samples().len());
+
+    assert!(apu.set_channel_tap(Channel::Pulse1, None).is_some());
+    assert!(apu.channel_tap_mut(Channel::Pulse1).is_none());
+}
+
+/// APU with a 44100 Hz output and channel 3's DAC on but not playing, a constant -0.25 on both
+/// sides
+fn setup_audio(high_pass: HighPass) -> Apu {
+    let mut apu = setup();
+    apu.write_register(0xFF1A, 0x80);
+    apu.set_audio_output(Some(AudioOutput::new(44_100, high_pass)));
+    apu
+}
+
+#[test]
+fn apu_audio_sample_count() {
+    let mut apu = setup_audio(HighPass::Off);
+    let mut samples = Vec::new();
+
+    // a tenth of a second, read in uneven chunks
+    let mut total = 0;
+    for chunk in &[1000, 70_224, 348_206] {
+        apu.tick(*chunk);
+        total += apu.audio_output_mut().unwrap().read_f32(&mut samples);
+    }
+    assert_eq!(4409, total);
+    assert_eq!(total * 2, samples.len());
+
+    // past the kernel's delay the output settles on the DC level
+    assert!(samples[200..].iter().all(|s| (s + 0.25).abs() < 1e-4));
+}
+
+#[test]
+fn apu_audio_high_pass_removes_dc() {
+    let mut apu = setup_audio(HighPass::Dmg);
+    apu.tick(CLOCK_RATE / 10);
+
+    let mut samples = Vec::new();
+    apu.audio_output_mut().unwrap().read_i16(&mut samples);
+    assert!(samples[40..60].iter().all(|s| *s < -7000));
+    assert!(samples[samples.len() - 20..].iter().all(|s| s.abs() < 10));
+}
+
+#[test]
+fn apu_audio_band_limited() {
+    // a pulse at 131072 Hz is way above Nyquist and shouldn't alias into the output
+    let mut apu = setup();
+    apu.write_register(0xFF11, 0x80);
+    apu.write_register(0xFF12, 0xF0);
+    apu.write_register(0xFF13, 0xFF);
+    apu.write_register(0xFF14, 0x87);
+    apu.set_audio_output(Some(AudioOutput::new(44_100, HighPass::Off)));
+    apu.tick(CLOCK_RATE / 20);
+
+    let mut samples = Vec::new();
+    apu.audio_output_mut().unwrap().read_f32(&mut samples);
+    let settled = &samples[200..];
+    let mean = settled.iter().sum::<f32>() / 2000.0;
+    assert!(settled[..2000].iter().all(|s| (s - mean).abs() < 0.01));
+}
+
+/// Records a short tune, starting the recording after `offset` T-states.
+fn record_tune(offset: u32) -> WavRecording {
+    let mut apu = setup();
+    apu.tick(offset);
+    apu.start_recording(22_050, HighPass::Dmg);
+
+    apu.write_register(0xFF12, 0xF3);
+    for (i, frequency) in (0u8..).zip(&[0x06B_u16, 0x0C2, 0x181, 0x1D7]) {
+        let [low, high] = frequency.to_le_bytes();
+        apu.write_register(0xFF13, low);
+        apu.write_register(0xFF14, 0x80 | high);
+        apu.write_register(0xFF11, i << 6);
+        apu.tick(CLOCK_RATE / 64 + u32::from(i) * 7);
+    }
+
+    apu.stop_recording().unwrap()
+}
+
+#[test]
+fn apu_wav_recording_format() {
+    let recording = record_tune(0);
+    let mut bytes = Vec::new();
+    recording.write_to(&mut bytes).unwrap();
+
+    // 4 * 65536 + 42 T-states at 22050 Hz
+    let frames = 1378;
+    let data_size = 1378u32 * 4;
+    assert_eq!(frames * 2, recording.samples().len());
+    assert_eq!(44 + frames * 4, bytes.len());
+    assert_eq!(b"RIFF", &bytes[0..4]);
+    assert_eq!(&(36 + data_size).to_le_bytes(), &bytes[4..8]);
+    assert_eq!(b"WAVEfmt ", &bytes[8..16]);
+    assert_eq!(&[16, 0, 0, 0, 1, 0, 2, 0], &bytes[16..24]);
+    assert_eq!(&22_050u32.to_le_bytes(), &bytes[24..28]);
+    assert_eq!(&(22_050u32 * 4).to_le_bytes(), &bytes[28..32]);
+    assert_eq!(&[4, 0, 16, 0], &bytes[32..36]);
+    assert_eq!(b"data", &bytes[36..40]);
+    assert_eq!(&data_size.to_le_bytes(), &bytes[40..44]);
+    assert!(recording.samples().iter().any(|sample| sample.abs() > 1000));
+}
+
+#[test]
+fn apu_wav_recording_deterministic() {
+    let write = |recording: &WavRecording| {
+        let mut bytes = Vec::new();
+        recording.write_to(&mut bytes).unwrap();
+        bytes
+    };
+
+    assert_eq!(write(&record_tune(0)), write(&record_tune(0)));
+    // the same emulated duration gives the same number of samples wherever it starts
+    assert_eq!(
+        record_tune(0).samples().len(),
+        record_tune(3 * 65_536).samples().len()
+    );
+}
+
+#[test]
+fn apu_wav_recording_independent_of_audio_output() {
+    let mut apu = setup_audio(HighPass::Off);
+    apu.tick(1000);
+    apu.start_recording(44_100, HighPass::Off);
+    apu.tick(CLOCK_RATE / 100);
+
+    let mut samples = Vec::new();
+    apu.audio_output_mut().unwrap().read_i16(&mut samples);
+    let recording = apu.stop_recording().unwrap();
+    assert!(!apu.is_recording());
+
+    // the recording starts 10 samples in, and carries the same signal once both kernels settle
+    assert_eq!(samples.len() - 20, recording.samples().len());
+    assert_eq!(
+        &samples[samples.len() - 200..],
+        &recording.samples()[recording.samples().len() - 200..]
+    );
+}
+
+/// Decodes the commands of a VGM file into `(sample, register, value)` writes and the total
+/// samples waited.
+fn decode_vgm(log: &VgmLog) -> (Vec<(u64, u8, u8)>, u64) {
+    let mut bytes = Vec::new();
+    log.write_to(&mut bytes).unwrap();
+    assert_eq!(b"Vgm ", &bytes[..4]);
+    assert_eq!(
+        &(u32::try_from(bytes.len()).unwrap() - 4).to_le_bytes(),
+        &bytes[0x04..0x08]
+    );
+    assert_eq!(&[0x61, 0x01, 0, 0], &bytes[0x08..0x0C]);
+    assert_eq!(
+        &u32::try_from(log.samples()).unwrap().to_le_bytes(),
+        &bytes[0x18..0x1C]
+    );
+    assert_eq!(&[0xCC, 0, 0, 0], &bytes[0x34..0x38]);
+    assert_eq!(&CLOCK_RATE.to_le_bytes(), &bytes[0x80..0x84]);
+
+    let mut writes = Vec::new();
+    let mut sample = 0;
+    let mut i = 0x100;
+    loop {
+        match bytes[i] {
+            0xB3 => {
+                writes.push((sample, bytes[i + 1], bytes[i + 2]));
+                i += 3;
+            }
+            0x61 => {
+                sample += u64::from(u16::from_le_bytes([bytes[i + 1], bytes[i + 2]]));
+                i += 3;
+            }
+            0x62 => {
+                sample += 735;
+                i += 1;
+            }
+            0x63 => {
+                sample += 882;
+                i += 1;
+            }
+            command @ 0x70..=0x7F => {
+                sample += u64::from(command - 0x6F);
+                i += 1;
+            }
+            0x66 => break,
+            command => panic!("unexpected command 0x{:02X}", command),
+        }
+    }
+    assert_eq!(i + 1, bytes.len());
+    (writes, sample)
+}
+
+#[test]
+fn apu_vgm_log_writes_with_timestamps() {
+    let mut apu = setup();
+    apu.tick(12_345);
+    apu.start_vgm_log();
+    assert!(apu.is_vgm_logging());
+
+    apu.write_register(0xFF12, 0xF0);
+    // 735.99 VGM samples
+    apu.tick(70_000);
+    apu.write_register(0xFF14, 0x87);
+    apu.tick(1000);
+    apu.write_register(0xFF30, 0xAB);
+    apu.tick(CLOCK_RATE);
+    let log = apu.stop_vgm_log().unwrap();
+    assert!(!apu.is_vgm_logging());
+
+    let (writes, samples) = decode_vgm(&log);
+    assert_eq!(44_100 + 746, samples);
+    assert_eq!(samples, log.samples());
+
+    // power on, wave RAM and registers at the start, triggers masked out
+    let (initial, logged) = writes.split_at(1 + 16 + 22);
+    assert_eq!((0, 0x16, 0x80), initial[0]);
+    assert!(initial[1..17]
+        .iter()
+        .all(|(_, register, _)| (0x20..0x30).contains(register)));
+    assert!(initial.contains(&(0, 0x14, 0x77)));
+    assert!(initial.contains(&(0, 0x15, 0xFF)));
+
+    assert_eq!(
+        &[(0, 0x02, 0xF0), (735, 0x04, 0x87), (746, 0x20, 0xAB)],
+        logged
+    );
+}
+
+#[test]
+fn apu_vgm_log_start_masks_triggers() {
+    let mut apu = setup();
+    apu.write_register(0xFF21, 0xF0);
+    apu.write_register(0xFF23, 0xC0);
+    apu.start_vgm_log();
+    let (writes, _) = decode_vgm(&apu.stop_vgm_log().unwrap());
+
+    assert!(writes.contains(&(0, 0x11, 0xF0)));
+    assert!(writes.contains(&(0, 0x13, 0x40)));
+}
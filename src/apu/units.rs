@@ -0,0 +1,180 @@
+//! Length counter and volume envelope shared by the channels.
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+/// Silences its channel once `max` frame sequencer length clocks have passed since the last
+/// load, when enabled by bit 6 of `NRx4`.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    pub(super) const fn new(max: u16) -> Self {
+        Self {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    /// Loads the length data written to `NRx1`, already masked to the width of the field.
+    pub(super) const fn load(&mut self, data: u8) {
+        self.counter = self.max - data as u16;
+    }
+
+    /// Clocks the counter on frame sequencer steps 0, 2, 4 and 6.
+    ///
+    /// # Return value
+    /// Whether the counter just expired, disabling the channel.
+    pub(super) const fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    /// Handles the length enable and trigger bits of a write to `NRx4`.
+    ///
+    /// When the last frame sequencer step clocked length, enabling the counter clocks it once
+    /// more and a trigger reloading an empty counter loads one less than the maximum.
+    ///
+    /// # Arguments
+    /// * `extra_clock` - Whether the last frame sequencer step clocked length
+    ///
+    /// # Return value
+    /// Whether the channel should be disabled.
+    pub(super) const fn write_control(
+        &mut self,
+        enable: bool,
+        trigger: bool,
+        extra_clock: bool,
+    ) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enable;
+
+        let expired = match extra_clock && enable && !was_enabled && self.counter > 0 {
+            true => {
+                self.counter -= 1;
+                self.counter == 0
+            }
+            false => false,
+        };
+
+        if trigger && self.counter == 0 {
+            self.counter = self.max;
+            if enable && extra_clock {
+                self.counter -= 1;
+            }
+        }
+
+        expired && !trigger
+    }
+
+    /// State after the APU is powered off. On DMG the counter itself survives.
+    pub(super) const fn powered_off(self) -> Self {
+        Self {
+            enabled: false,
+            ..self
+        }
+    }
+
+    pub(super) fn save_state(self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        state.u16(self.counter);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.enabled = state.bool()?;
+        self.counter = state.u16()?;
+        match self.counter <= self.max {
+            true => Ok(()),
+            false => Err(state.corrupt()),
+        }
+    }
+}
+
+/// Steps the channel volume up or down every `period` envelope clocks.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub(super) const fn new() -> Self {
+        Self {
+            initial: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    /// Writes `NRx2`. Changes only take effect on the next trigger.
+    pub(super) const fn write(&mut self, value: u8) {
+        self.initial = value >> 4;
+        self.increase = value & 0b_0000_1000 != 0;
+        self.period = value & 0b_0000_0111;
+    }
+
+    /// The DAC is on unless the upper five bits of `NRx2` are all 0.
+    pub(super) const fn dac_enabled(self) -> bool {
+        self.initial != 0 || self.increase
+    }
+
+    pub(super) const fn volume(self) -> u8 {
+        self.volume
+    }
+
+    pub(super) const fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = timer_period(self.period);
+    }
+
+    /// Clocks the envelope on frame sequencer step 7.
+    pub(super) const fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = timer_period(self.period);
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    pub(super) fn save_state(self, state: &mut StateWriter) {
+        state.bytes(&[self.initial, self.period, self.volume, self.timer]);
+        state.bool(self.increase);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        let fields = state.bytes(4)?;
+        self.initial = fields[0] & 0x0F;
+        self.period = fields[1] & 0b_0000_0111;
+        self.volume = fields[2] & 0x0F;
+        self.timer = fields[3];
+        self.increase = state.bool()?;
+        Ok(())
+    }
+}
+
+/// Envelope and sweep timers treat a period of 0 as 8.
+pub(super) const fn timer_period(period: u8) -> u8 {
+    match period {
+        0 => 8,
+        _ => period,
+    }
+}
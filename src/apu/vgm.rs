@@ -0,0 +1,127 @@
+//! Logging of APU register writes to VGM 1.61 files, which standard VGM players can play back
+//! with their own Game Boy DMG core.
+use super::output::CLOCK_RATE;
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// VGM timestamps count samples at this rate
+const VGM_SAMPLE_RATE: u32 = 44_100;
+const VGM_VERSION: u32 = 0x0000_0161;
+/// Commands start right after the header
+const HEADER_SIZE: usize = 0x100;
+
+const COMMAND_GB_WRITE: u8 = 0xB3;
+const COMMAND_WAIT: u8 = 0x61;
+const COMMAND_WAIT_60TH: u8 = 0x62;
+const COMMAND_WAIT_50TH: u8 = 0x63;
+/// `0x70..=0x7F` wait 1 to 16 samples
+const COMMAND_WAIT_SHORT: u8 = 0x70;
+const COMMAND_END: u8 = 0x66;
+
+/// Collects the commands of a VGM log in progress.
+#[derive(Debug)]
+pub(super) struct VgmLogger {
+    /// Emulated time the log started at, in T-states
+    start_clock: u64,
+    /// VGM samples waited so far
+    samples: u64,
+    commands: Vec<u8>,
+}
+
+impl VgmLogger {
+    pub(super) const fn new(start_clock: u64) -> Self {
+        Self {
+            start_clock,
+            samples: 0,
+            commands: Vec::new(),
+        }
+    }
+
+    /// Logs a write of `value` to APU register `address` at emulated time `clock`.
+    pub(super) fn write(&mut self, clock: u64, address: u16, value: u8) {
+        self.wait_until(clock);
+        let register = u8::try_from(address - 0xFF10).expect("APU register address");
+        self.commands
+            .extend_from_slice(&[COMMAND_GB_WRITE, register, value]);
+    }
+
+    /// Emits wait commands up to the VGM sample of emulated time `clock`.
+    fn wait_until(&mut self, clock: u64) {
+        let elapsed = u128::from(clock - self.start_clock);
+        let target = elapsed * u128::from(VGM_SAMPLE_RATE) / u128::from(CLOCK_RATE);
+        let target = u64::try_from(target).expect("log shorter than 2^64 samples");
+
+        while self.samples < target {
+            let wait = u16::try_from(target - self.samples).unwrap_or(u16::MAX);
+            match wait {
+                735 => self.commands.push(COMMAND_WAIT_60TH),
+                882 => self.commands.push(COMMAND_WAIT_50TH),
+                1..=16 => self
+                    .commands
+                    .push(COMMAND_WAIT_SHORT + (wait - 1).to_le_bytes()[0]),
+                _ => {
+                    self.commands.push(COMMAND_WAIT);
+                    self.commands.extend_from_slice(&wait.to_le_bytes());
+                }
+            }
+            self.samples += u64::from(wait);
+        }
+    }
+
+    /// Ends the log at emulated time `clock`.
+    pub(super) fn finish(mut self, clock: u64) -> VgmLog {
+        self.wait_until(clock);
+        self.commands.push(COMMAND_END);
+        VgmLog {
+            samples: self.samples,
+            commands: self.commands,
+        }
+    }
+}
+
+/// A finished log of APU register writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VgmLog {
+    /// Length of the log in 44100 Hz samples
+    samples: u64,
+    commands: Vec<u8>,
+}
+
+impl VgmLog {
+    /// Length of the log in 44100 Hz samples
+    #[must_use]
+    pub const fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Writes the log as a VGM file, header included.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let too_long = |_| io::Error::new(io::ErrorKind::InvalidData, "log too long");
+        let file_size = u32::try_from(HEADER_SIZE + self.commands.len()).map_err(too_long)?;
+        let samples = u32::try_from(self.samples).map_err(too_long)?;
+
+        let mut header = [0u8; HEADER_SIZE];
+        let mut put = |offset: usize, value: u32| {
+            header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        put(0x04, file_size - 0x04); // end of file, relative
+        put(0x08, VGM_VERSION);
+        put(0x18, samples);
+        put(0x34, 0x100 - 0x34); // start of the commands, relative
+        put(0x80, CLOCK_RATE); // Game Boy DMG clock
+        header[0x00..0x04].copy_from_slice(b"Vgm ");
+
+        writer.write_all(&header)?;
+        writer.write_all(&self.commands)?;
+        writer.flush()
+    }
+
+    /// Saves the log to a VGM file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+}
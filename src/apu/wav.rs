@@ -0,0 +1,106 @@
+//! Recording of the audio output to 16-bit PCM WAV files.
+use super::output::AudioOutput;
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BYTES_PER_FRAME: u32 = 4;
+
+/// Collects the samples of a recording in progress. It resamples the mixer output on its own,
+/// so reading the APU's audio output doesn't take samples away from it.
+#[derive(Debug)]
+pub(super) struct Recorder {
+    pub(super) output: AudioOutput,
+    samples: Vec<i16>,
+}
+
+impl Recorder {
+    pub(super) const fn new(output: AudioOutput) -> Self {
+        Self {
+            output,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Moves the samples ready in the resampler into the recording.
+    pub(super) fn collect(&mut self) {
+        self.output.read_i16(&mut self.samples);
+    }
+
+    pub(super) fn finish(mut self) -> WavRecording {
+        self.collect();
+        WavRecording {
+            sample_rate: self.output.sample_rate(),
+            samples: self.samples,
+        }
+    }
+}
+
+/// A finished recording of interleaved stereo 16-bit samples.
+///
+/// Its length only depends on the emulated time between starting and stopping it, and its
+/// contents on what the game played, so the same input replay gives byte-identical files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavRecording {
+    sample_rate: u32,
+    samples: Vec<i16>,
+}
+
+impl WavRecording {
+    /// Wraps interleaved left and right `samples` read elsewhere, for example from a channel
+    /// tap, to save them as a WAV file.
+    #[must_use]
+    pub const fn new(sample_rate: u32, samples: Vec<i16>) -> Self {
+        Self {
+            sample_rate,
+            samples,
+        }
+    }
+
+    #[must_use]
+    pub const fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Interleaved left and right samples
+    #[must_use]
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Writes the recording as a WAV file, header included.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let data_size = u32::try_from(self.samples.len() * 2)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "recording too long"))?;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        writer.write_all(&(self.sample_rate * BYTES_PER_FRAME).to_le_bytes())?;
+        writer.write_all(&(CHANNELS * BITS_PER_SAMPLE / 8).to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Saves the recording to a WAV file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+}
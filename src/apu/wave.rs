@@ -0,0 +1,181 @@
+//! Wave channel 3, playing back the 32 4-bit samples of wave RAM at `0xFF30..=0xFF3F`.
+use super::units::LengthCounter;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use std::convert::TryFrom;
+
+const WAVE_RAM_SIZE: usize = 0x10;
+
+/// Wave channel
+#[derive(Debug)]
+pub(super) struct Wave {
+    enabled: bool,
+    dac_enabled: bool,
+    length: LengthCounter,
+    /// `NR32` output level: mute, 100%, 50% or 25%
+    volume_code: u8,
+    frequency: u16,
+    /// T-states left until the next sample
+    timer: u16,
+    /// Index of the sample being played, `0..32`
+    position: u8,
+    /// The last sample read from wave RAM
+    sample: u8,
+    ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl Wave {
+    pub(super) const fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+
+    pub(super) const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) const fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    /// Writes `NR30` to `NR34`, `register` being the offset from `NR30`.
+    ///
+    /// # Arguments
+    /// * `extra_clock` - Whether the last frame sequencer step clocked length
+    pub(super) fn write(&mut self, register: u16, value: u8, extra_clock: bool) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0b_1000_0000 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value),
+            2 => self.volume_code = (value >> 5) & 0b11,
+            3 => self.frequency = (self.frequency & 0x0700) | u16::from(value),
+            4 => {
+                self.frequency = (self.frequency & 0x00FF) | (u16::from(value & 0b111) << 8);
+                let trigger = value & 0b_1000_0000 != 0;
+                let enable = value & 0b_0100_0000 != 0;
+                if self.length.write_control(enable, trigger, extra_clock) {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.enabled = self.dac_enabled;
+                    self.timer = self.period();
+                    self.position = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes the length data of `NR31`, the only write that goes through while powered off.
+    pub(super) const fn write_length(&mut self, value: u8) {
+        self.length.load(value);
+    }
+
+    /// Wave RAM, regardless of whether the CPU can reach it
+    pub(super) const fn ram(&self) -> [u8; WAVE_RAM_SIZE] {
+        self.ram
+    }
+
+    /// Reads wave RAM. While the channel plays the CPU can't reach it on DMG, and reads `0xFF`.
+    pub(super) const fn read_ram(&self, address: u16) -> u8 {
+        match self.enabled {
+            true => 0xFF,
+            false => self.ram[(address & 0x0F) as usize],
+        }
+    }
+
+    /// Writes wave RAM, dropped while the channel plays.
+    pub(super) const fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.enabled {
+            self.ram[(address & 0x0F) as usize] = value;
+        }
+    }
+
+    const fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    /// T-states until the one in which the channel next steps
+    pub(super) fn until_step(&self) -> u32 {
+        u32::from(self.timer.max(1))
+    }
+
+    /// Advances the channel by `t_states` T-states, fewer than `until_step`.
+    pub(super) fn skip(&mut self, t_states: u32) {
+        self.timer -= u16::try_from(t_states).expect("skipped past a step");
+    }
+
+    /// Advances the channel by one T-state.
+    pub(super) const fn tick(&mut self) {
+        if self.timer <= 1 {
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[(self.position / 2) as usize];
+            self.sample = match self.position % 2 {
+                0 => byte >> 4,
+                _ => byte & 0x0F,
+            };
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) const fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Digital output, `0..=15`
+    pub(super) const fn output(&self) -> u8 {
+        match (self.enabled, self.volume_code) {
+            (false, _) | (true, 0) => 0,
+            (true, code) => self.sample >> (code - 1),
+        }
+    }
+
+    /// State after the APU is powered off. Wave RAM is left untouched.
+    pub(super) const fn powered_off(&self) -> Self {
+        Self {
+            length: self.length.powered_off(),
+            ram: self.ram,
+            ..Self::new()
+        }
+    }
+
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        state.bool(self.dac_enabled);
+        self.length.save_state(state);
+        state.u8(self.volume_code);
+        state.u16(self.frequency);
+        state.u16(self.timer);
+        state.u8(self.position);
+        state.u8(self.sample);
+        state.bytes(&self.ram);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.enabled = state.bool()?;
+        self.dac_enabled = state.bool()?;
+        self.length.load_state(state)?;
+        self.volume_code = state.u8()? & 0b11;
+        self.frequency = state.u16()? & 0x07FF;
+        self.timer = state.u16()?;
+        self.position = state.u8()? % 32;
+        self.sample = state.u8()? & 0x0F;
+        state.read_into(&mut self.ram)
+    }
+}
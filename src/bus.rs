@@ -0,0 +1,118 @@
+//! The interface between the CPU and the memory and peripherals it's wired to.
+//!
+//! `Cpu` runs against any `Bus`. The console's is `memory::Mmu`, while `FlatMemory` is a plain
+//! 64 KiB array with nothing behind it, for running the SM83 core on its own.
+use crate::interrupts::Interrupt;
+
+/// Address space seen by the CPU, along with whatever runs alongside it.
+pub trait Bus {
+    /// Reads the byte at `address`.
+    fn read(&mut self, address: u16) -> u8;
+
+    /// Writes `value` to `address`.
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Advances whatever is on the bus by `t_states` clock ticks.
+    fn tick(&mut self, t_states: u32);
+
+    /// Bit mask of the interrupts both requested in IF and enabled in IE. The CPU checks them
+    /// outside of its bus cycles, so a bus logging accesses may want to leave this out.
+    fn pending_interrupts(&mut self) -> u8 {
+        self.read(0xFFFF) & self.read(0xFF0F) & 0b_0001_1111
+    }
+
+    /// Clears `interrupt` in IF as the CPU starts servicing it.
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        let flags = self.read(0xFF0F);
+        self.write(0xFF0F, flags & !interrupt.bit());
+    }
+
+    /// Clock ticks until the next one in which something on the bus may request an interrupt,
+    /// if known. A halted CPU sleeps until then instead of checking every M-cycle.
+    fn until_next_event(&mut self) -> Option<u64> {
+        None
+    }
+
+    /// Whether a button holds a selected line of P1 low, which ends STOP. A bus without a
+    /// joypad never keeps the CPU stopped.
+    fn wakes_from_stop(&self) -> bool {
+        true
+    }
+}
+
+impl<B: Bus + ?Sized> Bus for &mut B {
+    fn read(&mut self, address: u16) -> u8 {
+        (**self).read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        (**self).write(address, value);
+    }
+
+    fn tick(&mut self, t_states: u32) {
+        (**self).tick(t_states);
+    }
+
+    fn pending_interrupts(&mut self) -> u8 {
+        (**self).pending_interrupts()
+    }
+
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        (**self).acknowledge_interrupt(interrupt);
+    }
+
+    fn until_next_event(&mut self) -> Option<u64> {
+        (**self).until_next_event()
+    }
+
+    fn wakes_from_stop(&self) -> bool {
+        (**self).wakes_from_stop()
+    }
+}
+
+/// 64 KiB of RAM filling the whole address space, with no peripherals and no timing.
+pub struct FlatMemory {
+    bytes: Vec<u8>,
+}
+
+impl FlatMemory {
+    /// Memory filled with zeroes
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            bytes: vec![0; 0x10000],
+        }
+    }
+
+    /// The whole address space
+    #[must_use]
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The whole address space, to load a program or set up a test
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for FlatMemory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.bytes[usize::from(address)]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.bytes[usize::from(address)] = value;
+    }
+
+    fn tick(&mut self, _t_states: u32) {}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,62 @@
+use super::{Bus, FlatMemory};
+use crate::cpu::Cpu;
+
+/// Passes accesses on, keeping a log of the writes
+struct WriteLog<B> {
+    inner: B,
+    writes: Vec<(u16, u8)>,
+}
+
+impl<B: Bus> Bus for WriteLog<B> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.inner.read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.writes.push((address, value));
+        self.inner.write(address, value);
+    }
+
+    fn tick(&mut self, t_states: u32) {
+        self.inner.tick(t_states);
+    }
+}
+
+#[test]
+fn bus_flat_memory() {
+    let mut memory = FlatMemory::new();
+    #[rustfmt::skip]
+    memory.bytes_mut()[..6].copy_from_slice(&[
+        0x21, 0x00, 0xC0, // LD HL, 0xC000
+        0x36, 0x42,       // LD (HL), 0x42
+        0x34,             // INC (HL)
+    ]);
+
+    let mut cpu = Cpu::with_bus(memory);
+    for _ in 0..3 {
+        cpu.step();
+    }
+    assert_eq!(0x43, cpu.bus().bytes()[0xC000]);
+}
+
+#[test]
+fn bus_dyn_wrapper() {
+    let mut log = WriteLog {
+        inner: FlatMemory::new(),
+        writes: Vec::new(),
+    };
+    #[rustfmt::skip]
+    log.inner.bytes_mut()[..6].copy_from_slice(&[
+        0x31, 0x00, 0xD0, // LD SP, 0xD000
+        0xCD, 0x34, 0x12, // CALL 0x1234
+    ]);
+
+    let bus: &mut dyn Bus = &mut log;
+    let mut cpu = Cpu::with_bus(bus);
+    cpu.step();
+    cpu.step();
+    assert_eq!(0x1234, cpu.registers().pc);
+
+    // the return address is pushed high byte first
+    assert_eq!(vec![(0xCFFF, 0x00), (0xCFFE, 0x06)], log.writes);
+}
@@ -2,9 +2,18 @@ use registers::{Flag, Register16b, Register8b, Registers};
 
 // modules
 use crate::{
+    bus::Bus,
+    interrupts::Interrupt,
+    joypad::{Button, JoypadState},
     memory,
+    ppu::RendererKind,
+    savestate::{SaveStateError, StateReader, StateWriter},
     utils::{bytes_to_word, word_to_bytes},
 };
+use std::convert::TryFrom;
+
+/// Longest a halted CPU sleeps in one step, the most whole M-cycles a step can take
+const MAX_HALTED_T_STATES: u8 = 252;
 
 /// Implements arithmetic logic for `Cpu` functions.
 mod alu;
@@ -12,44 +21,359 @@ mod alu;
 /// Implements CPU instructions
 mod ops;
 
-/// CPU
-pub struct Cpu {
+/// Selects when the CPU's bus accesses happen relative to the peripherals.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Timing {
+    /// Every access of an instruction happens at its start, and the peripherals catch up once
+    /// it's done. Fast.
+    Instruction,
+    /// Every access happens in its own M-cycle, the peripherals being advanced in between, as
+    /// timing test ROMs expect.
+    MCycle,
+}
+
+/// CPU, wired to the console's bus unless given another
+#[allow(clippy::struct_excessive_bools)] // flags the instructions set and clear independently
+pub struct Cpu<B = memory::Mmu> {
     /// Registers
     registers: Registers,
-    /// TODO: Halt execution flag
-    #[allow(unused)]
+    /// Set by HALT, cleared when an enabled interrupt is requested
     halted: bool,
+    /// Set by STOP, cleared when a button press pulls a line of P1 low
+    stopped: bool,
     /// Interrupt enable flag
     interrupt_master_enable: bool, // IME
-    /// Memory controller
-    mmu: memory::Mmu,
+    /// Set by EI, which only sets IME once the instruction after it is done
+    enable_interrupts_pending: bool,
+    /// Memory and peripherals
+    bus: B,
+    timing: Timing,
+    /// T-states the peripherals were advanced by during the current instruction
+    ticked: u8,
 }
 
 impl Cpu {
     pub fn new() -> Cpu {
-        Cpu {
+        Cpu::with_renderer(RendererKind::Scanline)
+    }
+
+    /// Creates a CPU whose PPU draws lines with the given renderer.
+    #[must_use]
+    pub fn with_renderer(renderer: RendererKind) -> Self {
+        Self::with_bus(memory::Mmu::with_renderer(renderer))
+    }
+
+    /// Replaces the held buttons with `state`. Meant to be called once per frame by replays and
+    /// bots driving input deterministically.
+    pub fn set_joypad(&mut self, state: JoypadState) {
+        self.bus.set_joypad(state);
+    }
+
+    /// Presses `button`, which also wakes the CPU from STOP if its group is selected in P1.
+    pub fn press_button(&mut self, button: Button) {
+        self.bus.press(button);
+    }
+
+    /// Releases `button`.
+    pub fn release_button(&mut self, button: Button) {
+        self.bus.release(button);
+    }
+
+    /// The memory controller and the peripherals on its bus
+    #[must_use]
+    pub const fn mmu(&self) -> &memory::Mmu {
+        &self.bus
+    }
+
+    /// The memory controller and the peripherals on its bus
+    pub const fn mmu_mut(&mut self) -> &mut memory::Mmu {
+        &mut self.bus
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Creates a CPU wired to `bus`.
+    pub fn with_bus(bus: B) -> Self {
+        Self {
             // TODO: what are the initilization values here?
             registers: Registers::new(),
             halted: false,
+            stopped: false,
             interrupt_master_enable: false,
-            mmu: memory::Mmu::new(),
+            enable_interrupts_pending: false,
+            bus,
+            timing: Timing::Instruction,
+            ticked: 0,
         }
     }
 
+    /// What the CPU reads and writes
+    #[must_use]
+    pub const fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub const fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// When bus accesses happen within an instruction, `Timing::Instruction` by default
+    #[must_use]
+    pub const fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub const fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    /// Whether the CPU is stopped by STOP, waiting for a button press
+    #[must_use]
+    pub const fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Whether the CPU is halted by HALT, waiting for an interrupt
+    #[must_use]
+    pub const fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Whether interrupts are enabled (IME), so the next pending one gets serviced
+    #[must_use]
+    pub const fn interrupts_enabled(&self) -> bool {
+        self.interrupt_master_enable
+    }
+
+    /// The CPU registers
+    #[must_use]
+    pub const fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// The CPU registers, to set up a test or poke at from a debugger
+    pub const fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    /// IME, whether requested interrupts are serviced
+    #[must_use]
+    pub const fn interrupt_master_enable(&self) -> bool {
+        self.interrupt_master_enable
+    }
+
+    pub const fn set_interrupt_master_enable(&mut self, enabled: bool) {
+        self.interrupt_master_enable = enabled;
+    }
+
+    /// Moves the program counter to `address`, as a jump would.
+    pub(crate) const fn set_pc(&mut self, address: u16) {
+        self.registers.pc = address;
+    }
+
+    /// Puts the registers and I/O registers in the state the DMG boot ROM leaves them in, with
+    /// the program counter at the cartridge entry point `0x0100`.
+    pub(crate) fn skip_boot_rom(&mut self) {
+        self.registers.set_r16(Register16b::AF, 0x01B0);
+        self.registers.set_r16(Register16b::BC, 0x0013);
+        self.registers.set_r16(Register16b::DE, 0x00D8);
+        self.registers.set_r16(Register16b::HL, 0x014D);
+        self.registers.sp = 0xFFFE;
+        self.registers.pc = 0x0100;
+
+        for &(address, value) in &[
+            (0xFF26, 0xF1), // NR52, APU on
+            (0xFF24, 0x77), // NR50
+            (0xFF25, 0xF3), // NR51
+            (0xFF11, 0x80), // NR11
+            (0xFF12, 0xF3), // NR12
+            (0xFF47, 0xFC), // BGP
+            (0xFF48, 0xFF), // OBP0
+            (0xFF49, 0xFF), // OBP1
+            (0xFF40, 0x91), // LCDC, LCD and background on
+        ] {
+            self.bus.write(address, value);
+        }
+    }
+
+    /// Saves the registers and the IME, pending EI, HALT and STOP state. The timing mode is a setting of
+    /// the host and isn't saved.
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        for &register in &[
+            Register16b::AF,
+            Register16b::BC,
+            Register16b::DE,
+            Register16b::HL,
+            Register16b::SP,
+        ] {
+            state.u16(self.registers.get_r16(register));
+        }
+        state.u16(self.registers.pc);
+        state.bool(self.interrupt_master_enable);
+        state.bool(self.enable_interrupts_pending);
+        state.bool(self.halted);
+        state.bool(self.stopped);
+    }
+
+    /// Restores what `Cpu::save_state` saved.
+    pub(crate) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        for &register in &[
+            Register16b::AF,
+            Register16b::BC,
+            Register16b::DE,
+            Register16b::HL,
+            Register16b::SP,
+        ] {
+            self.registers.set_r16(register, state.u16()?);
+        }
+        self.registers.pc = state.u16()?;
+        self.interrupt_master_enable = state.bool()?;
+        self.enable_interrupts_pending = state.bool()?;
+        self.halted = state.bool()?;
+        self.stopped = state.bool()?;
+        Ok(())
+    }
+
     /// Read a byte pointed to by SP and increment the program counter by 1
     pub(in crate::cpu) fn fetch_byte(&mut self) -> u8 {
-        let value: u8 = self.mmu.read_byte(self.registers.pc);
+        let value: u8 = self.read_byte(self.registers.pc);
         self.registers.pc = self.registers.pc.wrapping_add(1);
         value
     }
 
     /// Read a word pointed to by SP and increment the program counter by 2
     pub(in crate::cpu) fn fetch_word(&mut self) -> u16 {
-        let value: u16 = self.mmu.read_word(self.registers.pc);
-        self.registers.pc = self.registers.pc.wrapping_add(2);
+        let low = self.fetch_byte();
+        let high = self.fetch_byte();
+        bytes_to_word(high, low)
+    }
+
+    /// Reads a byte from the bus, taking an M-cycle.
+    pub(in crate::cpu) fn read_byte(&mut self, address: u16) -> u8 {
+        let value = self.bus.read(address);
+        self.cycle();
         value
     }
 
+    /// Writes a byte to the bus, taking an M-cycle.
+    pub(in crate::cpu) fn write_byte(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+        self.cycle();
+    }
+
+    /// Writes a word to the bus, low byte first, taking two M-cycles.
+    pub(in crate::cpu) fn write_word(&mut self, address: u16, value: u16) {
+        let (high, low) = word_to_bytes(value);
+        self.write_byte(address, low);
+        self.write_byte(address.wrapping_add(1), high);
+    }
+
+    /// An M-cycle spent on internal work, without touching the bus.
+    pub(in crate::cpu) fn idle(&mut self) {
+        self.cycle();
+    }
+
+    /// Ends the M-cycle of a bus access or internal operation. With `Timing::MCycle` the
+    /// peripherals are advanced by it right away, otherwise they catch up in `Cpu::step`.
+    fn cycle(&mut self) {
+        if self.timing == Timing::MCycle {
+            self.bus.tick(4);
+            self.ticked += 4;
+        }
+    }
+
+    /// Pushes `value` onto the stack, high byte first
+    pub(in crate::cpu) fn push_word(&mut self, value: u16) {
+        let (high, low) = word_to_bytes(value);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write_byte(self.registers.sp, high);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write_byte(self.registers.sp, low);
+    }
+
+    /// Pops a word off the stack
+    pub(in crate::cpu) fn pop_word(&mut self) -> u16 {
+        let low = self.read_byte(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let high = self.read_byte(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        bytes_to_word(high, low)
+    }
+
+    /// JR cc, i8. Reads the offset and adds it to PC if `condition` holds.
+    ///
+    /// # Return value
+    /// T-states taken, 12 if the jump is taken and 8 otherwise.
+    pub(in crate::cpu) fn jump_relative(&mut self, condition: bool) -> u8 {
+        let offset = i8::from_le_bytes([self.fetch_byte()]);
+        match condition {
+            true => {
+                self.registers.pc = self.registers.pc.wrapping_add_signed(i16::from(offset));
+                self.idle();
+                12
+            }
+            false => 8,
+        }
+    }
+
+    /// JP cc, u16. Reads the address and jumps to it if `condition` holds.
+    ///
+    /// # Return value
+    /// T-states taken, 16 if the jump is taken and 12 otherwise.
+    pub(in crate::cpu) fn jump(&mut self, condition: bool) -> u8 {
+        let address = self.fetch_word();
+        match condition {
+            true => {
+                self.registers.pc = address;
+                self.idle();
+                16
+            }
+            false => 12,
+        }
+    }
+
+    /// CALL cc, u16. Reads the address and calls it if `condition` holds.
+    ///
+    /// # Return value
+    /// T-states taken, 24 if the call is made and 12 otherwise.
+    pub(in crate::cpu) fn call(&mut self, condition: bool) -> u8 {
+        let address = self.fetch_word();
+        match condition {
+            true => {
+                self.idle();
+                self.push_word(self.registers.pc);
+                self.registers.pc = address;
+                24
+            }
+            false => 12,
+        }
+    }
+
+    /// RET cc. Returns if `condition` holds.
+    ///
+    /// # Return value
+    /// T-states taken, 20 if returning and 8 otherwise.
+    pub(in crate::cpu) fn return_if(&mut self, condition: bool) -> u8 {
+        // the condition is checked in an M-cycle of its own
+        self.idle();
+        match condition {
+            true => {
+                self.registers.pc = self.pop_word();
+                self.idle();
+                20
+            }
+            false => 8,
+        }
+    }
+
+    /// RST. Calls the fixed address `vector`.
+    pub(in crate::cpu) fn restart(&mut self, vector: u16) -> u8 {
+        self.idle();
+        self.push_word(self.registers.pc);
+        self.registers.pc = vector;
+        16
+    }
+
     /// Loads the value from one 8-bit register to another. Used for LD R8, R8 instructions.
     pub(in crate::cpu) fn ld_regs_8b(&mut self, reg_to: Register8b, reg_from: Register8b) {
         /* if reg_from == reg_to { // ignore for now, assuming is not called for same reg instructions
@@ -67,25 +391,79 @@ impl Cpu {
     ///
     /// **NOTE:** one CPU cycle/"M-cycle" == four clock ticks/"T-states"
     pub fn fetch_and_execute(&mut self) -> u8 {
-        // let instruction: u8 = 0x00;
+        self.ticked = 0;
+        if self.stopped {
+            match self.bus.wakes_from_stop() {
+                true => self.stopped = false,
+                false => return 4,
+            }
+        }
+
+        let pending = self.bus.pending_interrupts();
+        if pending != 0 {
+            self.halted = false;
+            if self.interrupt_master_enable {
+                return self.service_interrupt(pending);
+            }
+        }
+        if self.halted {
+            // only an event can request an interrupt, so sleep until the M-cycle it's due in
+            return match self.bus.until_next_event() {
+                Some(until) => {
+                    let until = until.min(u64::from(MAX_HALTED_T_STATES));
+                    u8::try_from(until.div_ceil(4) * 4).unwrap_or(MAX_HALTED_T_STATES)
+                }
+                None => 4,
+            };
+        }
+
+        // an EI before this instruction takes effect after it, unless it's a DI
+        let enable_interrupts = self.enable_interrupts_pending;
         let instruction: u8 = self.fetch_byte();
+        let t_states = self.execute_instr(instruction);
+        if enable_interrupts && self.enable_interrupts_pending {
+            self.enable_interrupts_pending = false;
+            self.interrupt_master_enable = true;
+        }
+        t_states
+    }
+
+    /// Executes the next instruction, or services an interrupt, then advances the peripherals
+    /// on the bus by the time it took.
+    ///
+    /// # Return value
+    /// `t_states: u8` - Number of clock ticks taken.
+    pub fn step(&mut self) -> u8 {
+        let t_states = self.fetch_and_execute();
+        self.bus.tick(u32::from(t_states - self.ticked));
+        t_states
+    }
 
-        println!(
-            "CPU executing 0x{:X} at PC: {}",
-            instruction,
-            self.registers.pc - 1
-        );
+    /// Calls the handler of the highest priority interrupt in `pending`, acknowledging it in
+    /// IF and disabling further interrupts until RETI or EI.
+    fn service_interrupt(&mut self, pending: u8) -> u8 {
+        let interrupt = *Interrupt::ALL
+            .iter()
+            .find(|interrupt| pending & interrupt.bit() != 0)
+            .expect("an interrupt is pending");
 
-        self.execute_instr(instruction)
+        self.interrupt_master_enable = false;
+        self.bus.acknowledge_interrupt(interrupt);
+        self.idle();
+        self.idle();
+        self.push_word(self.registers.pc);
+        self.registers.pc = interrupt.vector();
+        20
     }
 
     /// _DEBUG FUNCTION_. Placeholder for instructions not yet implemented
     ///
     /// TODO: dump state?
     pub(in crate::cpu) fn unimpl_instr(&self) -> ! {
-        dbg!("{}", &self.registers);
-
-        unimplemented!("Unimplemented or invalid instruction!")
+        unimplemented!(
+            "Unimplemented or invalid instruction! Registers: {:?}",
+            self.registers
+        )
     }
 }
 
@@ -1,6 +1,6 @@
 use super::*;
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     /// Adds two byte length values and sets the appropriate flags in the F register for CPU instructions.
     ///
     /// Applicable for instructions 0x80..0x8F
@@ -19,9 +19,9 @@ impl Cpu {
             false => 0,
         };
         let x = x as u16;
-        let y = (y as u16).wrapping_add(c);
+        let y = y as u16;
 
-        let result = x.wrapping_add(y);
+        let result = x.wrapping_add(y).wrapping_add(c);
 
         self.registers.set_flag(Flag::Z, result & 0x00FF == 0);
         self.registers.set_flag(Flag::N, false);
@@ -49,13 +49,14 @@ impl Cpu {
     pub(in crate::cpu) fn alu_sub_bytes(&mut self, x: u8, y: u8, use_carry: bool) -> u8 {
         let c: u16 = (use_carry & self.registers.flag_value(Flag::C)) as u16;
         let x = x as u16;
-        let y = (y as u16).wrapping_add(c);
+        let y = y as u16;
 
-        let result = x.wrapping_sub(y);
+        let result = x.wrapping_sub(y).wrapping_sub(c);
 
         self.registers.set_flag(Flag::Z, result & 0x00FF == 0);
         self.registers.set_flag(Flag::N, true);
-        self.registers.set_flag(Flag::H, (y & 0x0F) > (x & 0x0F)); // x ^ (!y) ^ result TODO: check potential off-by-one?
+        self.registers
+            .set_flag(Flag::H, (y & 0x0F) + c > (x & 0x0F));
         self.registers.set_flag(Flag::C, (result & 0x100) != 0); // should work due to two's complement subtraction? TODO: verify
 
         result as u8
@@ -217,30 +218,87 @@ impl Cpu {
         let _ = self.alu_sub_bytes(a, y, false);
     }
 
-    /// "Rotates" or shifts the value of `x` to the left by 1 bit. The flag `C` is "rotated in".
+    /// Adds the signed operand of ADD SP, r8 and LD HL, SP + r8 to SP.
     ///
     /// # Argument
-    /// * `x` - `u8` operand
+    /// * `offset` - Two's complement `i8` operand
     ///
     /// # Flags
-    /// * `Z` if result == 0
+    /// * `Z = 0`
     /// * `N = 0`
+    /// * `H` if bit 3 overflows, adding `offset` unsigned to the low byte of SP
+    /// * `C` if bit 7 overflows, adding `offset` unsigned to the low byte of SP
+    pub(in crate::cpu) fn alu_add_sp_offset(&mut self, offset: u8) -> u16 {
+        let sp = self.registers.sp;
+        let (_, sp_low) = word_to_bytes(sp);
+        let _ = self.alu_add_bytes(sp_low, offset, false);
+        self.registers.set_flag(Flag::Z, false);
+
+        sp.wrapping_add_signed(i16::from(i8::from_le_bytes([offset])))
+    }
+
+    /// Adjusts `A` to binary-coded decimal after an addition or subtraction of two BCD values.
+    ///
+    /// # Flags
+    /// * `Z` if result == 0
     /// * `H = 0`
-    /// * `C` the value of the most significant bit in `x`, which is shifted out.
-    pub(in crate::cpu) fn bit_op_rlc(&mut self, x: u8) -> u8 {
-        let carry_out = x >> 7; // fills with 0
-        let carry_in = self.registers.flag_value(Flag::C) as u8;
-        let value = (x << 1) | carry_in;
+    /// * `C` if the adjusted addition overflowed past 99, kept set after a subtraction
+    pub(in crate::cpu) fn alu_daa(&mut self) {
+        let mut a = self.registers.get_r8(Register8b::A);
+        let mut carry = self.registers.flag_value(Flag::C);
+        let half_carry = self.registers.flag_value(Flag::H);
+
+        match self.registers.flag_value(Flag::N) {
+            false => {
+                if carry || a > 0x99 {
+                    a = a.wrapping_add(0x60);
+                    carry = true;
+                }
+                if half_carry || a & 0x0F > 0x09 {
+                    a = a.wrapping_add(0x06);
+                }
+            }
+            true => {
+                if carry {
+                    a = a.wrapping_sub(0x60);
+                }
+                if half_carry {
+                    a = a.wrapping_sub(0x06);
+                }
+            }
+        }
+
+        self.registers.set_flag(Flag::Z, a == 0);
+        self.registers.set_flag(Flag::H, false);
+        self.registers.set_flag(Flag::C, carry);
+        self.registers.set_r8(Register8b::A, a);
+    }
 
-        self.registers.set_flag(Flag::Z, value == 0);
+    /// Sets the flags for the result of a rotate or shift, which all set them the same way.
+    fn set_shift_flags(&mut self, result: u8, carry_out: bool) {
+        self.registers.set_flag(Flag::Z, result == 0);
         self.registers.set_flag(Flag::N, false);
         self.registers.set_flag(Flag::H, false);
-        self.registers.set_flag(Flag::C, carry_out != 0);
+        self.registers.set_flag(Flag::C, carry_out);
+    }
 
+    /// Rotates `x` to the left by 1 bit, bit 7 moving to bit 0.
+    ///
+    /// # Argument
+    /// * `x` - `u8` operand
+    ///
+    /// # Flags
+    /// * `Z` if result == 0
+    /// * `N = 0`
+    /// * `H = 0`
+    /// * `C` the value of the most significant bit in `x`, which is shifted out.
+    pub(in crate::cpu) fn bit_op_rlc(&mut self, x: u8) -> u8 {
+        let value = x.rotate_left(1);
+        self.set_shift_flags(value, x & 0x80 != 0);
         value
     }
 
-    /// "Rotates" or shifts the value of `x` to the right by 1 bit. The flag `C` is "rotated in".
+    /// Rotates `x` to the right by 1 bit, bit 0 moving to bit 7.
     ///
     /// # Argument
     /// * `x` - `u8` operand
@@ -251,15 +309,77 @@ impl Cpu {
     /// * `H = 0`
     /// * `C` the value of the least significant bit in `x`, which is shifted out.
     pub(in crate::cpu) fn bit_op_rrc(&mut self, x: u8) -> u8 {
-        let carry_out = x << 7; // fills with 0
-        let carry_in = self.registers.flag_value(Flag::C) as u8;
-        let value = (x >> 1) | carry_in;
+        let value = x.rotate_right(1);
+        self.set_shift_flags(value, x & 0x01 != 0);
+        value
+    }
 
-        self.registers.set_flag(Flag::Z, value == 0);
-        self.registers.set_flag(Flag::N, false);
-        self.registers.set_flag(Flag::H, false);
-        self.registers.set_flag(Flag::C, carry_out != 0);
+    /// "Rotates" or shifts the value of `x` to the left by 1 bit. The flag `C` is "rotated in".
+    ///
+    /// # Flags
+    /// * `Z` if result == 0
+    /// * `N = 0`
+    /// * `H = 0`
+    /// * `C` the value of the most significant bit in `x`, which is shifted out.
+    pub(in crate::cpu) fn bit_op_rl(&mut self, x: u8) -> u8 {
+        let carry_in = u8::from(self.registers.flag_value(Flag::C));
+        let value = (x << 1) | carry_in;
+        self.set_shift_flags(value, x & 0x80 != 0);
+        value
+    }
+
+    /// "Rotates" or shifts the value of `x` to the right by 1 bit. The flag `C` is "rotated in".
+    ///
+    /// # Flags
+    /// * `Z` if result == 0
+    /// * `N = 0`
+    /// * `H = 0`
+    /// * `C` the value of the least significant bit in `x`, which is shifted out.
+    pub(in crate::cpu) fn bit_op_rr(&mut self, x: u8) -> u8 {
+        let carry_in = u8::from(self.registers.flag_value(Flag::C));
+        let value = (x >> 1) | (carry_in << 7);
+        self.set_shift_flags(value, x & 0x01 != 0);
+        value
+    }
 
+    /// Shifts `x` to the left by 1 bit, filling bit 0 with 0. Flags as `bit_op_rlc`.
+    pub(in crate::cpu) fn bit_op_sla(&mut self, x: u8) -> u8 {
+        let value = x << 1;
+        self.set_shift_flags(value, x & 0x80 != 0);
         value
     }
+
+    /// Shifts `x` to the right by 1 bit, keeping bit 7. Flags as `bit_op_rrc`.
+    pub(in crate::cpu) fn bit_op_sra(&mut self, x: u8) -> u8 {
+        let value = (x >> 1) | (x & 0x80);
+        self.set_shift_flags(value, x & 0x01 != 0);
+        value
+    }
+
+    /// Shifts `x` to the right by 1 bit, filling bit 7 with 0. Flags as `bit_op_rrc`.
+    pub(in crate::cpu) fn bit_op_srl(&mut self, x: u8) -> u8 {
+        let value = x >> 1;
+        self.set_shift_flags(value, x & 0x01 != 0);
+        value
+    }
+
+    /// Swaps the upper and lower nibbles of `x`. Sets `Z` if the result is 0 and clears the
+    /// other flags.
+    pub(in crate::cpu) fn bit_op_swap(&mut self, x: u8) -> u8 {
+        let value = x.rotate_left(4);
+        self.set_shift_flags(value, false);
+        value
+    }
+
+    /// Tests bit `bit` of `x`.
+    ///
+    /// # Flags
+    /// * `Z` if the bit is 0
+    /// * `N = 0`
+    /// * `H = 1`
+    pub(in crate::cpu) fn bit_op_bit(&mut self, bit: u8, x: u8) {
+        self.registers.set_flag(Flag::Z, x & (1 << bit) == 0);
+        self.registers.set_flag(Flag::N, false);
+        self.registers.set_flag(Flag::H, true);
+    }
 }
@@ -1,6 +1,6 @@
 use super::*;
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     /// Executes CPU instruction.
     ///     - do thing
     ///     - set flags
@@ -12,7 +12,8 @@ impl Cpu {
     /// `instruction: u16` - Compiled machine code instruction for the CPU
     ///
     /// # Return value
-    /// `t_states: u8` - Number of clock ticks taken to run instruction. Doesn't simulate per clock cycle right now, only multiples of 4
+    /// `t_states: u8` - Number of clock ticks taken to run instruction, a multiple of 4. See
+    /// `Timing` for when its bus accesses happen.
     pub(crate) fn execute_instr(&mut self, instruction: u8) -> u8 {
         match instruction {
             // 0x00 -> 0x0F
@@ -27,7 +28,7 @@ impl Cpu {
                 // LD (BC), A   | 0x02          | write byte stored in A to memory location (BC)
                 let value = self.registers.get_r8(Register8b::A);
                 let address = self.registers.get_r16(Register16b::BC);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x03 => {
@@ -35,6 +36,7 @@ impl Cpu {
                 let value = self.registers.get_r16(Register16b::BC);
                 self.registers
                     .set_r16(Register16b::BC, value.wrapping_add(1));
+                self.idle();
                 8
             }
             0x04 => {
@@ -72,7 +74,7 @@ impl Cpu {
             0x08 => {
                 // LD (a16), SP | 0x03 0xNNNN    | write stack pointer, u16 to memory address in operand
                 let address = self.fetch_word();
-                self.mmu.write_word(address, self.registers.sp);
+                self.write_word(address, self.registers.sp);
                 20
             }
             0x09 => {
@@ -83,12 +85,13 @@ impl Cpu {
                 let result = self.alu_add_words(hl, bc);
                 self.registers.set_r16(Register16b::HL, result);
 
+                self.idle();
                 8
             }
             0x0A => {
                 // LD A, (BC)
                 let address = self.registers.get_r16(Register16b::BC);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::A, value);
                 8
             }
@@ -98,6 +101,7 @@ impl Cpu {
                 let value = value.wrapping_sub(1);
                 // no flags set!
                 self.registers.set_r16(Register16b::BC, value);
+                self.idle();
                 8
             }
             0x0C => {
@@ -131,10 +135,11 @@ impl Cpu {
                 4
             }
             0x10 => {
-                // STOP
-                self.unimpl_instr();
+                // STOP        | 0x10 0x00     | stop until a button is pressed, resets DIV
                 // TODO: MMU clock speed
                 self.registers.pc = self.registers.pc.wrapping_add(1);
+                self.bus.write(0xFF04, 0);
+                self.stopped = !self.bus.wakes_from_stop();
                 4
             }
             0x11 => {
@@ -147,7 +152,7 @@ impl Cpu {
                 // LD (DE), A
                 let value = self.registers.get_r8(Register8b::A);
                 let address = self.registers.get_r16(Register16b::DE);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x13 => {
@@ -155,6 +160,7 @@ impl Cpu {
                 let value = self.registers.get_r16(Register16b::DE);
                 self.registers
                     .set_r16(Register16b::DE, value.wrapping_add(1));
+                self.idle();
                 8
             }
             0x14 => {
@@ -179,13 +185,15 @@ impl Cpu {
             }
             0x17 => {
                 // RLA
-                self.unimpl_instr();
+                let value = self.registers.get_r8(Register8b::A);
+                let value = self.bit_op_rl(value);
+                self.registers.set_r8(Register8b::A, value);
+                self.registers.set_flag(Flag::Z, false);
                 4
             }
             0x18 => {
                 // JR i8
-                self.unimpl_instr();
-                12
+                self.jump_relative(true)
             }
             0x19 => {
                 // ADD HL, DE
@@ -193,12 +201,13 @@ impl Cpu {
                 let de = self.registers.get_r16(Register16b::DE);
                 let result = self.alu_add_words(hl, de);
                 self.registers.set_r16(Register16b::HL, result);
+                self.idle();
                 8
             }
             0x1A => {
                 // LD A, (DE)
                 let address = self.registers.get_r16(Register16b::DE);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::A, value);
                 8
             }
@@ -207,6 +216,7 @@ impl Cpu {
                 let value = self.registers.get_r16(Register16b::DE);
                 self.registers
                     .set_r16(Register16b::DE, value.wrapping_sub(1));
+                self.idle();
                 8
             }
             0x1C => {
@@ -229,7 +239,19 @@ impl Cpu {
                 self.registers.set_r8(Register8b::E, value);
                 8
             }
+            0x1F => {
+                // RRA
+                let value = self.registers.get_r8(Register8b::A);
+                let value = self.bit_op_rr(value);
+                self.registers.set_r8(Register8b::A, value);
+                self.registers.set_flag(Flag::Z, false);
+                4
+            }
             // 0x20 -> 0x2F
+            0x20 => {
+                // JR NZ, i8
+                self.jump_relative(!self.registers.flag_value(Flag::Z))
+            }
             0x21 => {
                 // LD HL, d16
                 let value = self.fetch_word();
@@ -240,7 +262,7 @@ impl Cpu {
                 // LD (HL+), A
                 let value = self.registers.get_r8(Register8b::A);
                 let address = self.registers.get_r16(Register16b::HL);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
 
                 // increment HL
                 self.registers
@@ -253,6 +275,7 @@ impl Cpu {
                 let value = self.registers.get_r16(Register16b::HL);
                 self.registers
                     .set_r16(Register16b::HL, value.wrapping_add(1));
+                self.idle();
                 8
             }
             0x24 => {
@@ -278,13 +301,19 @@ impl Cpu {
             0x27 => {
                 // DAA
                 // https://stackoverflow.com/questions/8119577/z80-daa-instruction/8119836
-                self.unimpl_instr();
+                self.alu_daa();
+                4
+            }
+            0x28 => {
+                // JR Z, i8
+                self.jump_relative(self.registers.flag_value(Flag::Z))
             }
             0x29 => {
                 // ADD HL, HL
                 let hl = self.registers.get_r16(Register16b::HL);
                 let result = self.alu_add_words(hl, hl);
                 self.registers.set_r16(Register16b::HL, result);
+                self.idle();
                 8
             }
             0x2A => {
@@ -292,7 +321,7 @@ impl Cpu {
                 let address = self.registers.get_r16(Register16b::HL);
                 self.registers
                     .set_r16(Register16b::HL, address.wrapping_add(1));
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::A, value);
                 8
             }
@@ -301,6 +330,7 @@ impl Cpu {
                 let value = self.registers.get_r16(Register16b::HL);
                 self.registers
                     .set_r16(Register16b::HL, value.wrapping_sub(1));
+                self.idle();
                 8
             }
             0x2C => {
@@ -332,6 +362,10 @@ impl Cpu {
                 4
             }
             // 0x30 -> 0x3F
+            0x30 => {
+                // JR NC, i8
+                self.jump_relative(!self.registers.flag_value(Flag::C))
+            }
             0x31 => {
                 // LD SP, d16
                 let value = self.fetch_word();
@@ -342,7 +376,7 @@ impl Cpu {
                 // LD (HL-), A
                 let value = self.registers.get_r8(Register8b::A);
                 let address = self.registers.get_r16(Register16b::HL);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
 
                 // decrement HL
                 self.registers
@@ -353,29 +387,30 @@ impl Cpu {
             0x33 => {
                 // INC SP
                 self.registers.sp = self.registers.sp.wrapping_add(1);
+                self.idle();
                 8
             }
             0x34 => {
                 // INC (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 let value = self.alu_inc_byte(value);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 12
             }
             0x35 => {
                 // DEC (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 let value = self.alu_dec_byte(value);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 12
             }
             0x36 => {
                 // LD (HL), d8
                 let value: u8 = self.fetch_byte();
                 let address = self.registers.get_r16(Register16b::HL);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 12
             }
             0x37 => {
@@ -386,13 +421,8 @@ impl Cpu {
                 4
             }
             0x38 => {
-                // SCF
-                // maybe should be done with set_flag for clarity?
-                let mut flags = self.registers.get_r8(Register8b::F);
-                flags |= 0b_0001_0000; // set bit 4
-                flags &= 0b_1001_1111; // clear bits 5, 6
-                self.registers.set_r8(Register8b::F, flags);
-                4
+                // JR C, i8
+                self.jump_relative(self.registers.flag_value(Flag::C))
             }
             0x39 => {
                 // ADD HL, SP
@@ -400,6 +430,7 @@ impl Cpu {
                 let sp = self.registers.get_r16(Register16b::SP);
                 let result = self.alu_add_words(hl, sp);
                 self.registers.set_r16(Register16b::HL, result);
+                self.idle();
                 8
             }
             0x3A => {
@@ -407,13 +438,14 @@ impl Cpu {
                 let address = self.registers.get_r16(Register16b::HL);
                 self.registers
                     .set_r16(Register16b::HL, address.wrapping_sub(1));
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::A, value);
                 8
             }
             0x3B => {
                 // DEC SP
                 self.registers.sp = self.registers.sp.wrapping_sub(1);
+                self.idle();
                 8
             }
             0x3C => {
@@ -476,7 +508,7 @@ impl Cpu {
             0x46 => {
                 // LD B, (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::B, value);
                 8
             }
@@ -517,7 +549,7 @@ impl Cpu {
             0x4E => {
                 // LD C, (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::C, value);
                 8
             }
@@ -559,7 +591,7 @@ impl Cpu {
             0x56 => {
                 // LD D, (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::D, value);
                 8
             }
@@ -600,7 +632,7 @@ impl Cpu {
             0x5E => {
                 // LD E, (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::E, value);
                 8
             }
@@ -642,7 +674,7 @@ impl Cpu {
             0x66 => {
                 // LD H, (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::H, value);
                 8
             }
@@ -683,7 +715,7 @@ impl Cpu {
             0x6E => {
                 // LD L, (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::L, value);
                 8
             }
@@ -696,54 +728,54 @@ impl Cpu {
                 // LD (HL), B
                 let address = self.registers.get_r16(Register16b::HL);
                 let value = self.registers.get_r8(Register8b::B);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x71 => {
                 // LD (HL), C
                 let address = self.registers.get_r16(Register16b::HL);
                 let value = self.registers.get_r8(Register8b::C);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x72 => {
                 // LD (HL), D
                 let address = self.registers.get_r16(Register16b::HL);
                 let value = self.registers.get_r8(Register8b::D);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x73 => {
                 // LD (HL), E
                 let address = self.registers.get_r16(Register16b::HL);
                 let value = self.registers.get_r8(Register8b::E);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x74 => {
                 // LD (HL), H
                 let address = self.registers.get_r16(Register16b::HL);
                 let value = self.registers.get_r8(Register8b::H);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x75 => {
                 // LD (HL), L
                 let address = self.registers.get_r16(Register16b::HL);
                 let value = self.registers.get_r8(Register8b::L);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x76 => {
-                // HALT
-                self.unimpl_instr();
+                // HALT       | wait for an interrupt
+                self.halted = true;
                 4
             }
             0x77 => {
                 // LD (HL), A
                 let address = self.registers.get_r16(Register16b::HL);
                 let value = self.registers.get_r8(Register8b::A);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 8
             }
             0x78 => {
@@ -779,7 +811,7 @@ impl Cpu {
             0x7E => {
                 // LD A, (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::A, value);
                 8
             }
@@ -846,7 +878,7 @@ impl Cpu {
                 // ADD A, (HL)
                 let a = self.registers.get_r8(Register8b::A);
                 let address = self.registers.get_r16(Register16b::HL);
-                let y = self.mmu.read_byte(address);
+                let y = self.read_byte(address);
                 let result = self.alu_add_bytes(a, y, false);
 
                 self.registers.set_r8(Register8b::A, result);
@@ -918,7 +950,7 @@ impl Cpu {
                 // ADC A, (HL)
                 let a = self.registers.get_r8(Register8b::A);
                 let address = self.registers.get_r16(Register16b::HL);
-                let y = self.mmu.read_byte(address);
+                let y = self.read_byte(address);
                 let result = self.alu_add_bytes(a, y, true);
 
                 self.registers.set_r8(Register8b::A, result);
@@ -991,7 +1023,7 @@ impl Cpu {
                 // SUB A, (HL)
                 let a = self.registers.get_r8(Register8b::A);
                 let address = self.registers.get_r16(Register16b::HL);
-                let y = self.mmu.read_byte(address);
+                let y = self.read_byte(address);
                 let result = self.alu_sub_bytes(a, y, false);
 
                 self.registers.set_r8(Register8b::A, result);
@@ -1063,7 +1095,7 @@ impl Cpu {
                 // SBC A, (HL)
                 let a = self.registers.get_r8(Register8b::A);
                 let address = self.registers.get_r16(Register16b::HL);
-                let y = self.mmu.read_byte(address);
+                let y = self.read_byte(address);
                 let result = self.alu_sub_bytes(a, y, true);
 
                 self.registers.set_r8(Register8b::A, result);
@@ -1116,7 +1148,7 @@ impl Cpu {
             0xA6 => {
                 // AND (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let y = self.mmu.read_byte(address);
+                let y = self.read_byte(address);
                 self.alu_and_a(y);
                 8
             }
@@ -1165,7 +1197,7 @@ impl Cpu {
             0xAE => {
                 // XOR (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let y = self.mmu.read_byte(address);
+                let y = self.read_byte(address);
                 self.alu_xor_a(y);
                 8
             }
@@ -1214,7 +1246,7 @@ impl Cpu {
             0xB6 => {
                 // OR (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let y = self.mmu.read_byte(address);
+                let y = self.read_byte(address);
                 self.alu_or_a(y);
                 8
             }
@@ -1263,7 +1295,7 @@ impl Cpu {
             0xBE => {
                 // CP (HL)
                 let address = self.registers.get_r16(Register16b::HL);
-                let y = self.mmu.read_byte(address);
+                let y = self.read_byte(address);
                 self.alu_cp_a(y);
                 8
             }
@@ -1274,106 +1306,167 @@ impl Cpu {
                 4
             }
             // 0xC0 -> 0xCF
+            0xC0 => {
+                // RET NZ
+                self.return_if(!self.registers.flag_value(Flag::Z))
+            }
             0xC1 => {
                 // POP BC
-                let value = self.mmu.read_word(self.registers.sp);
-                self.registers.sp = self.registers.sp.wrapping_add(2);
+                let value = self.pop_word();
                 self.registers.set_r16(Register16b::BC, value);
                 12
             }
+            0xC2 => {
+                // JP NZ, u16
+                self.jump(!self.registers.flag_value(Flag::Z))
+            }
+            0xC3 => {
+                // JP u16
+                self.jump(true)
+            }
+            0xC4 => {
+                // CALL NZ, u16
+                self.call(!self.registers.flag_value(Flag::Z))
+            }
             0xC5 => {
                 // PUSH BC
                 let value = self.registers.get_r16(Register16b::BC);
-                self.registers.sp = self.registers.sp.wrapping_sub(2);
-                self.mmu.write_word(self.registers.sp, value);
+                self.idle();
+                self.push_word(value);
                 16
             }
             0xC6 => {
                 // ADD A, d8
                 let y = self.fetch_byte();
                 let a = self.registers.get_r8(Register8b::A);
-                self.alu_add_bytes(a, y, false);
+                let result = self.alu_add_bytes(a, y, false);
+                self.registers.set_r8(Register8b::A, result);
                 8
             }
             0xC7 => {
                 // RST 00h
-                self.unimpl_instr();
-                16
+                self.restart(0x0000)
             }
             0xC8 => {
                 // RET Z
-                self.unimpl_instr();
-                // ?
+                self.return_if(self.registers.flag_value(Flag::Z))
             }
             0xC9 => {
                 // RET
-                self.unimpl_instr();
+                self.registers.pc = self.pop_word();
+                self.idle();
                 16
             }
             0xCA => {
                 // JP Z, u16
-                self.unimpl_instr();
-                // ? 12-16
+                self.jump(self.registers.flag_value(Flag::Z))
             }
             0xCB => {
                 // PREFIX
                 let instr = self.fetch_byte();
                 self.execute_prefixed_instr(instr)
             }
+            0xCC => {
+                // CALL Z, u16
+                self.call(self.registers.flag_value(Flag::Z))
+            }
+            0xCD => {
+                // CALL u16
+                self.call(true)
+            }
             0xCE => {
                 // ADC A, d8
                 let y = self.fetch_byte();
                 let a = self.registers.get_r8(Register8b::A);
-                self.alu_add_bytes(a, y, true);
+                let result = self.alu_add_bytes(a, y, true);
+                self.registers.set_r8(Register8b::A, result);
                 8
             }
+            0xCF => {
+                // RST 08h
+                self.restart(0x0008)
+            }
             // 0xD0 -> 0xDF
+            0xD0 => {
+                // RET NC
+                self.return_if(!self.registers.flag_value(Flag::C))
+            }
             0xD1 => {
                 // POP DE
-                let value = self.mmu.read_word(self.registers.sp);
-                self.registers.sp = self.registers.sp.wrapping_add(2);
+                let value = self.pop_word();
                 self.registers.set_r16(Register16b::DE, value);
                 12
             }
+            0xD2 => {
+                // JP NC, u16
+                self.jump(!self.registers.flag_value(Flag::C))
+            }
+            0xD4 => {
+                // CALL NC, u16
+                self.call(!self.registers.flag_value(Flag::C))
+            }
             0xD5 => {
                 // PUSH DE
                 let value = self.registers.get_r16(Register16b::DE);
-                self.registers.sp = self.registers.sp.wrapping_sub(2);
-                self.mmu.write_word(self.registers.sp, value);
+                self.idle();
+                self.push_word(value);
                 16
             }
             0xD6 => {
                 // SUB A, d8
                 let y = self.fetch_byte();
                 let a = self.registers.get_r8(Register8b::A);
-                self.alu_sub_bytes(a, y, false);
+                let result = self.alu_sub_bytes(a, y, false);
+                self.registers.set_r8(Register8b::A, result);
                 8
             }
             0xD7 => {
                 // RST 10h
-                self.unimpl_instr();
+                self.restart(0x0010)
+            }
+            0xD8 => {
+                // RET C
+                self.return_if(self.registers.flag_value(Flag::C))
+            }
+            0xD9 => {
+                // RETI
+                self.registers.pc = self.pop_word();
+                self.interrupt_master_enable = true;
+                self.idle();
                 16
             }
+            0xDA => {
+                // JP C, u16
+                self.jump(self.registers.flag_value(Flag::C))
+            }
+            0xDC => {
+                // CALL C, u16
+                self.call(self.registers.flag_value(Flag::C))
+            }
             0xDE => {
                 // SBC A, d8
                 let y = self.fetch_byte();
                 let a = self.registers.get_r8(Register8b::A);
-                self.alu_sub_bytes(a, y, true);
+                let result = self.alu_sub_bytes(a, y, true);
+                self.registers.set_r8(Register8b::A, result);
                 8
             }
+            0xDF => {
+                // RST 18h
+                self.restart(0x0018)
+            }
             // 0xE0 -> 0xEF
             0xE0 => {
                 // LDH (a8), A
                 let address = self.fetch_byte() as u16 + 0xFF00;
                 assert!(0xFF00 <= address); // DEBUG
                 let value = self.registers.get_r8(Register8b::A);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 12
             }
             0xE1 => {
                 // POP HL
-                let value = self.mmu.read_word(self.registers.sp);
-                self.registers.sp = self.registers.sp.wrapping_add(2);
+                let value = self.pop_word();
                 self.registers.set_r16(Register16b::HL, value);
                 12
             }
@@ -1382,14 +1475,14 @@ impl Cpu {
                 let address = self.registers.get_r8(Register8b::C) as u16 + 0xFF00;
                 assert!(0xFF00 <= address); // DEBUG
                 let value = self.registers.get_r8(Register8b::A);
-                self.mmu.write_byte(address, value);
-                12
+                self.write_byte(address, value);
+                8
             }
             0xE5 => {
                 // PUSH HL
                 let value = self.registers.get_r16(Register16b::HL);
-                self.registers.sp = self.registers.sp.wrapping_sub(2);
-                self.mmu.write_word(self.registers.sp, value);
+                self.idle();
+                self.push_word(value);
                 16
             }
             0xE6 => {
@@ -1400,23 +1493,26 @@ impl Cpu {
             }
             0xE7 => {
                 // RST 20h
-                self.unimpl_instr();
-                16
+                self.restart(0x0020)
             }
             0xE8 => {
                 // ADD SP, r8
-                // two's complement should work here
-                let offset = self.fetch_byte() as i8 as u16;
-                // (255 as i8) as u16 => (-1) as u16 => 25565
-                self.registers.sp = self.alu_add_words(self.registers.sp, offset);
-                self.registers.set_flag(Flag::Z, false);
+                let offset = self.fetch_byte();
+                self.registers.sp = self.alu_add_sp_offset(offset);
+                self.idle();
+                self.idle();
                 16
             }
+            0xE9 => {
+                // JP HL
+                self.registers.pc = self.registers.get_r16(Register16b::HL);
+                4
+            }
             0xEA => {
                 // LD (a16), A
                 let address = self.fetch_word();
                 let value = self.registers.get_r8(Register8b::A);
-                self.mmu.write_byte(address, value);
+                self.write_byte(address, value);
                 16
             }
             0xEE => {
@@ -1427,21 +1523,20 @@ impl Cpu {
             }
             0xEF => {
                 // RST 28h
-                16
+                self.restart(0x0028)
             }
             // 0xF0 -> 0xFF
             0xF0 => {
                 // LDH A, (a8)
                 let address = self.fetch_byte() as u16 + 0xFF00;
                 assert!(0xFF00 <= address); // DEBUG
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::A, value);
                 12
             }
             0xF1 => {
                 // POP AF
-                let value = self.mmu.read_word(self.registers.sp);
-                self.registers.sp = self.registers.sp.wrapping_add(2);
+                let value = self.pop_word();
                 self.registers.set_r16(Register16b::AF, value);
                 12
             }
@@ -1449,20 +1544,21 @@ impl Cpu {
                 // LD A, (C)
                 let address = self.registers.get_r8(Register8b::C) as u16 + 0xFF00;
                 assert!(0xFF00 <= address); // DEBUG
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::A, value);
-                12
+                8
             }
             0xF3 => {
                 // DI       | disable interrupts
                 self.interrupt_master_enable = false;
+                self.enable_interrupts_pending = false;
                 4
             }
             0xF5 => {
                 // PUSH AF
-                let value = self.registers.get_r16(Register16b::AF) | 0xFFF0; // must clear empty flag bits?
-                self.registers.sp = self.registers.sp.wrapping_sub(2);
-                self.mmu.write_word(self.registers.sp, value);
+                let value = self.registers.get_r16(Register16b::AF) & 0xFFF0;
+                self.idle();
+                self.push_word(value);
                 16
             }
             0xF6 => {
@@ -1473,33 +1569,33 @@ impl Cpu {
             }
             0xF7 => {
                 // RST 30h
-                self.unimpl_instr();
-                16
+                self.restart(0x0030)
             }
             0xF8 => {
                 // LD HL, SP + r8
-                let offset = self.fetch_byte() as i8 as u16; // see 0xE8
-                let value = self.alu_add_words(self.registers.sp, offset);
+                let offset = self.fetch_byte();
+                let value = self.alu_add_sp_offset(offset);
                 self.registers.set_r16(Register16b::HL, value);
-                self.registers.set_flag(Flag::Z, false);
+                self.idle();
                 12
             }
             0xFA => {
                 // LD A, (a16)
                 let address = self.fetch_word();
-                let value = self.mmu.read_byte(address);
+                let value = self.read_byte(address);
                 self.registers.set_r8(Register8b::A, value);
                 16
             }
             0xFB => {
-                // EI       | enable interrupts
-                self.interrupt_master_enable = true;
+                // EI       | enable interrupts, after the next instruction
+                self.enable_interrupts_pending = true;
                 4
             }
             0xF9 => {
                 // LD SP, HL
                 let value = self.registers.get_r16(Register16b::HL);
                 self.registers.set_r16(Register16b::SP, value);
+                self.idle();
                 8
             }
             0xFE => {
@@ -1510,24 +1606,72 @@ impl Cpu {
             }
             0xFF => {
                 // RST 38h
-                self.unimpl_instr();
-                16
+                self.restart(0x0038)
             }
             _ => self.unimpl_instr(),
         }
     }
 
-    fn execute_prefixed_instr(&mut self, instruction: u8) -> u8 {
-        self.unimpl_instr();
-
-        // interpret target r8 or (HL)
-
-        // read target and store in value
+    /// Executes a `0xCB` prefixed instruction. The low 3 bits of `instruction` select the
+    /// operand, one of B, C, D, E, H, L, (HL) and A. Bits 3 to 5 select the operation, or the
+    /// bit for BIT, RES and SET.
+    ///
+    /// # Return value
+    /// `t_states: u8` - Number of clock ticks taken, including the prefix.
+    pub(crate) fn execute_prefixed_instr(&mut self, instruction: u8) -> u8 {
+        let register = prefixed_operand(instruction);
+        let value = match register {
+            Some(register) => self.registers.get_r8(register),
+            None => self.read_byte(self.registers.get_r16(Register16b::HL)),
+        };
+        let bit = (instruction >> 3) & 0b111;
 
-        // interpret for desired instruction
+        let result = match instruction >> 6 {
+            0b00 => match bit {
+                0 => self.bit_op_rlc(value),
+                1 => self.bit_op_rrc(value),
+                2 => self.bit_op_rl(value),
+                3 => self.bit_op_rr(value),
+                4 => self.bit_op_sla(value),
+                5 => self.bit_op_sra(value),
+                6 => self.bit_op_swap(value),
+                _ => self.bit_op_srl(value),
+            },
+            0b01 => {
+                // BIT only reads its operand
+                self.bit_op_bit(bit, value);
+                return match register {
+                    Some(_) => 8,
+                    None => 12,
+                };
+            }
+            0b10 => value & !(1 << bit), // RES
+            _ => value | (1 << bit),     // SET
+        };
 
-        // modify value
+        match register {
+            Some(register) => {
+                self.registers.set_r8(register, result);
+                8
+            }
+            None => {
+                self.write_byte(self.registers.get_r16(Register16b::HL), result);
+                16
+            }
+        }
+    }
+}
 
-        // store back into target
+/// Register operand of a `0xCB` prefixed instruction, or `None` for (HL).
+const fn prefixed_operand(instruction: u8) -> Option<Register8b> {
+    match instruction & 0b111 {
+        0 => Some(Register8b::B),
+        1 => Some(Register8b::C),
+        2 => Some(Register8b::D),
+        3 => Some(Register8b::E),
+        4 => Some(Register8b::H),
+        5 => Some(Register8b::L),
+        6 => None,
+        _ => Some(Register8b::A),
     }
 }
@@ -461,7 +461,7 @@ fn cpu_fetch_byte() {
     let mut cpu = Cpu::new();
 
     let test_rom: Vec<u8> = vec![0x00, 0x50, 0x12, 0x55];
-    cpu.mmu.load_rom(test_rom.clone());
+    cpu.bus.load_rom(test_rom.clone());
 
     cpu.registers.pc = 0;
     let mut test_pc: u16 = 0;
@@ -484,7 +484,7 @@ fn cpu_fetch_word() {
         test_rom.push(low);
         test_rom.push(high);
     }
-    cpu.mmu.load_rom(test_rom);
+    cpu.bus.load_rom(test_rom);
 
     cpu.registers.pc = 0;
     let mut test_pc: u16 = 0;
@@ -528,7 +528,7 @@ fn cpu_instr_ld_r8_r8() {
 
     let mut test_pc = 0x00;
     cpu.registers.pc = 0x00;
-    cpu.mmu.load_rom(ops); // NOTE: unstable API for rom loading! subject to change
+    cpu.bus.load_rom(ops); // NOTE: unstable API for rom loading! subject to change
 
     let test_value: u8 = 0x0F;
     for (op, reg_to, reg_from) in test_cases {
@@ -566,7 +566,7 @@ fn cpu_instr_ld_r16_d16() {
         for (op, reg) in instrs.clone() {
             // mocked rom test
             let test_rom = vec![op, bytes.1, bytes.0];
-            cpu.mmu.load_rom(test_rom);
+            cpu.bus.load_rom(test_rom);
             cpu.registers.pc = 0;
 
             cpu.fetch_and_execute();
@@ -592,7 +592,7 @@ fn cpu_instr_ld_r8_d8() {
         for (op, reg) in instrs.clone() {
             let test_rom: Vec<u8> = vec![op, value];
             cpu.registers.pc = 0; // mocked program counter
-            cpu.mmu.load_rom(test_rom);
+            cpu.bus.load_rom(test_rom);
 
             let cycles = cpu.fetch_and_execute();
 
@@ -621,12 +621,12 @@ fn cpu_instr_ld_hl_ptr_d8() {
         cpu.registers.set_r16(Register16b::HL, address);
         let test_rom: Vec<u8> = vec![0x36, value];
         cpu.registers.pc = 0; // mocked program counter
-        cpu.mmu.load_rom(test_rom);
+        cpu.bus.load_rom(test_rom);
 
         let cycles = cpu.fetch_and_execute();
 
         assert_eq!(2, cpu.registers.pc);
-        assert_eq!(value, cpu.mmu.read_byte(address));
+        assert_eq!(value, cpu.bus.read_byte(address));
         assert_eq!(12, cycles);
     }
 }
@@ -651,7 +651,7 @@ fn cpu_instr_ld_bc_ptr_a() {
         let cycles = cpu.execute_instr(0x02);
 
         assert_eq!(8, cycles);
-        assert_eq!(value, cpu.mmu.read_byte(address));
+        assert_eq!(value, cpu.bus.read_byte(address));
         assert_eq!(cpu.registers.pc, 0);
     }
 }
@@ -676,7 +676,7 @@ fn cpu_instr_ld_de_ptr_a() {
         let cycles = cpu.execute_instr(0x12);
 
         assert_eq!(8, cycles);
-        assert_eq!(value, cpu.mmu.read_byte(address));
+        assert_eq!(value, cpu.bus.read_byte(address));
         assert_eq!(cpu.registers.pc, 0);
     }
 }
@@ -705,7 +705,7 @@ fn cpu_instr_ld_hla_ptr_a() {
             address.wrapping_add(1),
             cpu.registers.get_r16(Register16b::HL)
         );
-        assert_eq!(value, cpu.mmu.read_byte(address));
+        assert_eq!(value, cpu.bus.read_byte(address));
         assert_eq!(cpu.registers.pc, 0);
     }
 }
@@ -734,7 +734,7 @@ fn cpu_instr_ld_hld_ptr_a() {
             address.wrapping_sub(1),
             cpu.registers.get_r16(Register16b::HL)
         );
-        assert_eq!(value, cpu.mmu.read_byte(address));
+        assert_eq!(value, cpu.bus.read_byte(address));
         assert_eq!(0, cpu.registers.pc);
     }
 }
@@ -757,7 +757,7 @@ fn cpu_instr_ld_d16_ptr_sp() {
         cpu.registers.set_r16(Register16b::HL, address);
         let (high, low) = word_to_bytes(address);
         let test_rom: Vec<u8> = vec![0x08, low, high];
-        cpu.mmu.load_rom(test_rom);
+        cpu.bus.load_rom(test_rom);
 
         cpu.registers.pc = 0; // mocked program counter
         cpu.registers.sp = stack_pointer;
@@ -766,7 +766,7 @@ fn cpu_instr_ld_d16_ptr_sp() {
 
         assert_eq!(20, cycles);
         assert_eq!(3, cpu.registers.pc);
-        assert_eq!(stack_pointer, cpu.mmu.read_word(address));
+        assert_eq!(stack_pointer, cpu.bus.read_word(address));
     }
 }
 
@@ -814,12 +814,12 @@ fn cpu_instr_inc_hl_ptr() {
     let mut cpu = Cpu::new();
     cpu.registers.pc = 0; // test program counter, shouldn't change
     for (value, address) in test_cases {
-        cpu.mmu.write_byte(address, value);
+        cpu.bus.write_byte(address, value);
         cpu.registers.set_r16(Register16b::HL, address);
         let cycles = cpu.execute_instr(0x34);
 
         assert_eq!(12, cycles);
-        assert_eq!(value.wrapping_add(1), cpu.mmu.read_byte(address));
+        assert_eq!(value.wrapping_add(1), cpu.bus.read_byte(address));
         assert_eq!(cpu.registers.pc, 0);
     }
 }
@@ -840,12 +840,12 @@ fn cpu_instr_dec_hl_ptr() {
     let mut cpu = Cpu::new();
     cpu.registers.pc = 0; // test program counter, shouldn't change
     for (value, address) in test_cases {
-        cpu.mmu.write_byte(address, value);
+        cpu.bus.write_byte(address, value);
         cpu.registers.set_r16(Register16b::HL, address);
         let cycles = cpu.execute_instr(0x35);
 
         assert_eq!(12, cycles);
-        assert_eq!(value.wrapping_sub(1), cpu.mmu.read_byte(address));
+        assert_eq!(value.wrapping_sub(1), cpu.bus.read_byte(address));
         assert_eq!(cpu.registers.pc, 0);
     }
 }
@@ -1387,3 +1387,366 @@ fn cpu_instr_cp_a() {
         }
     }
 }
+
+#[test]
+fn cpu_stop_woken_by_button_press() {
+    use crate::joypad::Button;
+
+    let mut cpu = Cpu::new();
+    cpu.bus.load_rom(vec![0x10, 0x00, 0x00]);
+    // select the action buttons
+    cpu.bus.write_byte(0xFF00, 0x10);
+
+    cpu.registers.pc = 0;
+    assert_eq!(4, cpu.fetch_and_execute());
+    assert!(cpu.is_stopped());
+    assert_eq!(2, cpu.registers.pc);
+
+    // pressing a button in the deselected group doesn't wake the CPU
+    cpu.press_button(Button::Up);
+    cpu.fetch_and_execute();
+    assert!(cpu.is_stopped());
+    assert_eq!(2, cpu.registers.pc);
+
+    cpu.press_button(Button::Start);
+    cpu.fetch_and_execute();
+    assert!(!cpu.is_stopped());
+    assert_eq!(3, cpu.registers.pc);
+}
+
+#[test]
+fn cpu_instr_jumps_calls_and_returns() {
+    let mut cpu = Cpu::new();
+    #[rustfmt::skip]
+    cpu.bus.load_rom(vec![
+        0xC3, 0x10, 0x00, // 0x00: JP 0x0010
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xCD, 0x20, 0x00, // 0x10: CALL 0x0020
+        0x18, 0xFE,       // 0x13: JR -2, to itself
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xAF,             // 0x20: XOR A, setting Z
+        0x20, 0x05,       // 0x21: JR NZ, +5, not taken
+        0xC0,             // 0x23: RET NZ, not taken
+        0xC8,             // 0x24: RET Z
+    ]);
+    cpu.registers.sp = 0xDFFF;
+
+    assert_eq!(16, cpu.fetch_and_execute());
+    assert_eq!(0x0010, cpu.registers.pc);
+    assert_eq!(24, cpu.fetch_and_execute());
+    assert_eq!(0x0020, cpu.registers.pc);
+    assert_eq!(0x0013, cpu.bus.read_word(cpu.registers.sp));
+
+    cpu.fetch_and_execute();
+    assert_eq!(8, cpu.fetch_and_execute());
+    assert_eq!(8, cpu.fetch_and_execute());
+    assert_eq!(20, cpu.fetch_and_execute());
+    assert_eq!(0x0013, cpu.registers.pc);
+    assert_eq!(0xDFFF, cpu.registers.sp);
+
+    assert_eq!(12, cpu.fetch_and_execute());
+    assert_eq!(0x0013, cpu.registers.pc);
+}
+
+#[test]
+fn cpu_instr_prefixed() {
+    let test_cases = &[
+        // op,  register,       value, carry in, result, carry out
+        (0x00, Register8b::B, 0x85, false, 0x0B, true), // RLC B
+        (0x09, Register8b::C, 0x01, false, 0x80, true), // RRC C
+        (0x12, Register8b::D, 0x80, false, 0x00, true), // RL D
+        (0x1B, Register8b::E, 0x01, true, 0x80, true),  // RR E
+        (0x24, Register8b::H, 0xC0, false, 0x80, true), // SLA H
+        (0x2D, Register8b::L, 0x81, false, 0xC0, true), // SRA L
+        (0x37, Register8b::A, 0xF1, true, 0x1F, false), // SWAP A
+        (0x38, Register8b::B, 0x81, false, 0x40, true), // SRL B
+        (0x87, Register8b::A, 0xFF, true, 0xFE, true),  // RES 0, A
+        (0xF8, Register8b::B, 0x00, false, 0x80, false), // SET 7, B
+    ];
+
+    let mut cpu = Cpu::new();
+    for (op, register, value, carry_in, result, carry_out) in test_cases {
+        cpu.registers.set_r8(*register, *value);
+        cpu.registers.set_flag(Flag::C, *carry_in);
+
+        assert_eq!(8, cpu.execute_prefixed_instr(*op), "Op: 0xCB 0x{op:02X}");
+        assert_eq!(
+            *result,
+            cpu.registers.get_r8(*register),
+            "Op: 0xCB 0x{op:02X}"
+        );
+        assert_eq!(
+            *carry_out,
+            cpu.registers.flag_value(Flag::C),
+            "Op: 0xCB 0x{op:02X}"
+        );
+    }
+
+    // BIT 7, (HL)
+    cpu.registers.set_r16(Register16b::HL, 0xC000);
+    cpu.bus.write_byte(0xC000, 0x7F);
+    assert_eq!(12, cpu.execute_prefixed_instr(0x7E));
+    assert!(cpu.registers.flag_value(Flag::Z));
+    // SET 7, (HL)
+    assert_eq!(16, cpu.execute_prefixed_instr(0xFE));
+    assert_eq!(0xFF, cpu.bus.read_byte(0xC000));
+}
+
+#[test]
+fn cpu_instr_carry_in_and_stack() {
+    let mut cpu = Cpu::new();
+    #[rustfmt::skip]
+    let mut rom = vec![
+        0xCE, 0x0F, // 0x00: ADC A, 0x0F
+        0xDE, 0x01, // 0x02: SBC A, 0x01
+        0xE8, 0x01, // 0x04: ADD SP, 1
+        0xF8, 0xFF, // 0x06: LD HL, SP - 1
+        0xF5,       // 0x08: PUSH AF
+        0xEF,       // 0x09: RST 28h
+    ];
+    rom.resize(0x28, 0x00);
+    rom.push(0xD9); // 0x28: RETI
+    cpu.bus.load_rom(rom);
+    cpu.registers.sp = 0xDFFF;
+
+    // the carry in counts towards the half carry
+    cpu.registers.set_flag(Flag::C, true);
+    assert_eq!(8, cpu.fetch_and_execute());
+    assert_eq!(0x10, cpu.registers.get_r8(Register8b::A));
+    assert!(cpu.registers.flag_value(Flag::H));
+    assert!(!cpu.registers.flag_value(Flag::C));
+    cpu.registers.set_flag(Flag::C, true);
+    assert_eq!(8, cpu.fetch_and_execute());
+    assert_eq!(0x0E, cpu.registers.get_r8(Register8b::A));
+    assert!(cpu.registers.flag_value(Flag::H));
+    assert!(!cpu.registers.flag_value(Flag::C));
+
+    // carries out of the low byte of SP, the offset added unsigned
+    assert_eq!(16, cpu.fetch_and_execute());
+    assert_eq!(0xE000, cpu.registers.sp);
+    assert!(cpu.registers.flag_value(Flag::H));
+    assert!(cpu.registers.flag_value(Flag::C));
+    assert_eq!(12, cpu.fetch_and_execute());
+    assert_eq!(0xDFFF, cpu.registers.get_r16(Register16b::HL));
+    assert!(!cpu.registers.flag_value(Flag::H));
+    assert!(!cpu.registers.flag_value(Flag::C));
+
+    // the unused bits of F are pushed as 0
+    assert_eq!(16, cpu.fetch_and_execute());
+    assert_eq!(0x0E00, cpu.bus.read_word(0xDFFE));
+
+    assert_eq!(16, cpu.fetch_and_execute());
+    assert_eq!(0x0028, cpu.registers.pc);
+    assert_eq!(0x000A, cpu.bus.read_word(cpu.registers.sp));
+    assert_eq!(16, cpu.fetch_and_execute());
+    assert_eq!(0x000A, cpu.registers.pc);
+    assert_eq!(0xDFFE, cpu.registers.sp);
+    assert!(cpu.interrupt_master_enable);
+}
+
+#[test]
+fn cpu_instr_daa() {
+    let test_cases = &[
+        // x,   y,    add, result, carry
+        (0x15, 0x27, true, 0x42, false),
+        (0x99, 0x01, true, 0x00, true),
+        (0x42, 0x15, false, 0x27, false),
+        (0x10, 0x20, false, 0x90, true),
+    ];
+
+    let mut cpu = Cpu::new();
+    for (x, y, add, result, carry) in test_cases {
+        let value = match add {
+            true => cpu.alu_add_bytes(*x, *y, false),
+            false => cpu.alu_sub_bytes(*x, *y, false),
+        };
+        cpu.registers.set_r8(Register8b::A, value);
+        cpu.execute_instr(0x27);
+
+        assert_eq!(*result, cpu.registers.get_r8(Register8b::A));
+        assert_eq!(*carry, cpu.registers.flag_value(Flag::C));
+        assert_eq!(*result == 0, cpu.registers.flag_value(Flag::Z));
+    }
+}
+
+#[test]
+fn cpu_halt_and_interrupt_dispatch() {
+    use crate::interrupts::Interrupt;
+
+    let mut cpu = Cpu::new();
+    cpu.bus.load_rom(vec![0xFB, 0x76, 0x00]); // EI, HALT, NOP
+    cpu.registers.sp = 0xDFFF;
+    cpu.bus.write_byte(0xFFFF, Interrupt::Timer.bit());
+
+    cpu.fetch_and_execute();
+    cpu.fetch_and_execute();
+    assert!(cpu.is_halted());
+    // a halted CPU sleeps until the next event, at most 252 T-states
+    assert_eq!(252, cpu.fetch_and_execute());
+    cpu.bus.write_byte(0xFF07, 0b101);
+    assert_eq!(16, cpu.fetch_and_execute());
+    assert_eq!(0x0002, cpu.registers.pc);
+
+    // a disabled interrupt doesn't wake the CPU
+    cpu.bus.write_byte(0xFF0F, Interrupt::VBlank.bit());
+    cpu.fetch_and_execute();
+    assert!(cpu.is_halted());
+
+    cpu.bus
+        .write_byte(0xFF0F, Interrupt::VBlank.bit() | Interrupt::Timer.bit());
+    assert_eq!(20, cpu.fetch_and_execute());
+    assert!(!cpu.is_halted());
+    assert_eq!(Interrupt::Timer.vector(), cpu.registers.pc);
+    assert_eq!(0x0002, cpu.bus.read_word(cpu.registers.sp));
+    // only the serviced interrupt is acknowledged
+    assert_eq!(0xE0 | Interrupt::VBlank.bit(), cpu.bus.read_byte(0xFF0F));
+    assert!(!cpu.interrupt_master_enable);
+}
+
+#[test]
+fn cpu_ei_delayed_by_an_instruction() {
+    use crate::{interrupts::Interrupt, savestate::SaveState};
+
+    let mut cpu = Cpu::new();
+    cpu.bus.load_rom(vec![0xFB, 0x00, 0x00]); // EI, NOP, NOP
+    cpu.registers.sp = 0xDFFF;
+    cpu.bus.write_byte(0xFFFF, Interrupt::Timer.bit());
+    cpu.bus.write_byte(0xFF0F, Interrupt::Timer.bit());
+
+    assert_eq!(4, cpu.fetch_and_execute());
+    assert!(!cpu.interrupt_master_enable);
+
+    // the pending EI survives a save state
+    let mut writer = StateWriter::new(0);
+    writer.section("CPU ", |state| cpu.save_state(state));
+    let bytes = writer.finish();
+    let mut other = Cpu::new();
+    other.bus.load_rom(vec![0xFB, 0x00, 0x00]);
+    other.bus.write_byte(0xFFFF, Interrupt::Timer.bit());
+    other.bus.write_byte(0xFF0F, Interrupt::Timer.bit());
+    let state = SaveState::parse(&bytes).unwrap();
+    other
+        .load_state(&mut state.section("CPU ").unwrap())
+        .unwrap();
+
+    for cpu in &mut [cpu, other] {
+        // the instruction after EI runs before the interrupt is serviced
+        assert_eq!(4, cpu.fetch_and_execute());
+        assert_eq!(0x0002, cpu.registers.pc);
+        assert!(cpu.interrupt_master_enable);
+        assert_eq!(20, cpu.fetch_and_execute());
+        assert_eq!(Interrupt::Timer.vector(), cpu.registers.pc);
+        assert_eq!(0x0002, cpu.bus.read_word(cpu.registers.sp));
+    }
+
+    // DI right after EI cancels it
+    let mut cpu = Cpu::new();
+    cpu.bus.load_rom(vec![0xFB, 0xF3, 0x00]); // EI, DI, NOP
+    cpu.bus.write_byte(0xFFFF, Interrupt::Timer.bit());
+    cpu.bus.write_byte(0xFF0F, Interrupt::Timer.bit());
+    for _ in 0..3 {
+        cpu.fetch_and_execute();
+    }
+    assert!(!cpu.interrupt_master_enable);
+    assert_eq!(0x0003, cpu.registers.pc);
+}
+
+#[test]
+fn cpu_m_cycle_timing_access_order() {
+    // LD (HL), d8 writes in its third M-cycle
+    for &(timing, counter) in &[(Timing::Instruction, 12), (Timing::MCycle, 4)] {
+        let mut cpu = Cpu::new();
+        cpu.set_timing(timing);
+        cpu.bus.load_rom(vec![0x36, 0x00]);
+        cpu.registers.set_r16(Register16b::HL, 0xFF04); // DIV
+
+        assert_eq!(12, cpu.step());
+        assert_eq!(counter, cpu.bus.timer().counter(), "{timing:?}");
+    }
+
+    // CALL pushes in its last two M-cycles, after an internal one
+    for &(timing, counter) in &[(Timing::Instruction, 24), (Timing::MCycle, 4)] {
+        let mut cpu = Cpu::new();
+        cpu.set_timing(timing);
+        cpu.bus.load_rom(vec![0xCD, 0x34, 0x12]);
+        // the low byte of the return address is pushed to DIV
+        cpu.registers.sp = 0xFF06;
+
+        assert_eq!(24, cpu.step());
+        assert_eq!(0x1234, cpu.registers.pc);
+        assert_eq!(counter, cpu.bus.timer().counter(), "{timing:?}");
+    }
+}
+
+#[test]
+fn cpu_m_cycle_timing_matches_instruction_lengths() {
+    // M-cycles of each opcode, conditional ones when the condition fails, 0 for illegal ones
+    #[rustfmt::skip]
+    const CYCLES: [u8; 256] = [
+        1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1,
+        1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+        2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+        2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4,
+        2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4,
+        3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4,
+        3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4,
+    ];
+
+    /// Extra M-cycles a conditional opcode takes when its condition holds, and the condition
+    fn taken(opcode: u8, flags: u8) -> u8 {
+        let extra = match opcode {
+            0x20 | 0x28 | 0x30 | 0x38 | 0xC2 | 0xCA | 0xD2 | 0xDA => 1,
+            0xC0 | 0xC8 | 0xD0 | 0xD8 | 0xC4 | 0xCC | 0xD4 | 0xDC => 3,
+            _ => return 0,
+        };
+        // bits 3 and 4 select Z or C, bit 3 whether the flag has to be set
+        let flag = match opcode & 0x10 {
+            0 => 0x80,
+            _ => 0x10,
+        };
+        match (flags & flag != 0) == (opcode & 0x08 != 0) {
+            true => extra,
+            false => 0,
+        }
+    }
+
+    let programs = (0..=0xFFu8)
+        .filter(|&opcode| CYCLES[usize::from(opcode)] != 0 && opcode != 0xCB)
+        .map(|opcode| vec![opcode, 0x01, 0x02])
+        .chain((0..=0xFFu8).map(|opcode| vec![0xCB, opcode]));
+
+    for program in programs {
+        for &flags in &[0x00, 0xF0] {
+            let expected = match program[..] {
+                [0xCB, opcode, ..] => match (opcode & 0x07, opcode & 0xC0) {
+                    (6, 0x40) => 3,
+                    (6, _) => 4,
+                    _ => 2,
+                },
+                [opcode, ..] => CYCLES[usize::from(opcode)] + taken(opcode, flags),
+                [] => unreachable!(),
+            };
+            let mut cpu = Cpu::new();
+            cpu.set_timing(Timing::MCycle);
+            cpu.bus.load_rom(program.clone());
+            cpu.registers.sp = 0xDFF0;
+            cpu.registers.set_r16(Register16b::HL, 0xC000);
+            cpu.registers.set_r16(Register16b::AF, u16::from(flags));
+
+            // every M-cycle advances the peripherals as it happens, none being left to catch up
+            let t_states = cpu.fetch_and_execute();
+            let message = format!("{program:02X?} with flags {flags:02X}");
+            assert_eq!(4 * expected, t_states, "{message}");
+            assert_eq!(t_states, cpu.ticked, "{message}");
+        }
+    }
+}
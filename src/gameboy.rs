@@ -0,0 +1,269 @@
+//! The whole console: CPU, bus, cartridge and peripherals, advanced together.
+use crate::{
+    cpu::Cpu,
+    joypad::{Button, JoypadState},
+    memory::Mmu,
+    ppu::{Mode, RendererKind},
+    savestate::{self, SaveState, SaveStateError, StateWriter},
+};
+use std::{error::Error, fmt};
+
+/// T-states in a frame, 154 lines of 456 dots
+pub const T_STATES_PER_FRAME: u64 = 70_224;
+/// Largest cartridge without a memory bank controller
+const MAX_ROM_SIZE: usize = 0x8000;
+/// `LD B, B`, which does nothing and so serves as a software breakpoint
+const BREAKPOINT_OPCODE: u8 = 0x40;
+
+/// Why a cartridge could not be inserted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CartridgeError {
+    /// The ROM needs a memory bank controller, which isn't emulated yet
+    TooLarge(usize),
+}
+
+impl Error for CartridgeError {}
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::TooLarge(size) => write!(
+                f,
+                "ROM of {size} bytes needs a memory bank controller, only 32 KiB ROMs are supported"
+            ),
+        }
+    }
+}
+
+/// A DMG with a cartridge inserted, started from the state the boot ROM leaves it in.
+pub struct GameBoy {
+    cpu: Cpu,
+    /// T-states since power on
+    clock: u64,
+    /// Identifies the cartridge in save states
+    rom_hash: u64,
+}
+
+impl GameBoy {
+    /// Inserts `rom` and powers the console on.
+    pub fn new(rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        Self::with_renderer(rom, RendererKind::Scanline)
+    }
+
+    /// Inserts `rom` and powers the console on, with the PPU using the given renderer.
+    pub fn with_renderer(rom: Vec<u8>, renderer: RendererKind) -> Result<Self, CartridgeError> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(CartridgeError::TooLarge(rom.len()));
+        }
+
+        let rom_hash = savestate::rom_hash(&rom);
+        let mut cpu = Cpu::with_renderer(renderer);
+        cpu.mmu_mut().load_rom(rom);
+        cpu.skip_boot_rom();
+        Ok(Self {
+            cpu,
+            clock: 0,
+            rom_hash,
+        })
+    }
+
+    #[must_use]
+    pub const fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub const fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    /// The bus, through which the cartridge and peripherals are reached
+    #[must_use]
+    pub const fn mmu(&self) -> &Mmu {
+        self.cpu.mmu()
+    }
+
+    pub const fn mmu_mut(&mut self) -> &mut Mmu {
+        self.cpu.mmu_mut()
+    }
+
+    /// T-states since power on
+    #[must_use]
+    pub const fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// `savestate::rom_hash` of the inserted cartridge
+    #[must_use]
+    pub const fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// The last frame the PPU finished, as shades 0 (lightest) to 3 (darkest), row by row.
+    #[must_use]
+    pub fn framebuffer(&self) -> &[u8] {
+        self.mmu().ppu().framebuffer()
+    }
+
+    /// Replaces the held buttons with `state`.
+    pub fn set_joypad(&mut self, state: JoypadState) {
+        self.cpu.set_joypad(state);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.cpu.press_button(button);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.cpu.release_button(button);
+    }
+
+    /// Executes the next instruction, or services an interrupt, advancing the peripherals
+    /// along with it.
+    ///
+    /// # Return value
+    /// Number of clock ticks taken.
+    pub fn step(&mut self) -> u8 {
+        let t_states = self.cpu.step();
+        self.clock += u64::from(t_states);
+        t_states
+    }
+
+    /// Runs for at least `t_states` clock ticks, stopping after the instruction that reaches
+    /// them.
+    ///
+    /// # Return value
+    /// Number of clock ticks actually run.
+    pub fn run_cycles(&mut self, t_states: u64) -> u64 {
+        let start = self.clock;
+        while self.clock - start < t_states {
+            self.step();
+        }
+        self.clock - start
+    }
+
+    /// Runs until the PPU enters V-blank, when a new frame is in the framebuffer. With the LCD
+    /// off there is no V-blank, so it returns after a frame's worth of clock ticks instead.
+    ///
+    /// # Return value
+    /// Number of clock ticks run.
+    pub fn run_frame(&mut self) -> u64 {
+        let start = self.clock;
+        let mut mode = self.mmu().ppu().mode();
+        while self.clock - start < T_STATES_PER_FRAME {
+            self.step();
+            let previous = std::mem::replace(&mut mode, self.mmu().ppu().mode());
+            if mode == Mode::VBlank && previous != Mode::VBlank {
+                break;
+            }
+        }
+        self.clock - start
+    }
+
+    /// Whether the CPU is about to execute `LD B, B`, the breakpoint test ROMs such as
+    /// Mooneye's signal their result with, rather than sleep or service an interrupt first.
+    #[must_use]
+    pub fn at_breakpoint(&self) -> bool {
+        let pc = self.cpu.registers().pc;
+        let pending = self.mmu().read_byte(0xFFFF) & self.mmu().read_byte(0xFF0F) & 0b_0001_1111;
+        let interrupted = self.cpu.interrupts_enabled() && pending != 0;
+        !self.cpu.is_halted()
+            && !self.cpu.is_stopped()
+            && !interrupted
+            && self.mmu().read_byte(pc) == BREAKPOINT_OPCODE
+    }
+
+    /// Runs until the CPU has executed an `LD B, B` breakpoint.
+    ///
+    /// # Arguments
+    /// * `max_t_states` - Number of clock ticks to give up after
+    ///
+    /// # Return value
+    /// `true` if the breakpoint was hit in time.
+    pub fn run_until_breakpoint(&mut self, max_t_states: u64) -> bool {
+        let start = self.clock;
+        while self.clock - start < max_t_states {
+            let breakpoint = self.at_breakpoint();
+            self.step();
+            if breakpoint {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Snapshots the whole console, see `savestate` for the format.
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new(self.rom_hash);
+        state.section("GB  ", |state| state.u64(self.clock));
+        state.section("CPU ", |state| self.cpu.save_state(state));
+        self.mmu().save_state(&mut state);
+        state.finish()
+    }
+
+    /// Restores a snapshot taken by `GameBoy::save_state` with the same cartridge inserted.
+    /// If it can't be loaded the console is left as it was.
+    ///
+    /// # Panics
+    /// If the console can't be put back as it was after a failed load, which would be a bug.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let state = SaveState::parse(state)?;
+        if state.rom_hash() != self.rom_hash {
+            return Err(SaveStateError::WrongCartridge);
+        }
+
+        let backup = self.save_state();
+        let result = self.restore(&state);
+        if result.is_err() {
+            let backup = SaveState::parse(&backup).expect("own state parses");
+            self.restore(&backup).expect("own state loads");
+        }
+        result
+    }
+
+    fn restore(&mut self, state: &SaveState<'_>) -> Result<(), SaveStateError> {
+        self.clock = state.section("GB  ")?.u64()?;
+        self.cpu.load_state(&mut state.section("CPU ")?)?;
+        self.mmu_mut().load_state(state)
+    }
+
+    /// Runs until one of `patterns` is among the bytes shifted out of the serial port, which is
+    /// how test ROMs such as Blargg's report "Passed" or "Failed". Starts capturing the serial
+    /// output if it isn't already.
+    ///
+    /// # Arguments
+    /// * `patterns` - Strings to wait for
+    /// * `max_t_states` - Number of clock ticks to give up after
+    ///
+    /// # Return value
+    /// The first of `patterns` found in the output, or `None` if none appeared in time.
+    pub fn run_until_serial_output<'p>(
+        &mut self,
+        patterns: &[&'p str],
+        max_t_states: u64,
+    ) -> Option<&'p str> {
+        self.mmu_mut().serial_mut().set_capture(true);
+
+        let start = self.clock;
+        let mut searched = None;
+        loop {
+            let output = self.mmu().serial().captured();
+            if searched != Some(output.len()) {
+                searched = Some(output.len());
+                let found = patterns.iter().find(|pattern| {
+                    let pattern = pattern.as_bytes();
+                    pattern.is_empty() || output.windows(pattern.len()).any(|w| w == pattern)
+                });
+                if let Some(pattern) = found {
+                    return Some(pattern);
+                }
+            }
+            if self.clock - start >= max_t_states {
+                return None;
+            }
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,245 @@
+use super::{CartridgeError, GameBoy, T_STATES_PER_FRAME};
+use crate::{
+    cpu::registers::Register8b,
+    joypad::Button,
+    ppu::{Mode, RendererKind},
+    savestate::SaveStateError,
+};
+
+/// A cartridge running `program` from the entry point
+fn cartridge(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    rom
+}
+
+#[test]
+fn gameboy_power_on() {
+    assert_eq!(
+        Err(CartridgeError::TooLarge(0x10000)),
+        GameBoy::new(vec![0; 0x10000]).map(|_| ())
+    );
+
+    // the last byte of ROM is reachable
+    let mut rom = cartridge(&[0xFA, 0xFF, 0x7F, 0x18, 0xFE]); // LD A, (0x7FFF); JR -2
+    rom[0x7FFF] = 0x42;
+    let mut gameboy = GameBoy::new(rom).unwrap();
+    assert_eq!(0x91, gameboy.mmu().read_byte(0xFF40));
+    assert_eq!(0x0100, gameboy.cpu().registers().pc);
+    gameboy.step();
+    assert_eq!(0x42, gameboy.cpu().registers().get_r8(Register8b::A));
+}
+
+#[test]
+fn gameboy_run_cycles_and_frames() {
+    // JR -2, 12 T-states a jump
+    let mut gameboy = GameBoy::new(cartridge(&[0x18, 0xFE])).unwrap();
+    assert_eq!(1008, gameboy.run_cycles(1000));
+    assert_eq!(1008, gameboy.clock());
+
+    // the LCD was switched on at power on, and its first V-blank is 144 lines in
+    let ran = gameboy.run_frame();
+    assert_eq!(Mode::VBlank, gameboy.mmu().ppu().mode());
+    assert_eq!(144, gameboy.mmu().read_byte(0xFF44));
+    assert!(ran < T_STATES_PER_FRAME);
+
+    // then a frame apart
+    let ran = gameboy.run_frame();
+    assert!(ran.abs_diff(T_STATES_PER_FRAME) < 12);
+
+    // with the LCD off, a frame's worth of time
+    gameboy.mmu_mut().write_byte(0xFF40, 0x00);
+    assert!(gameboy.run_frame() >= T_STATES_PER_FRAME);
+}
+
+/// Keeps the timer, the interrupts, pulse channel 1 and the PPU busy, scrolling by DIV and
+/// filling VRAM with it
+fn busy_cartridge() -> Vec<u8> {
+    #[rustfmt::skip]
+    let mut rom = cartridge(&[
+        0x3E, 0x05,       // 0x0100: LD A, 0x05
+        0xE0, 0x07,       // 0x0102: LDH (TAC), A
+        0xE0, 0xFF,       // 0x0104: LDH (IE), A       ; V-blank and timer
+        0x3E, 0x87,       // 0x0106: LD A, 0x87
+        0xE0, 0x14,       // 0x0108: LDH (NR14), A     ; trigger channel 1
+        0xFB,             // 0x010A: EI
+        0xF0, 0x04,       // 0x010B: LDH A, (DIV)
+        0xE0, 0x43,       // 0x010D: LDH (SCX), A
+        0x22,             // 0x010F: LD (HL+), A
+        0x7C,             // 0x0110: LD A, H
+        0xF6, 0x80,       // 0x0111: OR 0x80           ; keep HL in VRAM
+        0xE6, 0x9F,       // 0x0113: AND 0x9F
+        0x67,             // 0x0115: LD H, A
+        0x18, 0xF3,       // 0x0116: JR 0x010B
+    ]);
+    rom[0x40] = 0xD9; // RETI
+    rom[0x50] = 0xD9;
+    rom
+}
+
+/// Frames and clock of a run, pressing A halfway through
+fn run_frames(gameboy: &mut GameBoy) -> Vec<(u64, Vec<u8>)> {
+    (0..8)
+        .map(|frame| {
+            match frame == 4 {
+                true => gameboy.press(Button::A),
+                false => gameboy.release(Button::A),
+            }
+            gameboy.run_frame();
+            (gameboy.clock(), gameboy.framebuffer().to_vec())
+        })
+        .collect()
+}
+
+#[test]
+fn gameboy_save_state_restores_exactly() {
+    for &kind in &[RendererKind::Scanline, RendererKind::PixelFifo] {
+        let mut gameboy = GameBoy::with_renderer(busy_cartridge(), kind).unwrap();
+        gameboy.run_frame();
+        // stop in mode 3, with the renderer partway through a line
+        while gameboy.mmu().ppu().mode() != Mode::Drawing {
+            gameboy.step();
+        }
+
+        let state = gameboy.save_state();
+        let expected = run_frames(&mut gameboy);
+        let after = gameboy.save_state();
+
+        gameboy.load_state(&state).unwrap();
+        assert_eq!(state, gameboy.save_state());
+        assert!(expected == run_frames(&mut gameboy), "{:?}", kind);
+        assert!(after == gameboy.save_state(), "{:?}", kind);
+
+        // in HBlank, with the renderer done with the line
+        while gameboy.mmu().ppu().mode() != Mode::HBlank {
+            gameboy.step();
+        }
+        let hblank = gameboy.save_state();
+        gameboy.load_state(&hblank).unwrap();
+        assert!(hblank == gameboy.save_state(), "{:?}", kind);
+
+        // into another console with the same cartridge
+        let mut other = GameBoy::with_renderer(busy_cartridge(), kind).unwrap();
+        other.load_state(&state).unwrap();
+        assert!(expected == run_frames(&mut other), "{:?}", kind);
+    }
+}
+
+#[test]
+fn gameboy_load_state_errors() {
+    let mut gameboy = GameBoy::with_renderer(busy_cartridge(), RendererKind::PixelFifo).unwrap();
+    while gameboy.mmu().ppu().mode() != Mode::Drawing {
+        gameboy.step();
+    }
+    let state = gameboy.save_state();
+    gameboy.run_frame();
+    let before = gameboy.save_state();
+
+    let mut other = GameBoy::new(cartridge(&[0x18, 0xFE])).unwrap();
+    assert_eq!(
+        Err(SaveStateError::WrongCartridge),
+        other.load_state(&state)
+    );
+    assert_eq!(
+        Err(SaveStateError::NotASaveState),
+        gameboy.load_state(&state[..state.len() - 1])
+    );
+
+    // a state missing its last section fails after the others were read
+    let last = state.windows(4).rposition(|tag| tag == b"SCHD").unwrap();
+    assert_eq!(
+        Err(SaveStateError::MissingSection("SCHD")),
+        gameboy.load_state(&state[..last])
+    );
+    assert!(before == gameboy.save_state());
+
+    // mode 3 can't be picked up by another renderer
+    let mut scanline = GameBoy::new(busy_cartridge()).unwrap();
+    assert_eq!(
+        Err(SaveStateError::Incompatible("renderer")),
+        scanline.load_state(&state)
+    );
+}
+
+#[test]
+fn gameboy_run_until_breakpoint() {
+    #[rustfmt::skip]
+    let program = [
+        0x06, 0x03,       // 0x0100: LD B, 3
+        0x05,             // 0x0102: DEC B
+        0x20, 0xFD,       // 0x0103: JR NZ, -3
+        0x40,             // 0x0105: LD B, B
+        0x18, 0xFE,       // 0x0106: JR -2
+    ];
+
+    let mut gameboy = GameBoy::new(cartridge(&program)).unwrap();
+    assert!(!gameboy.at_breakpoint());
+    assert!(gameboy.run_until_breakpoint(1000));
+    assert_eq!(0x0106, gameboy.cpu().registers().pc);
+    assert!(!gameboy.run_until_breakpoint(1000));
+}
+
+#[test]
+fn gameboy_breakpoint_after_interrupt() {
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x01,       // 0x0100: LD A, 1
+        0xE0, 0xFF,       // 0x0102: LDH (IE), A
+        0xE0, 0x0F,       // 0x0104: LDH (IF), A
+        0xFB,             // 0x0106: EI
+        0x00,             // 0x0107: NOP
+        0x40,             // 0x0108: LD B, B
+        0x18, 0xFE,       // 0x0109: JR -2
+    ];
+    let mut rom = cartridge(&program);
+    rom[0x0040] = 0xD9; // RETI
+
+    let mut gameboy = GameBoy::new(rom).unwrap();
+    for _ in 0..5 {
+        gameboy.step();
+    }
+    // the V-blank interrupt is serviced before LD B, B runs
+    assert_eq!(0x0108, gameboy.cpu().registers().pc);
+    assert!(!gameboy.at_breakpoint());
+    gameboy.step();
+    assert_eq!(0x0040, gameboy.cpu().registers().pc);
+    gameboy.step();
+    assert_eq!(0x0108, gameboy.cpu().registers().pc);
+    assert!(gameboy.at_breakpoint());
+}
+
+#[test]
+fn gameboy_run_until_serial_output() {
+    #[rustfmt::skip]
+    let mut program = vec![
+        0x21, 0x20, 0x01, // 0x0100: LD HL, 0x0120
+        0x2A,             // 0x0103: LD A, (HL+)
+        0xB7,             // 0x0104: OR A
+        0x28, 0xFE,       // 0x0105: JR Z, -2, to itself
+        0xE0, 0x01,       // 0x0107: LDH (SB), A
+        0x3E, 0x81,       // 0x0109: LD A, 0x81
+        0xE0, 0x02,       // 0x010B: LDH (SC), A
+        0xF0, 0x02,       // 0x010D: LDH A, (SC)
+        0xCB, 0x7F,       // 0x010F: BIT 7, A
+        0x20, 0xFA,       // 0x0111: JR NZ, -6
+        0x18, 0xEE,       // 0x0113: JR -18, to 0x0103
+    ];
+    program.resize(0x20, 0x00);
+    program.extend_from_slice(b"Test\nPassed\n\0");
+
+    let mut gameboy = GameBoy::new(cartridge(&program)).unwrap();
+    // a byte takes 4096 T-states
+    assert_eq!(
+        None,
+        gameboy.run_until_serial_output(&["Passed", "Failed"], 8 * 4096)
+    );
+    assert_eq!(b"Test\nPa", gameboy.mmu().serial().captured());
+    assert_eq!(
+        Some("Passed"),
+        gameboy.run_until_serial_output(&["Failed", "Passed"], 10 * 4096)
+    );
+
+    let mut gameboy = GameBoy::new(cartridge(&program)).unwrap();
+    assert_eq!(None, gameboy.run_until_serial_output(&["Failed"], 100_000));
+    assert_eq!(b"Test\nPassed\n", gameboy.mmu().serial().captured());
+}
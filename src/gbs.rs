@@ -0,0 +1,369 @@
+//! Playback of GBS rips, the sound driver and music data of a game along with a header saying
+//! how to drive them.
+//!
+//! The data is mapped at its load address in a synthetic cartridge. Below it the cartridge holds
+//! a small driver, as hardware GBS players do:
+//!
+//! | address           | contents                                                       |
+//! | ----------------- | -------------------------------------------------------------- |
+//! | `0x0000..=0x003F` | RST vectors, jumping to the same offsets from the load address |
+//! | `0x0040..=0x0060` | interrupt handlers, the V-blank and timer ones calling PLAY    |
+//! | `0x0100`          | sets up the APU and timer, calls INIT, then halts forever      |
+//!
+//! PLAY is called on V-blank, about 59.7 times a second, unless the header enables the timer, in
+//! which case it's called on the timer interrupt as set up by TMA and TAC.
+use crate::{
+    apu::{AudioOutput, HighPass},
+    cpu::Cpu,
+    interrupts::Interrupt,
+};
+use std::{error::Error, fmt};
+
+/// Size of the header, the data following it
+pub const HEADER_SIZE: usize = 0x70;
+/// The driver lives below this address
+const MIN_LOAD_ADDRESS: u16 = 0x0400;
+const DRIVER_ENTRY: u16 = 0x0100;
+const TAC_ENABLE: u8 = 0b_0000_0100;
+
+/// Why a GBS file was rejected, or a track could not be selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GbsError {
+    /// The file is shorter than its header
+    Truncated,
+    /// The file doesn't start with `GBS`
+    BadMagic,
+    UnsupportedVersion(u8),
+    /// The header lists no songs
+    NoSongs,
+    /// The data would overlap the driver, or start outside of ROM
+    BadLoadAddress(u16),
+    /// The track doesn't exist, tracks being counted from 0
+    TrackOutOfRange {
+        track: u8,
+        count: u8,
+    },
+}
+
+impl Error for GbsError {}
+impl fmt::Display for GbsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Truncated => write!(f, "GBS file is shorter than its header"),
+            Self::BadMagic => write!(f, "Not a GBS file"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported GBS version {version}")
+            }
+            Self::NoSongs => write!(f, "GBS file contains no songs"),
+            Self::BadLoadAddress(address) => {
+                write!(f, "Invalid GBS load address 0x{address:04X}")
+            }
+            Self::TrackOutOfRange { track, count } => {
+                write!(f, "Track {track} out of range, the file has {count}")
+            }
+        }
+    }
+}
+
+/// A parsed GBS file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gbs {
+    song_count: u8,
+    first_track: u8,
+    load_address: u16,
+    init_address: u16,
+    play_address: u16,
+    stack_pointer: u16,
+    timer_modulo: u8,
+    timer_control: u8,
+    title: String,
+    author: String,
+    copyright: String,
+    data: Vec<u8>,
+}
+
+impl Gbs {
+    /// Parses the contents of a GBS file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, GbsError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(GbsError::Truncated);
+        }
+        if &bytes[0x00..0x03] != b"GBS" {
+            return Err(GbsError::BadMagic);
+        }
+        if bytes[0x03] != 1 {
+            return Err(GbsError::UnsupportedVersion(bytes[0x03]));
+        }
+        if bytes[0x04] == 0 {
+            return Err(GbsError::NoSongs);
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let text = |offset: usize| {
+            let field = &bytes[offset..offset + 0x20];
+            let end = field
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).into_owned()
+        };
+
+        let load_address = word(0x06);
+        let data = bytes[HEADER_SIZE..].to_vec();
+        if !(MIN_LOAD_ADDRESS..0x8000).contains(&load_address) {
+            return Err(GbsError::BadLoadAddress(load_address));
+        }
+
+        Ok(Self {
+            song_count: bytes[0x04],
+            // the header counts from 1
+            first_track: bytes[0x05].saturating_sub(1).min(bytes[0x04] - 1),
+            load_address,
+            init_address: word(0x08),
+            play_address: word(0x0A),
+            stack_pointer: word(0x0C),
+            timer_modulo: bytes[0x0E],
+            timer_control: bytes[0x0F],
+            title: text(0x10),
+            author: text(0x30),
+            copyright: text(0x50),
+            data,
+        })
+    }
+
+    #[must_use]
+    pub const fn song_count(&self) -> u8 {
+        self.song_count
+    }
+
+    /// Track to play by default, counting from 0
+    #[must_use]
+    pub const fn first_track(&self) -> u8 {
+        self.first_track
+    }
+
+    #[must_use]
+    pub const fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    /// Routine called once with the track number in A
+    #[must_use]
+    pub const fn init_address(&self) -> u16 {
+        self.init_address
+    }
+
+    /// Routine called on every V-blank or timer interrupt
+    #[must_use]
+    pub const fn play_address(&self) -> u16 {
+        self.play_address
+    }
+
+    #[must_use]
+    pub const fn stack_pointer(&self) -> u16 {
+        self.stack_pointer
+    }
+
+    /// TMA, loaded before INIT
+    #[must_use]
+    pub const fn timer_modulo(&self) -> u8 {
+        self.timer_modulo
+    }
+
+    /// TAC, loaded before INIT. Bit 7 asks for CGB double speed, which isn't emulated.
+    #[must_use]
+    pub const fn timer_control(&self) -> u8 {
+        self.timer_control
+    }
+
+    /// Whether PLAY is called on the timer interrupt rather than on V-blank
+    #[must_use]
+    pub const fn uses_timer(&self) -> bool {
+        self.timer_control & TAC_ENABLE != 0
+    }
+
+    #[must_use]
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    #[must_use]
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    #[must_use]
+    pub fn copyright(&self) -> &str {
+        &self.copyright
+    }
+
+    /// Contents of the synthetic cartridge, with the driver calling INIT with `track`.
+    fn cartridge(&self, track: u8) -> Vec<u8> {
+        let load = usize::from(self.load_address);
+        let mut rom = vec![0xFF; load + self.data.len()];
+        rom[load..].copy_from_slice(&self.data);
+
+        for vector in (0x00..0x40).step_by(8) {
+            let [low, high] = (self.load_address + vector).to_le_bytes();
+            let vector = usize::from(vector);
+            rom[vector..vector + 3].copy_from_slice(&[0xC3, low, high]); // JP load + vector
+        }
+
+        let [play_low, play_high] = self.play_address.to_le_bytes();
+        for interrupt in Interrupt::ALL {
+            let vector = usize::from(interrupt.vector());
+            match interrupt {
+                // CALL play, RETI
+                Interrupt::VBlank | Interrupt::Timer => {
+                    rom[vector..vector + 4].copy_from_slice(&[0xCD, play_low, play_high, 0xD9]);
+                }
+                _ => rom[vector] = 0xD9,
+            }
+        }
+
+        let (interrupt, lcdc) = match self.uses_timer() {
+            true => (Interrupt::Timer, 0x00),
+            false => (Interrupt::VBlank, 0x80),
+        };
+        let [sp_low, sp_high] = self.stack_pointer.to_le_bytes();
+        let [init_low, init_high] = self.init_address.to_le_bytes();
+        #[rustfmt::skip]
+        let driver = [
+            0xF3,                                         // DI
+            0x31, sp_low, sp_high,                        // LD SP, sp
+            0x3E, 0x80, 0xE0, 0x26,                       // NR52: power on
+            0x3E, 0x77, 0xE0, 0x24,                       // NR50: full volume
+            0x3E, 0xFF, 0xE0, 0x25,                       // NR51: all channels to both sides
+            0x3E, self.timer_modulo, 0xE0, 0x06,          // TMA
+            0x3E, self.timer_control & 0b111, 0xE0, 0x07, // TAC
+            0x3E, lcdc, 0xE0, 0x40,                       // LCDC: on for V-blank
+            0x3E, interrupt.bit(), 0xE0, 0xFF,            // IE
+            0x3E, track,                                  // LD A, track
+            0xCD, init_low, init_high,                    // CALL init
+            0xAF, 0xE0, 0x0F,                             // clear IF
+            0xFB,                                         // EI
+            0x76,                                         // HALT
+            0x18, 0xFD,                                   // JR -3, back to HALT
+        ];
+        let entry = usize::from(DRIVER_ENTRY);
+        rom[entry..entry + driver.len()].copy_from_slice(&driver);
+        rom
+    }
+}
+
+/// Plays the tracks of a GBS file, rendering them to samples without any video or audio
+/// device.
+pub struct GbsPlayer {
+    gbs: Gbs,
+    cpu: Cpu,
+    track: u8,
+    sample_rate: u32,
+    high_pass: HighPass,
+    /// Interleaved samples rendered but not yet returned
+    pending: Vec<f32>,
+}
+
+impl GbsPlayer {
+    /// Creates a player starting the first track of `gbs`.
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Host sample rate in Hz, see `AudioOutput::new`
+    /// * `high_pass` - Which console's output capacitor to model
+    #[must_use]
+    pub fn new(gbs: Gbs, sample_rate: u32, high_pass: HighPass) -> Self {
+        let track = gbs.first_track();
+        let mut player = Self {
+            gbs,
+            cpu: Cpu::new(),
+            track,
+            sample_rate,
+            high_pass,
+            pending: Vec::new(),
+        };
+        player.restart();
+        player
+    }
+
+    #[must_use]
+    pub const fn gbs(&self) -> &Gbs {
+        &self.gbs
+    }
+
+    /// Track being played, counting from 0
+    #[must_use]
+    pub const fn track(&self) -> u8 {
+        self.track
+    }
+
+    /// Starts playing `track` from the beginning, counting from 0.
+    pub fn select_track(&mut self, track: u8) -> Result<(), GbsError> {
+        if track >= self.gbs.song_count() {
+            return Err(GbsError::TrackOutOfRange {
+                track,
+                count: self.gbs.song_count(),
+            });
+        }
+        self.track = track;
+        self.restart();
+        Ok(())
+    }
+
+    /// Resets the console and runs the driver from its entry point.
+    fn restart(&mut self) {
+        self.cpu = Cpu::new();
+        self.cpu
+            .mmu_mut()
+            .insert_gbs_cartridge(self.gbs.cartridge(self.track));
+        self.cpu.set_pc(DRIVER_ENTRY);
+        let output = AudioOutput::new(self.sample_rate, self.high_pass);
+        self.cpu.mmu_mut().apu_mut().set_audio_output(Some(output));
+        self.pending.clear();
+    }
+
+    /// Plays the next `frames` stereo samples of the track.
+    ///
+    /// # Arguments
+    /// * `buffer` - Gets the samples appended as interleaved left and right `f32`s in
+    ///   `-1.0..=1.0`
+    pub fn render_f32(&mut self, frames: usize, buffer: &mut Vec<f32>) {
+        self.run_until(frames);
+        buffer.extend(self.pending.drain(..frames * 2));
+    }
+
+    /// Plays the next `frames` stereo samples of the track.
+    ///
+    /// # Arguments
+    /// * `buffer` - Gets the samples appended as interleaved left and right `i16`s
+    pub fn render_i16(&mut self, frames: usize, buffer: &mut Vec<i16>) {
+        self.run_until(frames);
+        #[allow(clippy::cast_possible_truncation)]
+        buffer.extend(
+            self.pending
+                .drain(..frames * 2)
+                .map(|sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16),
+        );
+    }
+
+    /// Runs the console until `frames` stereo samples are pending.
+    fn run_until(&mut self, frames: usize) {
+        while self.pending.len() < frames * 2 {
+            let output = self
+                .cpu
+                .mmu_mut()
+                .apu_mut()
+                .audio_output_mut()
+                .expect("the player sets an audio output");
+            match output.samples_available() {
+                0 => {
+                    self.cpu.step();
+                }
+                _ => {
+                    output.read_f32(&mut self.pending);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,118 @@
+use super::*;
+
+const LOAD: u16 = 0x0400;
+const PLAY: u16 = 0x0420;
+/// Number of PLAY calls, incremented by the test driver
+const PLAY_COUNT: u16 = 0xC001;
+
+/// A GBS file whose INIT stores the track at `0xC000` and starts a square wave on channel
+/// 2, and whose PLAY counts its calls at `PLAY_COUNT`.
+fn test_file(timer_control: u8) -> Vec<u8> {
+    let mut file = vec![0; HEADER_SIZE];
+    file[0x00..0x03].copy_from_slice(b"GBS");
+    file[0x03] = 1;
+    file[0x04] = 3; // songs
+    file[0x05] = 2; // first song
+    file[0x06..0x08].copy_from_slice(&LOAD.to_le_bytes());
+    file[0x08..0x0A].copy_from_slice(&LOAD.to_le_bytes());
+    file[0x0A..0x0C].copy_from_slice(&PLAY.to_le_bytes());
+    file[0x0C..0x0E].copy_from_slice(&0xDFFFu16.to_le_bytes());
+    file[0x0E] = 0x00;
+    file[0x0F] = timer_control;
+    file[0x10..0x14].copy_from_slice(b"Tune");
+    file[0x30..0x32].copy_from_slice(b"Me");
+
+    #[rustfmt::skip]
+    let init = [
+        0xEA, 0x00, 0xC0,       // LD (0xC000), A
+        0x3E, 0xF0, 0xE0, 0x17, // NR22: full volume
+        0x3E, 0x80, 0xE0, 0x16, // NR21: 50% duty
+        0x3E, 0x00, 0xE0, 0x18, // NR23
+        0x3E, 0x87, 0xE0, 0x19, // NR24: trigger
+        0xC9,                   // RET
+    ];
+    #[rustfmt::skip]
+    let play = [
+        0x21, 0x01, 0xC0, // LD HL, PLAY_COUNT
+        0x34,             // INC (HL)
+        0xC9,             // RET
+    ];
+    let mut data = vec![0; 0x40];
+    data[..init.len()].copy_from_slice(&init);
+    data[usize::from(PLAY - LOAD)..][..play.len()].copy_from_slice(&play);
+    file.extend_from_slice(&data);
+    file
+}
+
+fn read(player: &GbsPlayer, address: u16) -> u8 {
+    player.cpu.mmu().read_byte(address)
+}
+
+#[test]
+fn gbs_parse_header() {
+    let gbs = Gbs::parse(&test_file(0)).unwrap();
+    assert_eq!(3, gbs.song_count());
+    assert_eq!(1, gbs.first_track());
+    assert_eq!(LOAD, gbs.load_address());
+    assert_eq!(PLAY, gbs.play_address());
+    assert_eq!(0xDFFF, gbs.stack_pointer());
+    assert_eq!("Tune", gbs.title());
+    assert_eq!("Me", gbs.author());
+    assert_eq!("", gbs.copyright());
+    assert!(!gbs.uses_timer());
+
+    let mut file = test_file(0);
+    assert_eq!(Err(GbsError::Truncated), Gbs::parse(&file[..0x10]));
+    file[0x06..0x08].copy_from_slice(&0x0200u16.to_le_bytes());
+    assert_eq!(Err(GbsError::BadLoadAddress(0x0200)), Gbs::parse(&file));
+    file[0x00] = b'X';
+    assert_eq!(Err(GbsError::BadMagic), Gbs::parse(&file));
+}
+
+#[test]
+fn gbs_play_on_vblank() {
+    let gbs = Gbs::parse(&test_file(0)).unwrap();
+    let mut player = GbsPlayer::new(gbs, 44_100, HighPass::Off);
+    assert_eq!(1, player.track());
+
+    let mut samples = Vec::new();
+    player.render_f32(22_050, &mut samples);
+    assert_eq!(2 * 22_050, samples.len());
+    assert!(samples.iter().any(|&sample| sample > 0.1));
+
+    assert_eq!(1, read(&player, 0xC000));
+    // about 59.7 frames a second
+    let calls = read(&player, PLAY_COUNT);
+    assert!((29..=30).contains(&calls), "{} PLAY calls", calls);
+}
+
+#[test]
+fn gbs_play_on_timer() {
+    // 4096 Hz timer, overflowing every 256 increments
+    let gbs = Gbs::parse(&test_file(TAC_ENABLE)).unwrap();
+    assert!(gbs.uses_timer());
+    let mut player = GbsPlayer::new(gbs, 44_100, HighPass::Off);
+
+    let mut samples = Vec::new();
+    player.render_i16(22_050, &mut samples);
+    assert_eq!(2 * 22_050, samples.len());
+    let calls = read(&player, PLAY_COUNT);
+    assert!((7..=8).contains(&calls), "{} PLAY calls", calls);
+}
+
+#[test]
+fn gbs_select_track() {
+    let gbs = Gbs::parse(&test_file(0)).unwrap();
+    let mut player = GbsPlayer::new(gbs, 44_100, HighPass::Off);
+    let mut samples = Vec::new();
+    player.render_f32(1000, &mut samples);
+
+    player.select_track(2).unwrap();
+    assert_eq!(2, player.track());
+    player.render_f32(1000, &mut samples);
+    assert_eq!(2, read(&player, 0xC000));
+    assert_eq!(
+        Err(GbsError::TrackOutOfRange { track: 3, count: 3 }),
+        player.select_track(3)
+    );
+}
@@ -0,0 +1,41 @@
+//! Interrupt sources, in the bit order of the IE (`0xFFFF`) and IF (`0xFF0F`) registers.
+
+/// Interrupts that peripherals can request by setting their bit in IF.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Interrupt {
+    VBlank = 0b_0000_0001,
+    LcdStat = 0b_0000_0010,
+    Timer = 0b_0000_0100,
+    Serial = 0b_0000_1000,
+    Joypad = 0b_0001_0000,
+}
+
+impl Interrupt {
+    /// Every interrupt, highest priority first
+    pub const ALL: [Self; 5] = [
+        Self::VBlank,
+        Self::LcdStat,
+        Self::Timer,
+        Self::Serial,
+        Self::Joypad,
+    ];
+
+    /// Bit mask of this interrupt in IE and IF.
+    #[must_use]
+    pub const fn bit(self) -> u8 {
+        self as u8
+    }
+
+    /// Address of the handler the CPU jumps to when servicing this interrupt.
+    #[must_use]
+    pub const fn vector(self) -> u16 {
+        match self {
+            Self::VBlank => 0x0040,
+            Self::LcdStat => 0x0048,
+            Self::Timer => 0x0050,
+            Self::Serial => 0x0058,
+            Self::Joypad => 0x0060,
+        }
+    }
+}
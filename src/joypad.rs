@@ -0,0 +1,184 @@
+//! Implements the joypad and the P1/JOYP register at `0xFF00`.
+//!
+//! Buttons are wired in two groups of four sharing the lower nibble of P1. Writing 0 to bit 4
+//! selects the d-pad and writing 0 to bit 5 selects the action buttons. A pressed button in a
+//! selected group reads as 0. Any of those lines going from high to low requests the joypad
+//! interrupt.
+use crate::{
+    interrupts::Interrupt,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const SELECT_DPAD: u8 = 0b_0001_0000;
+const SELECT_ACTION: u8 = 0b_0010_0000;
+
+/// The eight buttons, as bits of a `JoypadState`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Button {
+    Right = 0b_0000_0001,
+    Left = 0b_0000_0010,
+    Up = 0b_0000_0100,
+    Down = 0b_0000_1000,
+    A = 0b_0001_0000,
+    B = 0b_0010_0000,
+    Select = 0b_0100_0000,
+    Start = 0b_1000_0000,
+}
+
+impl Button {
+    /// All buttons, in bit order
+    pub const ALL: [Self; 8] = [
+        Self::Right,
+        Self::Left,
+        Self::Up,
+        Self::Down,
+        Self::A,
+        Self::B,
+        Self::Select,
+        Self::Start,
+    ];
+}
+
+/// Which buttons are held, one bit per `Button` with 1 meaning pressed.
+///
+/// Setting a whole state at once is meant for replays and bots that drive input frame by frame.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct JoypadState(u8);
+
+impl JoypadState {
+    /// No buttons pressed
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// State from a byte with one bit per `Button`
+    #[must_use]
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// One bit per `Button`, 1 meaning pressed
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    #[must_use]
+    pub const fn is_pressed(self, button: Button) -> bool {
+        self.0 & button as u8 != 0
+    }
+
+    /// Copy of this state with `button` pressed
+    #[must_use]
+    pub const fn with(self, button: Button) -> Self {
+        Self(self.0 | button as u8)
+    }
+
+    /// Copy of this state with `button` released
+    #[must_use]
+    pub const fn without(self, button: Button) -> Self {
+        Self(self.0 & !(button as u8))
+    }
+}
+
+/// Joypad
+#[derive(Debug)]
+pub struct Joypad {
+    state: JoypadState,
+    /// Group select bits 4 and 5 of P1, active low
+    select: u8,
+    /// Lower nibble of P1 as last seen, to detect high to low transitions
+    lines: u8,
+}
+
+impl Joypad {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: JoypadState::new(),
+            select: SELECT_DPAD | SELECT_ACTION,
+            lines: 0x0F,
+        }
+    }
+
+    /// Buttons currently held
+    #[must_use]
+    pub const fn state(&self) -> JoypadState {
+        self.state
+    }
+
+    /// Reads P1. Bits 6 and 7 are unused and read as 1.
+    #[must_use]
+    pub const fn read_register(&self) -> u8 {
+        0b_1100_0000 | self.select | self.lines
+    }
+
+    /// Writes the group select bits of P1.
+    ///
+    /// # Return value
+    /// Bit mask of the interrupts requested, to be OR-ed into IF.
+    pub const fn write_register(&mut self, value: u8) -> u8 {
+        self.select = value & (SELECT_DPAD | SELECT_ACTION);
+        self.update_lines()
+    }
+
+    /// Replaces the held buttons with `state`.
+    ///
+    /// # Return value
+    /// Bit mask of the interrupts requested, to be OR-ed into IF.
+    pub const fn set_state(&mut self, state: JoypadState) -> u8 {
+        self.state = state;
+        self.update_lines()
+    }
+
+    /// Whether any line of P1 is pulled low, which is what wakes the CPU from STOP.
+    #[must_use]
+    pub const fn any_line_low(&self) -> bool {
+        self.lines != 0x0F
+    }
+
+    const fn update_lines(&mut self) -> u8 {
+        let [dpad, action] = [self.state.0 & 0x0F, self.state.0 >> 4];
+
+        let mut pressed = 0;
+        if self.select & SELECT_DPAD == 0 {
+            pressed |= dpad;
+        }
+        if self.select & SELECT_ACTION == 0 {
+            pressed |= action;
+        }
+
+        let lines = !pressed & 0x0F;
+        let falling = self.lines & !lines;
+        self.lines = lines;
+
+        match falling {
+            0 => 0,
+            _ => Interrupt::Joypad.bit(),
+        }
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.state.bits());
+        state.u8(self.select);
+        state.u8(self.lines);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.state = JoypadState::from_bits(state.u8()?);
+        self.select = state.u8()? & (SELECT_DPAD | SELECT_ACTION);
+        self.lines = state.u8()? & 0x0F;
+        Ok(())
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,63 @@
+use super::{Button, Interrupt, Joypad, JoypadState};
+
+#[test]
+fn joypad_state_bits() {
+    let mut state = JoypadState::new();
+    for button in &Button::ALL {
+        assert!(!state.is_pressed(*button));
+        state = state.with(*button);
+        assert!(state.is_pressed(*button));
+    }
+    assert_eq!(0xFF, state.bits());
+
+    state = state.without(Button::A).without(Button::Down);
+    assert_eq!(0b_1110_0111, state.bits());
+    assert_eq!(state, JoypadState::from_bits(0b_1110_0111));
+}
+
+#[test]
+fn joypad_default_is_released() {
+    let joypad = Joypad::default();
+    assert_eq!(Joypad::new().read_register(), joypad.read_register());
+    assert!(!joypad.any_line_low());
+}
+
+#[test]
+fn joypad_register_groups() {
+    let mut joypad = Joypad::new();
+    joypad.set_state(JoypadState::new().with(Button::Up).with(Button::Start));
+
+    // nothing selected
+    assert_eq!(0xFF, joypad.read_register());
+
+    joypad.write_register(0x20);
+    assert_eq!(0b_1110_1011, joypad.read_register());
+
+    joypad.write_register(0x10);
+    assert_eq!(0b_1101_0111, joypad.read_register());
+
+    joypad.write_register(0x00);
+    assert_eq!(0b_1100_0011, joypad.read_register());
+}
+
+#[test]
+fn joypad_interrupt_on_press() {
+    let mut joypad = Joypad::new();
+    assert_eq!(0, joypad.write_register(0x10));
+
+    // buttons in the deselected group don't pull a line low
+    assert_eq!(0, joypad.set_state(JoypadState::new().with(Button::Left)));
+    assert!(!joypad.any_line_low());
+
+    let state = JoypadState::new().with(Button::Left).with(Button::B);
+    assert_eq!(Interrupt::Joypad.bit(), joypad.set_state(state));
+    assert!(joypad.any_line_low());
+
+    // holding or releasing doesn't
+    assert_eq!(0, joypad.set_state(state));
+    assert_eq!(0, joypad.set_state(JoypadState::new()));
+
+    // selecting a group with a held button does
+    joypad.set_state(JoypadState::new().with(Button::Left));
+    assert_eq!(Interrupt::Joypad.bit(), joypad.write_register(0x20));
+}
@@ -12,6 +12,21 @@
 )]
 #![allow(clippy::missing_errors_doc, clippy::match_bool, clippy::map_err_ignore)]
 
+pub mod apu;
+pub mod bus;
 pub mod cpu;
+pub mod gameboy;
+pub mod gbs;
+pub mod interrupts;
+pub mod joypad;
+pub mod link;
 pub mod memory;
+pub mod movie;
+pub mod ppu;
+pub mod printer;
+pub mod rewind;
+pub mod savestate;
+pub mod scheduler;
+pub mod serial;
+pub mod timer;
 mod utils;
@@ -0,0 +1,124 @@
+//! Connects two consoles in the same process with a link cable.
+//!
+//! The consoles run in lock-step: whichever is behind in emulated time executes next, so they
+//! never drift apart by more than one instruction. Every transfer clocked by one console is
+//! handed to the other right after the instruction it completes in, which makes a link session
+//! fully deterministic.
+//!
+//! `TcpLink` connects consoles in two processes instead.
+use crate::{gameboy::GameBoy, serial::SerialLink};
+
+mod tcp;
+pub use tcp::{LinkConfig, LinkError, TcpLink};
+
+/// Shifted in when the other console isn't waiting for a transfer
+const NOT_LISTENING: u8 = 0xFF;
+
+/// Two consoles connected by a link cable
+pub struct LinkCable {
+    consoles: [GameBoy; 2],
+    /// Emulated time of each console, in T-states
+    clocks: [u64; 2],
+}
+
+impl LinkCable {
+    /// Connects `first` and `second`, replacing whatever was plugged into their serial ports.
+    #[must_use]
+    pub fn new(first: GameBoy, second: GameBoy) -> Self {
+        let mut consoles = [first, second];
+        for console in &mut consoles {
+            console.mmu_mut().serial_mut().set_link(SerialLink::Cable);
+        }
+        Self {
+            consoles,
+            clocks: [0; 2],
+        }
+    }
+
+    /// Console `index`, 0 or 1
+    ///
+    /// # Panics
+    /// If `index` is above 1.
+    #[must_use]
+    pub const fn console(&self, index: usize) -> &GameBoy {
+        &self.consoles[index]
+    }
+
+    /// Console `index`, 0 or 1, to press buttons on for example
+    ///
+    /// # Panics
+    /// If `index` is above 1.
+    pub const fn console_mut(&mut self, index: usize) -> &mut GameBoy {
+        &mut self.consoles[index]
+    }
+
+    /// Emulated time of console `index`, in T-states
+    ///
+    /// # Panics
+    /// If `index` is above 1.
+    #[must_use]
+    pub const fn clock(&self, index: usize) -> u64 {
+        self.clocks[index]
+    }
+
+    /// Disconnects the consoles.
+    #[must_use]
+    pub fn into_consoles(self) -> (GameBoy, GameBoy) {
+        let [mut first, mut second] = self.consoles;
+        first
+            .mmu_mut()
+            .serial_mut()
+            .set_link(SerialLink::Disconnected);
+        second
+            .mmu_mut()
+            .serial_mut()
+            .set_link(SerialLink::Disconnected);
+        (first, second)
+    }
+
+    /// Steps the console that is behind, or the first one if they are level, then passes on
+    /// any transfer it clocked.
+    ///
+    /// # Return value
+    /// Number of clock ticks the stepped console took.
+    pub fn step(&mut self) -> u8 {
+        let index = match self.clocks[1] < self.clocks[0] {
+            true => 1,
+            false => 0,
+        };
+        let t_states = self.consoles[index].step();
+        self.clocks[index] += u64::from(t_states);
+        self.transfer(index);
+        t_states
+    }
+
+    /// Runs both consoles until each has advanced by at least `t_states` clock ticks.
+    pub fn run_cycles(&mut self, t_states: u64) {
+        let targets = [self.clocks[0] + t_states, self.clocks[1] + t_states];
+        while self.clocks[0] < targets[0] || self.clocks[1] < targets[1] {
+            self.step();
+        }
+    }
+
+    /// Completes the transfer console `master` clocked, if any, with the byte of the other
+    /// console, or `0xFF` if that one isn't waiting for a transfer.
+    fn transfer(&mut self, master: usize) {
+        let [first, second] = &mut self.consoles;
+        let (master, slave) = match master {
+            0 => (first, second),
+            _ => (second, first),
+        };
+
+        if let Some(sent) = master.mmu().serial().clocked_transfer() {
+            let received = slave
+                .mmu_mut()
+                .serial_mut()
+                .clock_external(sent)
+                .unwrap_or(NOT_LISTENING);
+            master.mmu_mut().serial_mut().complete_transfer(received);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
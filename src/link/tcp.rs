@@ -0,0 +1,359 @@
+//! Link cable between two processes over a TCP connection.
+//!
+//! Each process runs its own console. Emulated time is cut into quanta of the same length at
+//! both ends, and after every quantum the ends swap a sync message and wait for the other's, so
+//! neither gets more than a quantum ahead. A transfer clocked by one end reaches the other at
+//! the start of the next quantum, which is when an external-clock slave sees it complete.
+//!
+//! # Protocol
+//!
+//! All integers are little-endian. After connecting, both ends send a hello message and check
+//! the other's:
+//!
+//! | bytes | contents                               |
+//! | ----- | -------------------------------------- |
+//! | 4     | `GBLK`                                 |
+//! | 1     | protocol version, currently 1          |
+//! | 4     | quantum length in T-states, must match |
+//!
+//! Then both ends send one sync message per quantum, numbered from 0:
+//!
+//! | bytes | contents                                                            |
+//! | ----- | ------------------------------------------------------------------- |
+//! | 8     | quantum number                                                      |
+//! | 8     | emulated time at the end of the quantum, in T-states                |
+//! | 1     | 1 if the port waits for the other end to clock a transfer, else 0   |
+//! | 1     | SB, the byte such a transfer would receive                          |
+//! | 2     | number of transfers this end clocked during the quantum             |
+//! | n     | the bytes it clocked out, in order                                  |
+//!
+//! An end clocking a transfer completes it right away with the waiting byte from the other's
+//! last sync message, or `0xFF` if it wasn't waiting. That byte is only used once, and not at
+//! all if the other end receives a transfer at the same sync, as that transfer takes it. When
+//! the other end then applies the transfer, the byte it shifts out must be the one it
+//! advertised, otherwise the ends disagree on what was exchanged and the link reports a desync.
+//! A desync is also reported if the quantum numbers of the two ends ever differ, or the other
+//! end claims to have stopped short of the end of the quantum.
+use crate::{gameboy::GameBoy, serial::SerialLink};
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+const MAGIC: &[u8; 4] = b"GBLK";
+const PROTOCOL_VERSION: u8 = 1;
+/// Shifted in when the other end isn't waiting for a transfer
+const NOT_LISTENING: u8 = 0xFF;
+/// How often `TcpLink::accept` checks for a connection
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Settings both ends of a TCP link use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LinkConfig {
+    /// How long to wait for the other end before giving up with `LinkError::Timeout`
+    pub timeout: Duration,
+    /// Length of a quantum in T-states, which has to be the same at both ends and can't be 0.
+    /// Shorter quanta deliver transfers sooner but sync more often.
+    pub quantum: u32,
+}
+
+impl Default for LinkConfig {
+    /// A 5 second timeout and quanta of 512 T-states, one bit at 8192 Hz
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            quantum: 512,
+        }
+    }
+}
+
+impl LinkConfig {
+    /// Rejects settings the link can't run with.
+    const fn check(self) -> Result<(), LinkError> {
+        match self.quantum {
+            0 => Err(LinkError::ZeroQuantum),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Why a TCP link failed.
+#[derive(Debug)]
+pub enum LinkError {
+    Io(io::Error),
+    /// The other end didn't answer within the timeout
+    Timeout,
+    /// The other end closed the connection
+    Disconnected,
+    /// The other end doesn't speak this protocol, or another version of it
+    BadHandshake,
+    /// The quantum is 0 T-states long, in which the console would never run
+    ZeroQuantum,
+    /// The ends were set up with different quantum lengths
+    QuantumMismatch {
+        ours: u32,
+        theirs: u32,
+    },
+    /// The ends disagree on the emulated state of the link in this quantum
+    Desync {
+        quantum: u64,
+    },
+}
+
+impl Error for LinkError {}
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Link I/O error: {error}"),
+            Self::Timeout => write!(f, "Timed out waiting for the other end of the link"),
+            Self::Disconnected => write!(f, "The other end closed the link"),
+            Self::BadHandshake => write!(f, "The other end isn't a compatible link"),
+            Self::ZeroQuantum => write!(f, "Link quantum can't be 0 T-states"),
+            Self::QuantumMismatch { ours, theirs } => write!(
+                f,
+                "Link quantum of {ours} T-states doesn't match the other end's {theirs}"
+            ),
+            Self::Desync { quantum } => write!(f, "Link desynced in quantum {quantum}"),
+        }
+    }
+}
+
+impl From<io::Error> for LinkError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Self::Timeout,
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => Self::Disconnected,
+            _ => Self::Io(error),
+        }
+    }
+}
+
+/// Sync message sent after every quantum
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SyncMessage {
+    quantum: u64,
+    clock: u64,
+    /// SB, if the port waits for the other end to clock a transfer
+    listening: Option<u8>,
+    /// Bytes clocked out during the quantum
+    transfers: Vec<u8>,
+}
+
+impl SyncMessage {
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let count = u16::try_from(self.transfers.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "too many transfers"))?;
+        let mut message = Vec::with_capacity(20 + self.transfers.len());
+        message.extend_from_slice(&self.quantum.to_le_bytes());
+        message.extend_from_slice(&self.clock.to_le_bytes());
+        message.push(u8::from(self.listening.is_some()));
+        message.push(self.listening.unwrap_or(NOT_LISTENING));
+        message.extend_from_slice(&count.to_le_bytes());
+        message.extend_from_slice(&self.transfers);
+        writer.write_all(&message)?;
+        writer.flush()
+    }
+
+    fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 20];
+        reader.read_exact(&mut header)?;
+        let count = u16::from_le_bytes([header[18], header[19]]);
+        let mut transfers = vec![0u8; usize::from(count)];
+        reader.read_exact(&mut transfers)?;
+
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&header[0..8]);
+        let quantum = u64::from_le_bytes(word);
+        word.copy_from_slice(&header[8..16]);
+        let clock = u64::from_le_bytes(word);
+        Ok(Self {
+            quantum,
+            clock,
+            listening: match header[16] {
+                0 => None,
+                _ => Some(header[17]),
+            },
+            transfers,
+        })
+    }
+}
+
+/// One end of a link cable to another process.
+///
+/// It drives the console given to `TcpLink::run_cycles`, always the same one for the lifetime
+/// of the link.
+pub struct TcpLink {
+    stream: TcpStream,
+    config: LinkConfig,
+    /// Number of the next quantum
+    quantum: u64,
+    /// Emulated time of the console, in T-states
+    clock: u64,
+    /// Byte the other end waits to send, as of its last sync message and not yet taken
+    peer_listening: Option<u8>,
+    /// Byte the other end may take from this one during the current quantum
+    advertised: Option<u8>,
+}
+
+impl TcpLink {
+    /// Connects to the other end listening at `address`.
+    pub fn connect<A: ToSocketAddrs>(address: A, config: LinkConfig) -> Result<Self, LinkError> {
+        config.check()?;
+        let mut last_error = None;
+        for address in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, config.timeout) {
+                Ok(stream) => return Self::handshake(stream, config),
+                Err(error) => last_error = Some(error),
+            }
+        }
+        Err(last_error.map_or(LinkError::Disconnected, LinkError::from))
+    }
+
+    /// Waits up to the timeout for the other end to connect to `listener`, which is left in
+    /// blocking mode.
+    pub fn accept(listener: &TcpListener, config: LinkConfig) -> Result<Self, LinkError> {
+        config.check()?;
+        listener.set_nonblocking(true)?;
+        let start = Instant::now();
+        let accepted = loop {
+            match listener.accept() {
+                Ok((stream, _)) => break Ok(stream),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    if start.elapsed() >= config.timeout {
+                        break Err(io::Error::from(io::ErrorKind::TimedOut));
+                    }
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                }
+                Err(error) => break Err(error),
+            }
+        };
+        listener.set_nonblocking(false)?;
+
+        let stream = accepted?;
+        stream.set_nonblocking(false)?;
+        Self::handshake(stream, config)
+    }
+
+    fn handshake(mut stream: TcpStream, config: LinkConfig) -> Result<Self, LinkError> {
+        stream.set_read_timeout(Some(config.timeout))?;
+        stream.set_write_timeout(Some(config.timeout))?;
+        stream.set_nodelay(true)?;
+
+        let mut hello = [0u8; 9];
+        hello[0..4].copy_from_slice(MAGIC);
+        hello[4] = PROTOCOL_VERSION;
+        hello[5..9].copy_from_slice(&config.quantum.to_le_bytes());
+        stream.write_all(&hello)?;
+
+        let mut theirs = [0u8; 9];
+        stream.read_exact(&mut theirs)?;
+        if &theirs[0..4] != MAGIC || theirs[4] != PROTOCOL_VERSION {
+            return Err(LinkError::BadHandshake);
+        }
+        let quantum = u32::from_le_bytes([theirs[5], theirs[6], theirs[7], theirs[8]]);
+        if quantum != config.quantum {
+            return Err(LinkError::QuantumMismatch {
+                ours: config.quantum,
+                theirs: quantum,
+            });
+        }
+
+        Ok(Self {
+            stream,
+            config,
+            quantum: 0,
+            clock: 0,
+            peer_listening: None,
+            advertised: None,
+        })
+    }
+
+    #[must_use]
+    pub const fn config(&self) -> LinkConfig {
+        self.config
+    }
+
+    /// Emulated time of the console, in T-states
+    #[must_use]
+    pub const fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// Runs `gameboy` for whole quanta until it has advanced by at least `t_states` clock ticks,
+    /// syncing with the other end after each one. Plugs the link into its serial port.
+    pub fn run_cycles(&mut self, gameboy: &mut GameBoy, t_states: u64) -> Result<(), LinkError> {
+        let target = self.clock + t_states;
+        while self.clock < target {
+            self.run_quantum(gameboy)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `gameboy` to the end of the current quantum and syncs with the other end.
+    pub fn run_quantum(&mut self, gameboy: &mut GameBoy) -> Result<(), LinkError> {
+        gameboy.mmu_mut().serial_mut().set_link(SerialLink::Cable);
+
+        let end = (self.quantum + 1) * u64::from(self.config.quantum);
+        let mut transfers = Vec::new();
+        while self.clock < end {
+            self.clock += u64::from(gameboy.step());
+            let serial = gameboy.mmu_mut().serial_mut();
+            if let Some(sent) = serial.clocked_transfer() {
+                serial.complete_transfer(self.peer_listening.take().unwrap_or(NOT_LISTENING));
+                transfers.push(sent);
+            }
+        }
+
+        let serial = gameboy.mmu().serial();
+        let ours = SyncMessage {
+            quantum: self.quantum,
+            clock: self.clock,
+            listening: match serial.is_waiting_for_clock() {
+                true => Some(serial.read_register(0xFF01)),
+                false => None,
+            },
+            transfers,
+        };
+        ours.write_to(&mut self.stream)?;
+        let theirs = SyncMessage::read_from(&mut self.stream)?;
+        if theirs.quantum != self.quantum || theirs.clock < end {
+            return Err(LinkError::Desync {
+                quantum: self.quantum,
+            });
+        }
+
+        // the other end's first transfer took the byte advertised for this quantum
+        let serial = gameboy.mmu_mut().serial_mut();
+        for (index, &sent) in theirs.transfers.iter().enumerate() {
+            let own = serial.clock_external(sent);
+            if index == 0 && self.advertised.is_some() && own != self.advertised {
+                return Err(LinkError::Desync {
+                    quantum: self.quantum,
+                });
+            }
+        }
+
+        // a byte taken by a transfer arriving at this sync can't be taken again
+        self.advertised = match theirs.transfers.is_empty() {
+            true => ours.listening,
+            false => None,
+        };
+        self.peer_listening = match ours.transfers.is_empty() {
+            true => theirs.listening,
+            false => None,
+        };
+        self.quantum += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
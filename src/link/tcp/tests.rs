@@ -0,0 +1,141 @@
+use super::{LinkConfig, LinkError, SyncMessage, TcpLink};
+use crate::link::tests::{console, transfer_program};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn listener() -> TcpListener {
+    TcpListener::bind("127.0.0.1:0").unwrap()
+}
+
+fn short_timeout() -> LinkConfig {
+    LinkConfig {
+        timeout: Duration::from_millis(200),
+        ..LinkConfig::default()
+    }
+}
+
+#[test]
+fn tcp_link_exchanges_bytes() {
+    let listener = listener();
+    let address = listener.local_addr().unwrap();
+
+    let slave = thread::spawn(move || {
+        let mut gameboy = console(&transfer_program(0x34, 0x80));
+        let mut link = TcpLink::accept(&listener, LinkConfig::default()).unwrap();
+        link.run_cycles(&mut gameboy, 8 * 512 + 2048).unwrap();
+        gameboy.mmu().read_byte(0xFF80)
+    });
+
+    let mut gameboy = console(&transfer_program(0x12, 0x81));
+    let mut link = TcpLink::connect(address, LinkConfig::default()).unwrap();
+    link.run_cycles(&mut gameboy, 8 * 512 + 2048).unwrap();
+
+    assert_eq!(0x34, gameboy.mmu().read_byte(0xFF80));
+    assert_eq!(0x12, slave.join().unwrap());
+}
+
+#[test]
+fn tcp_link_quantum_mismatch() {
+    let listener = listener();
+    let address = listener.local_addr().unwrap();
+
+    let other = thread::spawn(move || {
+        let config = LinkConfig {
+            quantum: 1024,
+            ..short_timeout()
+        };
+        TcpLink::accept(&listener, config).err()
+    });
+
+    match TcpLink::connect(address, short_timeout()) {
+        Err(LinkError::QuantumMismatch { ours, theirs }) => {
+            assert_eq!((512, 1024), (ours, theirs));
+        }
+        _ => panic!("quantum mismatch not detected"),
+    }
+    assert!(other.join().unwrap().is_some());
+}
+
+/// Answers the handshake, then sends `sync` if there's one and keeps the connection open
+/// until joined.
+fn fake_peer(listener: TcpListener, sync: Option<SyncMessage>) -> thread::JoinHandle<TcpStream> {
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut hello = [0u8; 9];
+        stream.read_exact(&mut hello).unwrap();
+        stream.write_all(&hello).unwrap();
+        if let Some(sync) = sync {
+            sync.write_to(&mut stream).unwrap();
+        }
+        stream
+    })
+}
+
+#[test]
+fn tcp_link_timeout() {
+    let listener = listener();
+    let address = listener.local_addr().unwrap();
+    let peer = fake_peer(listener, None);
+
+    let mut gameboy = console(&transfer_program(0x12, 0x81));
+    let mut link = TcpLink::connect(address, short_timeout()).unwrap();
+    assert!(matches!(
+        link.run_quantum(&mut gameboy),
+        Err(LinkError::Timeout)
+    ));
+    peer.join().unwrap();
+}
+
+#[test]
+fn tcp_link_accept_timeout() {
+    let listener = listener();
+    let start = Instant::now();
+    assert!(matches!(
+        TcpLink::accept(&listener, short_timeout()),
+        Err(LinkError::Timeout)
+    ));
+    assert!(start.elapsed() >= short_timeout().timeout);
+}
+
+#[test]
+fn tcp_link_zero_quantum() {
+    let listener = listener();
+    let config = LinkConfig {
+        quantum: 0,
+        ..short_timeout()
+    };
+    assert!(matches!(
+        TcpLink::connect(listener.local_addr().unwrap(), config),
+        Err(LinkError::ZeroQuantum)
+    ));
+    assert!(matches!(
+        TcpLink::accept(&listener, config),
+        Err(LinkError::ZeroQuantum)
+    ));
+}
+
+#[test]
+fn tcp_link_desync() {
+    let listener = listener();
+    let address = listener.local_addr().unwrap();
+    // skips quantum 0
+    let skipped = SyncMessage {
+        quantum: 1,
+        clock: 1024,
+        listening: None,
+        transfers: Vec::new(),
+    };
+    let peer = fake_peer(listener, Some(skipped));
+
+    let mut gameboy = console(&transfer_program(0x12, 0x81));
+    let mut link = TcpLink::connect(address, short_timeout()).unwrap();
+    assert!(matches!(
+        link.run_quantum(&mut gameboy),
+        Err(LinkError::Desync { quantum: 0 })
+    ));
+    peer.join().unwrap();
+}
@@ -0,0 +1,60 @@
+use super::LinkCable;
+use crate::{gameboy::GameBoy, interrupts::Interrupt};
+
+/// Sends `value` with SC set to `control`, waits for the transfer to end and stores the
+/// byte received in `0xFF80`.
+pub(super) fn transfer_program(value: u8, control: u8) -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = vec![
+        0x3E, value,   // LD A, value
+        0xE0, 0x01,    // LDH (SB), A
+        0x3E, control, // LD A, control
+        0xE0, 0x02,    // LDH (SC), A
+        0xF0, 0x02,    // LDH A, (SC)
+        0xCB, 0x7F,    // BIT 7, A
+        0x20, 0xFA,    // JR NZ, -6
+        0xF0, 0x01,    // LDH A, (SB)
+        0xE0, 0x80,    // LDH (0xFF80), A
+        0x18, 0xFE,    // JR -2
+    ];
+    program
+}
+
+/// A console running `program` from the cartridge entry point
+pub(super) fn console(program: &[u8]) -> GameBoy {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    GameBoy::new(rom).unwrap()
+}
+
+#[test]
+fn link_cable_exchanges_bytes() {
+    let master = console(&transfer_program(0x12, 0x81));
+    let slave = console(&transfer_program(0x34, 0x80));
+    let mut cable = LinkCable::new(master, slave);
+
+    // eight bits at 8192 Hz, and then some
+    cable.run_cycles(8 * 512 + 100);
+    for index in 0..2 {
+        let mmu = cable.console(index).mmu();
+        assert_ne!(0, mmu.read_byte(0xFF0F) & Interrupt::Serial.bit());
+    }
+    assert_eq!(0x34, cable.console(0).mmu().read_byte(0xFF80));
+    assert_eq!(0x12, cable.console(1).mmu().read_byte(0xFF80));
+
+    let difference = cable.clock(0).abs_diff(cable.clock(1));
+    assert!(difference <= 24, "consoles {} T-states apart", difference);
+}
+
+#[test]
+fn link_cable_without_listener() {
+    // both consoles clock their own transfers, so neither listens
+    let first = console(&transfer_program(0x12, 0x81));
+    let second = console(&transfer_program(0x34, 0x81));
+    let mut cable = LinkCable::new(first, second);
+
+    cable.run_cycles(8 * 512 + 100);
+    let (first, second) = cable.into_consoles();
+    assert_eq!(0xFF, first.mmu().read_byte(0xFF80));
+    assert_eq!(0xFF, second.mmu().read_byte(0xFF80));
+}
@@ -1,5 +1,78 @@
-use rusty_gb::cpu::Cpu;
+//! Runs a ROM without a display for a number of frames, optionally recording what it plays.
+use rusty_gb::{apu::HighPass, gameboy::GameBoy};
+use std::{env, error::Error, fs, process};
+
+const USAGE: &str = "Usage: rusty-gb <rom> [--frames <count>] [--record-wav <path>]";
+/// About ten seconds
+const DEFAULT_FRAMES: u32 = 600;
+const WAV_SAMPLE_RATE: u32 = 44_100;
+
+struct Options {
+    rom: String,
+    frames: u32,
+    record_wav: Option<String>,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut record_wav = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                let count = args.next().ok_or("--frames needs a count")?;
+                frames = count
+                    .parse()
+                    .map_err(|_| format!("Invalid frame count {count}"))?;
+            }
+            "--record-wav" => record_wav = Some(args.next().ok_or("--record-wav needs a path")?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ if rom.is_none() => rom = Some(arg),
+            _ => return Err(format!("Unexpected argument {arg}")),
+        }
+    }
+
+    Ok(Options {
+        rom: rom.ok_or("No ROM given")?,
+        frames,
+        record_wav,
+    })
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(&options.rom)?;
+    let mut gameboy = GameBoy::new(rom)?;
+    if options.record_wav.is_some() {
+        gameboy
+            .mmu_mut()
+            .apu_mut()
+            .start_recording(WAV_SAMPLE_RATE, HighPass::Dmg);
+    }
+
+    for _ in 0..options.frames {
+        gameboy.run_frame();
+    }
+
+    if let Some(path) = &options.record_wav {
+        if let Some(recording) = gameboy.mmu_mut().apu_mut().stop_recording() {
+            recording.save(path)?;
+        }
+    }
+    Ok(())
+}
 
 fn main() {
-    let _ = Cpu::new();
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    if let Err(error) = run(&options) {
+        eprintln!("{error}");
+        process::exit(1);
+    }
 }
@@ -1,37 +1,273 @@
-use crate::utils::{bytes_to_word, word_to_bytes};
-use std::boxed::Box;
+use crate::{
+    apu::Apu,
+    bus::Bus,
+    joypad::{Button, Joypad, JoypadState},
+    ppu::{Mode, Ppu, RendererKind},
+    savestate::{SaveState, SaveStateError, StateWriter},
+    scheduler::{Event, Scheduler},
+    serial::Serial,
+    timer::Timer,
+    utils::{bytes_to_word, word_to_bytes},
+};
+use std::{boxed::Box, convert::TryFrom};
 
 // TODO implement memory
-//  * working RAM (WRAM)
-//  * video RAM (VRAM)
-//  * memory-mapped
 //  * memory write modes 1 & 2
+mod dma;
 mod mbc;
 
-const WRAM_SIZE: usize = 0x1FFF;
-const REGS_SIZE: usize = 0x7F;
+const WRAM_SIZE: usize = 0x2000;
+const HRAM_SIZE: usize = 0x7F;
+const REGS_SIZE: usize = 0x80;
 
 pub struct Mmu {
     wram: Vec<u8>,
-    #[allow(unused)]
+    hram: Vec<u8>,
+    /// Backing store for I/O registers without a peripheral behind them
     io_registers: Vec<u8>,
-    interrupt_enable: bool,
+    /// IE, `0xFFFF`
+    interrupt_enable: u8,
+    /// IF, `0xFF0F`
+    interrupt_flag: u8,
     mbc: Box<dyn mbc::MemoryBankController + 'static>,
+    ppu: Ppu,
+    dma: dma::Dma,
+    timer: Timer,
+    joypad: Joypad,
+    apu: Apu,
+    serial: Serial,
+    /// When the peripherals next do something, so the time in between can be skipped over
+    scheduler: Scheduler,
+    /// Set when a register write or the host may have moved an event, so the deadlines are
+    /// refreshed before time moves on
+    reschedule: bool,
+    /// Whether the CPU is locked out of VRAM and OAM while the PPU is using them
+    access_locking: bool,
 }
 
 impl Mmu {
     /// initializes memory sections
     pub fn new() -> Mmu {
-        Mmu {
+        Mmu::with_renderer(RendererKind::Scanline)
+    }
+
+    /// initializes memory sections, with the PPU using the given renderer
+    #[must_use]
+    pub fn with_renderer(renderer: RendererKind) -> Self {
+        Self {
             wram: vec![0; WRAM_SIZE],
+            hram: vec![0; HRAM_SIZE],
             io_registers: vec![0; REGS_SIZE],
-            interrupt_enable: false,
+            interrupt_enable: 0,
+            interrupt_flag: 0,
             mbc: Box::new(mbc::MbcNone::new()),
+            ppu: Ppu::new(renderer),
+            dma: dma::Dma::new(),
+            timer: Timer::new(),
+            joypad: Joypad::new(),
+            apu: Apu::new(),
+            serial: Serial::new(),
+            scheduler: Scheduler::new(),
+            reschedule: true,
+            access_locking: true,
+        }
+    }
+
+    /// Enables or disables the PPU's VRAM and OAM locks, which are on by default.
+    ///
+    /// As on hardware, while they are on the CPU reads `0xFF` and its writes are dropped for
+    /// VRAM in mode 3 and OAM in modes 2 and 3. Turning them off is useful for debugging.
+    pub const fn set_access_locking(&mut self, enabled: bool) {
+        self.access_locking = enabled;
+    }
+
+    /// Whether the PPU's VRAM and OAM locks are on
+    #[must_use]
+    pub const fn access_locking(&self) -> bool {
+        self.access_locking
+    }
+
+    /// The picture processing unit
+    #[must_use]
+    pub const fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    /// The audio processing unit
+    #[must_use]
+    pub const fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    /// The audio processing unit, to set up and read its audio output
+    pub const fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    /// The serial port
+    #[must_use]
+    pub const fn serial(&self) -> &Serial {
+        &self.serial
+    }
+
+    /// The serial port, to plug something into it
+    pub const fn serial_mut(&mut self) -> &mut Serial {
+        self.reschedule = true;
+        &mut self.serial
+    }
+
+    /// The timer and divider
+    #[must_use]
+    pub const fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    /// The joypad
+    #[must_use]
+    pub const fn joypad(&self) -> &Joypad {
+        &self.joypad
+    }
+
+    /// Replaces the held buttons with `state`, requesting the joypad interrupt if that pulls
+    /// a selected line of P1 low.
+    pub fn set_joypad(&mut self, state: JoypadState) {
+        self.interrupt_flag |= self.joypad.set_state(state);
+    }
+
+    /// Presses `button`, keeping the others as they are.
+    pub fn press(&mut self, button: Button) {
+        self.set_joypad(self.joypad.state().with(button));
+    }
+
+    /// Releases `button`, keeping the others as they are.
+    pub fn release(&mut self, button: Button) {
+        self.set_joypad(self.joypad.state().without(button));
+    }
+
+    /// T-states the peripherals have been ticked since power on
+    #[must_use]
+    pub const fn clock(&self) -> u64 {
+        self.scheduler.now()
+    }
+
+    /// The next event on the bus and the T-state it's due in, if any peripheral is waiting for
+    /// one. Frame sequencer steps are always scheduled.
+    pub fn next_event(&mut self) -> Option<(Event, u64)> {
+        self.refresh_events();
+        self.scheduler.next()
+    }
+
+    /// Advances the peripherals on the bus by `t_states` clock ticks, latching any interrupts
+    /// they request into IF.
+    ///
+    /// Up to the next event the peripherals only count time, so they are advanced over it in
+    /// one go. Only the T-state of the event itself is run in full.
+    pub fn tick(&mut self, t_states: u32) {
+        self.refresh_events();
+
+        let mut remaining = t_states;
+        while remaining > 0 {
+            let span = self
+                .scheduler
+                .until_next()
+                .map_or(remaining, |until| {
+                    u32::try_from(until).map_or(remaining, |until| until.min(remaining))
+                })
+                .max(1);
+            self.tick_quiet(span - 1);
+            self.tick_event();
+            self.scheduler.advance(u64::from(span));
+            self.schedule_events();
+            remaining -= span;
+        }
+    }
+
+    /// Advances the peripherals by `t_states` clock ticks in which no event is due.
+    fn tick_quiet(&mut self, t_states: u32) {
+        if t_states == 0 {
+            return;
+        }
+
+        self.interrupt_flag |= self.ppu.tick(t_states);
+        self.interrupt_flag |= self.timer.tick(t_states);
+        self.apu.clock_div(self.timer.counter());
+        self.apu.tick(t_states);
+        self.interrupt_flag |= self.serial.tick(t_states);
+    }
+
+    /// Advances the peripherals by the single clock tick in which an event is due.
+    fn tick_event(&mut self) {
+        if let Some((source, offset)) = self.dma.tick() {
+            let value = self.read_bus(source);
+            self.ppu.write_oam(0xFE00 + offset, value);
+        }
+        self.interrupt_flag |= self.ppu.tick(1);
+        self.interrupt_flag |= self.timer.tick(1);
+        self.apu.clock_div(self.timer.counter());
+        self.apu.tick(1);
+        self.interrupt_flag |= self.serial.tick(1);
+    }
+
+    fn refresh_events(&mut self) {
+        if std::mem::take(&mut self.reschedule) {
+            self.schedule_events();
+        }
+    }
+
+    /// Asks every peripheral when it next does something.
+    fn schedule_events(&mut self) {
+        let dma = match self.dma.is_busy() {
+            true => Some(1),
+            false => None,
+        };
+        let events = [
+            (Event::Timer, self.timer.next_event()),
+            (Event::Ppu, self.ppu.next_event()),
+            (
+                Event::FrameSequencer,
+                Some(Apu::next_frame_sequencer_step(self.timer.counter())),
+            ),
+            (Event::Serial, self.serial.next_event()),
+            (Event::Dma, dma),
+        ];
+
+        let now = self.scheduler.now();
+        for &(event, until) in &events {
+            match until {
+                Some(until) => self.scheduler.schedule(event, now + u64::from(until)),
+                None => self.scheduler.cancel(event),
+            }
         }
     }
 
-    /// TODO: reads a byte from the memory-mapped bus
+    /// Reads a byte from the memory-mapped bus as seen by the CPU.
+    ///
+    /// During OAM DMA only `0xFF00..=0xFFFF` is reachable, other reads return the byte the
+    /// DMA controller is copying. VRAM and OAM read `0xFF` while the PPU has them locked, see
+    /// `Mmu::set_access_locking`.
     pub fn read_byte(&self, address: u16) -> u8 {
+        match self.dma.bus_address() {
+            Some(source) if address < 0xFF00 => self.read_bus(source),
+            _ if self.is_locked(address) => 0xFF,
+            _ => self.read_bus(address),
+        }
+    }
+
+    /// Whether `address` is in VRAM or OAM while the PPU has locked it.
+    fn is_locked(&self, address: u16) -> bool {
+        if !self.access_locking {
+            return false;
+        }
+
+        match address {
+            0x8000..=0x9FFF => self.ppu.mode() == Mode::Drawing,
+            0xFE00..=0xFE9F => matches!(self.ppu.mode(), Mode::OamScan | Mode::Drawing),
+            _ => false,
+        }
+    }
+
+    /// Reads a byte from the memory-mapped bus, regardless of who owns it.
+    fn read_bus(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x7FFF => {
                 // fixed ROM
@@ -40,35 +276,53 @@ impl Mmu {
                     Err(_) => panic!("Cartirdge ROM read error."), // handle this?
                 }
             }
-            0x8000..=0x9FFF => {
-                unimplemented!("VRAM read");
-            }
-            0xA000..=0xBFFF => {
-                unimplemented!("Cartridge RAM read");
-            }
+            0x8000..=0x9FFF => self.ppu.read_vram(address),
+            0xA000..=0xBFFF => self.mbc.read_ram(address),
             0xC000..=0xDFFF => {
                 // WRAM read
                 // TODO: test
                 self.wram[address as usize - 0xC000]
             }
-            0xE000..=0xFDFF => {
-                // echos all WRAM r/w up to 0xDDFF
-                unimplemented!("Echo RAM read");
-            }
-            0xFE00..=0xFE9F => {
-                unimplemented!("Sprite attribute table read");
-            }
+            // echos all WRAM r/w up to 0xDDFF
+            0xE000..=0xFDFF => self.wram[usize::from(address - 0xE000)],
+            0xFE00..=0xFE9F => self.ppu.read_oam(address),
             0xFEA0..=0xFEFF => 0, // unused memory area, returns 0
-            0xFF00..=0xFF7F => {
-                unimplemented!("I/O registers read");
-            }
-            0xFF80..=0xFFFE => {
-                unimplemented!("High RAM read");
+            0xFF00..=0xFF7F => self.read_io(address),
+            0xFF80..=0xFFFE => self.hram[usize::from(address - 0xFF80)],
+            0xFFFF => self.interrupt_enable,
+        }
+    }
+
+    /// Reads one of the I/O registers in `0xFF00..=0xFF7F`
+    fn read_io(&self, address: u16) -> u8 {
+        match address {
+            0xFF00 => self.joypad.read_register(),
+            0xFF01..=0xFF02 => self.serial.read_register(address),
+            0xFF04..=0xFF07 => self.timer.read_register(address),
+            0xFF0F => 0b_1110_0000 | self.interrupt_flag,
+            0xFF10..=0xFF3F => self.apu.read_register(address),
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.read_register(address),
+            0xFF46 => self.dma.register(),
+            _ => self.io_registers[usize::from(address - 0xFF00)],
+        }
+    }
+
+    /// Writes one of the I/O registers in `0xFF00..=0xFF7F`
+    fn write_io(&mut self, address: u16, value: u8) {
+        self.reschedule = true;
+        match address {
+            0xFF00 => self.interrupt_flag |= self.joypad.write_register(value),
+            0xFF01..=0xFF02 => self.serial.write_register(address, value),
+            0xFF04..=0xFF07 => {
+                self.timer.write_register(address, value);
+                // resetting DIV can clock the frame sequencer
+                self.apu.clock_div(self.timer.counter());
             }
-            0xFFFF => match self.interrupt_enable {
-                true => 1,
-                false => 0,
-            },
+            0xFF0F => self.interrupt_flag = value & 0b_0001_1111,
+            0xFF10..=0xFF3F => self.apu.write_register(address, value),
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.write_register(address, value),
+            0xFF46 => self.dma.start(value),
+            _ => self.io_registers[usize::from(address - 0xFF00)] = value,
         }
     }
 
@@ -81,19 +335,31 @@ impl Mmu {
 
     // TODO: writes a byte to the memory-mapped bus
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        if self.dma.is_active() && address < 0xFF00 {
+            return; // the DMA controller owns the bus
+        }
+        if self.is_locked(address) {
+            return;
+        }
+
         match address {
             0x0000..=0x7FFF => {
-                // fixed ROM
+                // MBC registers, the ROM itself being read-only
                 self.mbc
                     .write_byte(address, value)
                     .expect("memory write in valid range");
             }
+            0x8000..=0x9FFF => self.ppu.write_vram(address, value),
+            0xA000..=0xBFFF => self.mbc.write_ram(address, value),
             0xC000..=0xDFFF => {
                 self.wram[address as usize - 0xC000] = value;
             }
-            _ => {
-                unimplemented!("Memory write")
-            }
+            0xE000..=0xFDFF => self.wram[usize::from(address - 0xE000)] = value,
+            0xFE00..=0xFE9F => self.ppu.write_oam(address, value),
+            0xFEA0..=0xFEFF => {} // unused memory area
+            0xFF00..=0xFF7F => self.write_io(address, value),
+            0xFF80..=0xFFFE => self.hram[usize::from(address - 0xFF80)] = value,
+            0xFFFF => self.interrupt_enable = value,
         };
     }
 
@@ -104,16 +370,86 @@ impl Mmu {
         self.write_byte(address + 1, high);
     }
 
-    /// TODO: load game program to ROM
+    /// Inserts the synthetic cartridge of a GBS rip, holding `rom` in 16 KiB banks switched by
+    /// writes to `0x2000..=0x3FFF`, along with 8 KiB of RAM.
+    pub(crate) fn insert_gbs_cartridge(&mut self, rom: Vec<u8>) {
+        self.mbc = Box::new(mbc::MbcGbs::new(rom));
+    }
+
+    /// Saves the memory, the cartridge and every peripheral, one section each.
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.section("MMU ", |state| {
+            state.bytes(&self.wram);
+            state.bytes(&self.hram);
+            state.bytes(&self.io_registers);
+            state.u8(self.interrupt_enable);
+            state.u8(self.interrupt_flag);
+            state.bool(self.reschedule);
+        });
+        state.section("CART", |state| self.mbc.save_state(state));
+        state.section("PPU ", |state| self.ppu.save_state(state));
+        state.section("DMA ", |state| self.dma.save_state(state));
+        state.section("TIMR", |state| self.timer.save_state(state));
+        state.section("JOYP", |state| self.joypad.save_state(state));
+        state.section("APU ", |state| self.apu.save_state(state));
+        state.section("SERL", |state| self.serial.save_state(state));
+        state.section("SCHD", |state| self.scheduler.save_state(state));
+    }
+
+    /// Restores what `Mmu::save_state` saved.
+    pub(crate) fn load_state(&mut self, state: &SaveState<'_>) -> Result<(), SaveStateError> {
+        let mut section = state.section("MMU ")?;
+        section.read_into(&mut self.wram)?;
+        section.read_into(&mut self.hram)?;
+        section.read_into(&mut self.io_registers)?;
+        self.interrupt_enable = section.u8()?;
+        self.interrupt_flag = section.u8()? & 0b_0001_1111;
+        self.reschedule = section.bool()?;
+
+        self.mbc.load_state(&mut state.section("CART")?)?;
+        self.ppu.load_state(&mut state.section("PPU ")?)?;
+        self.dma.load_state(&mut state.section("DMA ")?)?;
+        self.timer.load_state(&mut state.section("TIMR")?)?;
+        self.joypad.load_state(&mut state.section("JOYP")?)?;
+        self.apu.load_state(&mut state.section("APU ")?)?;
+        self.serial.load_state(&mut state.section("SERL")?)?;
+        self.scheduler.load_state(&mut state.section("SCHD")?)
+    }
+
+    /// Inserts a cartridge without MBC holding `data`.
+    ///
+    /// # Panics
+    /// If `data` doesn't fit in the 32 KiB of such a cartridge.
     pub fn load_rom(&mut self, data: Vec<u8>) -> () {
-        for (i, instr) in data.into_iter().enumerate() {
-            // TODO: handle his error
-            if let Err(_) = self.mbc.write_byte(i as u16, instr) {
-                panic!("ROM loading error! Illegal write to ROM address 0x{:X}", i);
-            }
+        match mbc::MbcNone::with_rom(&data) {
+            Ok(mbc) => self.mbc = Box::new(mbc),
+            Err(_) => panic!("ROM loading error! ROM of {} bytes too large", data.len()),
         }
     }
 }
 
+impl Bus for Mmu {
+    fn read(&mut self, address: u16) -> u8 {
+        self.read_byte(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.write_byte(address, value);
+    }
+
+    fn tick(&mut self, t_states: u32) {
+        Self::tick(self, t_states);
+    }
+
+    fn until_next_event(&mut self) -> Option<u64> {
+        let now = self.clock();
+        self.next_event().map(|(_, at)| at - now)
+    }
+
+    fn wakes_from_stop(&self) -> bool {
+        self.joypad.any_line_low()
+    }
+}
+
 #[cfg(test)]
 mod tests;
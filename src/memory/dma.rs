@@ -0,0 +1,161 @@
+//! OAM DMA. Writing page `XX` to `0xFF46` copies `0xXX00..=0xXX9F` into OAM, one byte per
+//! M-cycle. While a transfer runs the DMA controller owns the bus, so the CPU can only reach the
+//! registers and HRAM at `0xFF00..=0xFFFF`.
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+/// Bytes copied by one transfer, filling OAM
+const TRANSFER_LENGTH: u16 = 0xA0;
+/// One byte is copied every M-cycle
+const T_STATES_PER_BYTE: u8 = 4;
+/// A transfer takes over the bus one M-cycle after the write to `0xFF46`
+const STARTUP_T_STATES: u8 = 4;
+
+/// A transfer copying bytes into OAM.
+#[derive(Debug, Copy, Clone)]
+struct Transfer {
+    source: u16,
+    /// Next byte to copy, `0..TRANSFER_LENGTH`
+    index: u16,
+    /// T-states into the current M-cycle
+    ticks: u8,
+}
+
+/// A transfer requested by a write to `0xFF46`, waiting out its startup delay.
+#[derive(Debug, Copy, Clone)]
+struct Request {
+    source: u16,
+    ticks: u8,
+}
+
+/// OAM DMA controller
+#[derive(Debug, Default)]
+pub(super) struct Dma {
+    /// Last value written to `0xFF46`
+    register: u8,
+    transfer: Option<Transfer>,
+    /// A new request does not stop a running transfer until its startup delay is over
+    request: Option<Request>,
+}
+
+impl Dma {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Value of the `0xFF46` register
+    pub(super) const fn register(&self) -> u8 {
+        self.register
+    }
+
+    /// Requests a transfer from page `value`, restarting any transfer in progress.
+    pub(super) fn start(&mut self, value: u8) {
+        self.register = value;
+        self.request = Some(Request {
+            source: u16::from(value) << 8,
+            ticks: 0,
+        });
+    }
+
+    /// Whether a transfer currently owns the bus
+    pub(super) const fn is_active(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    /// Whether a transfer is running or waiting out its startup delay
+    pub(super) const fn is_busy(&self) -> bool {
+        self.transfer.is_some() || self.request.is_some()
+    }
+
+    /// Source address of the byte being copied in the current M-cycle, if a transfer is active.
+    /// CPU reads outside `0xFF00..=0xFFFF` see this byte instead of the one they asked for.
+    pub(super) const fn bus_address(&self) -> Option<u16> {
+        match &self.transfer {
+            Some(transfer) => Some(source_address(transfer.source + transfer.index)),
+            None => None,
+        }
+    }
+
+    /// Advances the controller by one T-state.
+    ///
+    /// # Return value
+    /// `Some((source, offset))` when a byte should be copied from bus address `source` to
+    /// `0xFE00 + offset` in this T-state.
+    pub(super) const fn tick(&mut self) -> Option<(u16, u16)> {
+        let mut copy = None;
+
+        if let Some(transfer) = &mut self.transfer {
+            transfer.ticks += 1;
+            if transfer.ticks == T_STATES_PER_BYTE {
+                transfer.ticks = 0;
+                copy = Some((
+                    source_address(transfer.source + transfer.index),
+                    transfer.index,
+                ));
+                transfer.index += 1;
+                if transfer.index == TRANSFER_LENGTH {
+                    self.transfer = None;
+                }
+            }
+        }
+
+        if let Some(request) = &mut self.request {
+            request.ticks += 1;
+            if request.ticks == STARTUP_T_STATES {
+                self.transfer = Some(Transfer {
+                    source: request.source,
+                    index: 0,
+                    ticks: 0,
+                });
+                self.request = None;
+            }
+        }
+
+        copy
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.register);
+        state.bool(self.transfer.is_some());
+        if let Some(transfer) = &self.transfer {
+            state.u16(transfer.source);
+            state.u16(transfer.index);
+            state.u8(transfer.ticks);
+        }
+        state.bool(self.request.is_some());
+        if let Some(request) = &self.request {
+            state.u16(request.source);
+            state.u8(request.ticks);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.register = state.u8()?;
+        self.transfer = match state.bool()? {
+            true => Some(Transfer {
+                source: state.u16()?,
+                index: state.u16()?,
+                ticks: state.u8()?,
+            }),
+            false => None,
+        };
+        self.request = match state.bool()? {
+            true => Some(Request {
+                source: state.u16()?,
+                ticks: state.u8()?,
+            }),
+            false => None,
+        };
+        match self.transfer {
+            Some(transfer) if transfer.index >= TRANSFER_LENGTH => Err(state.corrupt()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Sources from `0xE000` upwards read the WRAM behind echo RAM.
+const fn source_address(address: u16) -> u16 {
+    match address {
+        0xE000..=0xFFFF => address - 0x2000,
+        _ => address,
+    }
+}
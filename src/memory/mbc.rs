@@ -1,8 +1,11 @@
 // library imports
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
 use std::{error::Error, fmt};
 // module imports
+pub mod mbc_gbs;
 pub mod mbc_none;
 // export MBC types to other modules at this level
+pub use mbc_gbs::MbcGbs;
 pub use mbc_none::MbcNone;
 
 /// Trait for memory bank controllers (MBCs). All MBCs should have the same interface provided through this trait.
@@ -12,6 +15,16 @@ pub trait MemoryBankController {
     fn read_byte(&self, address: u16) -> Result<u8, MBCError>;
     ///
     fn write_byte(&mut self, address: u16, value: u8) -> Result<(), MBCError>;
+    /// Reads a byte of cartridge RAM, `0xA000..=0xBFFF`. Reads `0xFF` without RAM.
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xFF
+    }
+    /// Writes a byte of cartridge RAM, `0xA000..=0xBFFF`. Ignored without RAM.
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+    /// Saves whatever the cartridge can change: RAM, bank registers and the like.
+    fn save_state(&self, state: &mut StateWriter);
+    /// Restores what `save_state` saved.
+    fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError>;
 }
 
 #[derive(Debug, Clone)]
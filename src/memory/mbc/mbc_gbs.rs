@@ -0,0 +1,86 @@
+//! Synthetic cartridge for GBS rips: 16 KiB ROM banks, the one mapped at `0x4000..=0x7FFF`
+//! being selected by writes to `0x2000..=0x3FFF` as on MBC1, and 8 KiB of RAM.
+
+use super::{MBCError, MemoryBankController};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use std::convert::TryFrom;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_SIZE: usize = 0x2000;
+
+pub struct MbcGbs {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    /// Bank mapped at `0x4000..=0x7FFF`
+    bank: usize,
+}
+
+impl MbcGbs {
+    /// Creates a cartridge holding `rom`, padded to a whole number of banks.
+    #[must_use]
+    pub fn new(mut rom: Vec<u8>) -> Self {
+        let banks = rom.len().div_ceil(ROM_BANK_SIZE).max(2);
+        rom.resize(banks * ROM_BANK_SIZE, 0xFF);
+        Self {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            bank: 1,
+        }
+    }
+
+    const fn banks(&self) -> usize {
+        self.rom.len() / ROM_BANK_SIZE
+    }
+}
+
+impl MemoryBankController for MbcGbs {
+    fn read_byte(&self, address: u16) -> Result<u8, MBCError> {
+        let address = usize::from(address);
+        match address {
+            0x0000..=0x3FFF => Ok(self.rom[address]),
+            0x4000..=0x7FFF => Ok(self.rom[self.bank * ROM_BANK_SIZE + address - ROM_BANK_SIZE]),
+            _ => Err(MBCError::ROMAccessOutOfRange),
+        }
+    }
+
+    /// Selects the bank mapped at `0x4000..=0x7FFF`, wrapped to the banks the ROM has, bank 0
+    /// selecting bank 1. Other writes are ignored.
+    fn write_byte(&mut self, address: u16, value: u8) -> Result<(), MBCError> {
+        match address {
+            0x2000..=0x3FFF => {
+                self.bank = match usize::from(value) % self.banks() {
+                    0 => 1,
+                    bank => bank,
+                };
+                Ok(())
+            }
+            0x0000..=0x7FFF => Ok(()),
+            _ => Err(MBCError::ROMAccessOutOfRange),
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.ram[usize::from(address - 0xA000)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        self.ram[usize::from(address - 0xA000)] = value;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.ram);
+        state.u16(u16::try_from(self.bank).expect("bank number fits in 16 bits"));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        state.read_into(&mut self.ram)?;
+        self.bank = usize::from(state.u16()?);
+        match (1..self.banks()).contains(&self.bank) {
+            true => Ok(()),
+            false => Err(state.corrupt()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
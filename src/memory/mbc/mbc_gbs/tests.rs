@@ -0,0 +1,47 @@
+use super::*;
+use crate::savestate::SaveState;
+
+#[test]
+fn mbc_gbs_bank_switching() {
+    let mut rom = vec![0; 3 * ROM_BANK_SIZE];
+    rom[0x0000] = 0xA0;
+    rom[ROM_BANK_SIZE] = 0xA1;
+    rom[2 * ROM_BANK_SIZE] = 0xA2;
+    let mut mbc = MbcGbs::new(rom);
+
+    assert_eq!(0xA0, mbc.read_byte(0x0000).unwrap());
+    assert_eq!(0xA1, mbc.read_byte(0x4000).unwrap());
+    mbc.write_byte(0x2000, 2).unwrap();
+    assert_eq!(0xA2, mbc.read_byte(0x4000).unwrap());
+    // bank 0 can't be mapped twice
+    mbc.write_byte(0x3FFF, 0).unwrap();
+    assert_eq!(0xA1, mbc.read_byte(0x4000).unwrap());
+    // nor by a number wrapping around to it
+    mbc.write_byte(0x2000, 2).unwrap();
+    mbc.write_byte(0x2000, 3).unwrap();
+    assert_eq!(0xA1, mbc.read_byte(0x4000).unwrap());
+    // other writes leave the ROM alone
+    mbc.write_byte(0x0000, 0x55).unwrap();
+    assert_eq!(0xA0, mbc.read_byte(0x0000).unwrap());
+
+    mbc.write_ram(0xBFFF, 0x42);
+    assert_eq!(0x42, mbc.read_ram(0xBFFF));
+}
+
+#[test]
+fn mbc_gbs_state_round_trips_every_bank() {
+    let mut mbc = MbcGbs::new(vec![0; 4 * ROM_BANK_SIZE]);
+    for value in 0..=0xFF {
+        mbc.write_byte(0x2000, value).unwrap();
+        let mut writer = StateWriter::new(0);
+        writer.section("CART", |state| mbc.save_state(state));
+        let bytes = writer.finish();
+        let state = SaveState::parse(&bytes).unwrap();
+
+        let mut loaded = MbcGbs::new(vec![0; 4 * ROM_BANK_SIZE]);
+        loaded
+            .load_state(&mut state.section("CART").unwrap())
+            .unwrap();
+        assert_eq!(mbc.bank, loaded.bank, "{value:02X}");
+    }
+}
@@ -1,13 +1,12 @@
 //! No MBC in cartrige. This provides direct rom access to ROM addresses
-//! 0 through 0x7FFF for a total of 8 KB.
+//! 0 through 0x7FFF for a total of 32 KiB.
 
 // TODO:
-//  * tests for MbcNone reading and writing. Need to reference documentation
 //  * MBC RAM
 
 use super::*;
 
-const ROM_SIZE_MBC_NONE: usize = 0x7FFF;
+const ROM_SIZE_MBC_NONE: usize = 0x8000;
 
 pub struct MbcNone {
     rom: [u8; ROM_SIZE_MBC_NONE],
@@ -19,6 +18,16 @@ impl MbcNone {
             rom: [0; ROM_SIZE_MBC_NONE],
         }
     }
+
+    /// Creates a cartridge holding `rom`, the rest of its 32 KiB left zeroed.
+    pub fn with_rom(rom: &[u8]) -> Result<Self, MBCError> {
+        let mut mbc = Self::new();
+        mbc.rom
+            .get_mut(..rom.len())
+            .ok_or(MBCError::ROMAccessOutOfRange)?
+            .copy_from_slice(rom);
+        Ok(mbc)
+    }
 }
 
 impl MemoryBankController for MbcNone {
@@ -34,18 +43,24 @@ impl MemoryBankController for MbcNone {
         Ok(self.rom[address])
     }
 
-    /// Write a byte to the cartridge ROM
-    fn write_byte(&mut self, address: u16, value: u8) -> Result<(), MBCError> {
+    /// Writes to the cartridge ROM are ignored, there being no registers to write to
+    fn write_byte(&mut self, address: u16, _value: u8) -> Result<(), MBCError> {
         let address = address as usize;
 
         if address >= ROM_SIZE_MBC_NONE {
             return Err(MBCError::ROMAccessOutOfRange);
         }
 
-        self.rom[address] = value;
+        Ok(())
+    }
+
+    /// Nothing can change, the ROM being identified by the save state's ROM hash
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    fn load_state(&mut self, _state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
         Ok(())
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests;
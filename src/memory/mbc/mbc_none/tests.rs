@@ -0,0 +1,20 @@
+use super::{MbcNone, MemoryBankController};
+use crate::savestate::StateWriter;
+
+#[test]
+fn mbc_none_rom_is_read_only() {
+    let mut mbc = MbcNone::with_rom(&[0x12, 0x34]).unwrap();
+    assert!(MbcNone::with_rom(&vec![0; 0x8001]).is_err());
+
+    mbc.write_byte(0x0001, 0xFF).unwrap();
+    mbc.write_byte(0x7FFF, 0xFF).unwrap();
+    assert!(mbc.write_byte(0x8000, 0xFF).is_err());
+    assert_eq!(0x34, mbc.read_byte(0x0001).unwrap());
+    assert_eq!(0x00, mbc.read_byte(0x7FFF).unwrap());
+
+    // the ROM hash identifies the cartridge, so there's nothing to save
+    let mut writer = StateWriter::new(0);
+    writer.section("CART", |state| mbc.save_state(state));
+    let (header, section) = (14, 8);
+    assert_eq!(header + section, writer.finish().len());
+}
@@ -1,3 +1,9 @@
+use super::*;
+use crate::{
+    apu::{AudioOutput, HighPass},
+    interrupts::Interrupt,
+};
+
 #[test]
 fn memory_bytes_read() {}
 
@@ -12,3 +18,240 @@ fn memory_words_write() {}
 
 #[test]
 fn memory_io_register_read() {}
+
+/// Fills `0xC000..=0xC09F` with `i ^ seed` and `0xC100..=0xC19F` with `!i`
+fn setup_dma_sources() -> Mmu {
+    let mut mmu = Mmu::new();
+    for i in 0..0xA0u16 {
+        mmu.write_byte(0xC000 + i, i.to_le_bytes()[0] ^ 0x5A);
+        mmu.write_byte(0xC100 + i, !i.to_le_bytes()[0]);
+    }
+    mmu
+}
+
+#[test]
+fn memory_dma_transfer() {
+    let mut mmu = setup_dma_sources();
+
+    mmu.write_byte(0xFF46, 0xC0);
+    assert_eq!(0xC0, mmu.read_byte(0xFF46));
+
+    // one M-cycle of startup, then one byte per M-cycle
+    mmu.tick(4 + 4 * 0x50);
+    assert_eq!(0x4F ^ 0x5A, mmu.ppu.read_oam(0xFE4F));
+    assert_eq!(0, mmu.ppu.read_oam(0xFE50));
+
+    mmu.tick(4 * 0x50);
+    for i in 0..0xA0u16 {
+        assert_eq!(i.to_le_bytes()[0] ^ 0x5A, mmu.ppu.read_oam(0xFE00 + i));
+    }
+    assert!(!mmu.dma.is_active());
+}
+
+#[test]
+fn memory_dma_bus_conflict() {
+    let mut mmu = setup_dma_sources();
+    mmu.write_byte(0xFF80, 0x12);
+
+    mmu.write_byte(0xFF46, 0xC0);
+    mmu.tick(2);
+    // the bus isn't taken until the startup M-cycle is over
+    assert!(!mmu.dma.is_active());
+    assert_eq!(!0x50, mmu.read_byte(0xC150));
+
+    mmu.tick(2 + 4 * 2);
+    assert!(mmu.dma.is_active());
+    // reads outside HRAM and the registers see the byte being copied
+    assert_eq!(0x02 ^ 0x5A, mmu.read_byte(0xC150));
+    assert_eq!(0x02 ^ 0x5A, mmu.read_byte(0x8000));
+    assert_eq!(0x12, mmu.read_byte(0xFF80));
+    // writes outside HRAM are dropped
+    mmu.write_byte(0xC150, 0x00);
+    mmu.write_byte(0xFF81, 0x34);
+    assert_eq!(0x34, mmu.read_byte(0xFF81));
+
+    mmu.tick(4 * 0x9E);
+    assert!(!mmu.dma.is_active());
+    assert_eq!(!0x50, mmu.read_byte(0xC150));
+}
+
+#[test]
+fn memory_dma_restart() {
+    let mut mmu = setup_dma_sources();
+
+    mmu.write_byte(0xFF46, 0xC0);
+    mmu.tick(4 + 4 * 0x10);
+    mmu.write_byte(0xFF46, 0xC1);
+
+    // the first transfer carries on for one more byte while the new one starts up
+    mmu.tick(4);
+    assert!(mmu.dma.is_active());
+    assert_eq!(0x10 ^ 0x5A, mmu.ppu.read_oam(0xFE10));
+    assert_eq!(0x0F ^ 0x5A, mmu.ppu.read_oam(0xFE0F));
+
+    mmu.tick(4 * 0xA0);
+    assert!(!mmu.dma.is_active());
+    for i in 0..0xA0u16 {
+        assert_eq!(!i.to_le_bytes()[0], mmu.ppu.read_oam(0xFE00 + i));
+    }
+}
+
+#[test]
+fn memory_vram_oam_locking() {
+    let mut mmu = Mmu::new();
+    mmu.write_byte(0x8000, 0x11);
+    mmu.write_byte(0xFE00, 0x22);
+
+    // LCD on, starting in mode 2
+    mmu.write_byte(0xFF40, 0x80);
+    assert_eq!(0x11, mmu.read_byte(0x8000));
+    assert_eq!(0xFF, mmu.read_byte(0xFE00));
+    mmu.write_byte(0xFE00, 0x33);
+
+    mmu.tick(80);
+    assert_eq!(Mode::Drawing, mmu.ppu.mode());
+    assert_eq!(0xFF, mmu.read_byte(0x8000));
+    assert_eq!(0xFF, mmu.read_byte(0xFE00));
+    mmu.write_byte(0x8000, 0x44);
+
+    mmu.tick(172);
+    assert_eq!(Mode::HBlank, mmu.ppu.mode());
+    assert_eq!(0x11, mmu.read_byte(0x8000));
+    assert_eq!(0x22, mmu.read_byte(0xFE00));
+}
+
+#[test]
+fn memory_vram_oam_locking_disabled() {
+    let mut mmu = Mmu::new();
+    mmu.set_access_locking(false);
+
+    mmu.write_byte(0xFF40, 0x80);
+    mmu.tick(80);
+    assert_eq!(Mode::Drawing, mmu.ppu.mode());
+
+    mmu.write_byte(0x8000, 0x44);
+    mmu.write_byte(0xFE00, 0x55);
+    assert_eq!(0x44, mmu.read_byte(0x8000));
+    assert_eq!(0x55, mmu.read_byte(0xFE00));
+}
+
+#[test]
+fn memory_timer_interrupt() {
+    let mut mmu = Mmu::new();
+    mmu.write_byte(0xFF05, 0xFF);
+    mmu.write_byte(0xFF07, 0b101);
+
+    mmu.tick(16 + 4);
+    assert_eq!(0xE0 | Interrupt::Timer.bit(), mmu.read_byte(0xFF0F));
+}
+
+#[test]
+fn memory_joypad_register_and_interrupt() {
+    let mut mmu = Mmu::new();
+    mmu.write_byte(0xFF00, 0x20);
+    assert_eq!(0xEF, mmu.read_byte(0xFF00));
+
+    mmu.press(Button::Down);
+    assert_eq!(0xE7, mmu.read_byte(0xFF00));
+    assert_eq!(Interrupt::Joypad.bit(), mmu.read_byte(0xFF0F) & 0x1F);
+
+    mmu.release(Button::Down);
+    assert_eq!(0xEF, mmu.read_byte(0xFF00));
+}
+
+#[test]
+fn memory_apu_frame_sequencer_clocked_by_div() {
+    let mut mmu = Mmu::new();
+    mmu.write_byte(0xFF26, 0x80);
+    // channel 2 with a single length clock left
+    mmu.write_byte(0xFF17, 0xF0);
+    mmu.write_byte(0xFF16, 63);
+    mmu.write_byte(0xFF19, 0xC0);
+    assert_eq!(0xF2, mmu.read_byte(0xFF26));
+
+    // DIV bit 4 falls after 0x2000 T-states
+    mmu.tick(0x1FFF);
+    assert_eq!(0xF2, mmu.read_byte(0xFF26));
+    mmu.tick(1);
+    assert_eq!(0xF0, mmu.read_byte(0xFF26));
+}
+
+/// LCD, timer, sound, serial and DMA all busy, with STAT and timer interrupts enabled
+fn setup_busy_bus() -> Mmu {
+    let mut mmu = setup_dma_sources();
+    mmu.apu_mut()
+        .set_audio_output(Some(AudioOutput::new(48_000, HighPass::Off)));
+
+    // LY=LYC and H-blank STAT interrupts
+    mmu.write_byte(0xFF45, 3);
+    mmu.write_byte(0xFF41, 0x48);
+    mmu.write_byte(0xFF40, 0x91);
+    // TIMA overflowing every 16 * 0x10 T-states
+    mmu.write_byte(0xFF06, 0xF0);
+    mmu.write_byte(0xFF07, 0b101);
+    // pulse 2 and noise playing with envelopes, and a length counter running out
+    mmu.write_byte(0xFF26, 0x80);
+    mmu.write_byte(0xFF25, 0xFF);
+    mmu.write_byte(0xFF24, 0x77);
+    mmu.write_byte(0xFF17, 0xF3);
+    mmu.write_byte(0xFF18, 0x9A);
+    mmu.write_byte(0xFF19, 0x87);
+    mmu.write_byte(0xFF21, 0xA1);
+    mmu.write_byte(0xFF22, 0x35);
+    mmu.write_byte(0xFF20, 0x30);
+    mmu.write_byte(0xFF23, 0xC0);
+    // a byte out of the serial port
+    mmu.write_byte(0xFF01, 0x42);
+    mmu.write_byte(0xFF02, 0x81);
+    mmu.write_byte(0xFF46, 0xC1);
+    mmu
+}
+
+#[test]
+#[allow(clippy::float_cmp)] // the same levels are expected, not close ones
+fn memory_batched_ticks_match_single_ticks() {
+    let mut single = setup_busy_bus();
+    let mut batched = setup_busy_bus();
+
+    // two frames, in steps of various lengths as instructions would take
+    let mut elapsed = 0;
+    for step in [4, 8, 12, 16, 20, 24, 252].iter().cycle() {
+        for _ in 0..*step {
+            single.tick(1);
+        }
+        batched.tick(*step);
+        elapsed += step;
+
+        for address in 0xFF00..=0xFF7F {
+            assert_eq!(
+                single.read_byte(address),
+                batched.read_byte(address),
+                "{address:04X} after {elapsed} T-states"
+            );
+        }
+        assert_eq!(single.ppu.mode(), batched.ppu.mode());
+        assert_eq!(single.apu.channel_levels(), batched.apu.channel_levels());
+        assert_eq!(single.clock(), batched.clock());
+
+        if elapsed > 2 * 70_224 {
+            break;
+        }
+    }
+
+    let requested = Interrupt::ALL
+        .iter()
+        .filter(|&&interrupt| interrupt != Interrupt::Joypad)
+        .fold(0xE0, |flags, interrupt| flags | interrupt.bit());
+    assert_eq!(requested, batched.read_byte(0xFF0F));
+    assert!(single.ppu.framebuffer() == batched.ppu.framebuffer());
+    for address in 0xFE00..=0xFE9F {
+        assert_eq!(single.ppu.read_oam(address), batched.ppu.read_oam(address));
+    }
+    let mut samples = [Vec::new(), Vec::new()];
+    for (mmu, samples) in [&mut single, &mut batched].iter_mut().zip(&mut samples) {
+        let output = mmu.apu_mut().audio_output_mut().unwrap();
+        output.read_f32(samples);
+    }
+    assert!(!samples[0].is_empty());
+    assert!(samples[0] == samples[1]);
+}
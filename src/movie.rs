@@ -0,0 +1,396 @@
+//! Movies: the input of a run, frame by frame, to play it back exactly.
+//!
+//! A movie holds what a run depends on besides its input: the cartridge, the console model
+//! and settings, and a save state to start from unless it starts at power on. Playing it back
+//! on the same cartridge then reproduces the run bit for bit. The hash of the console's save
+//! state is recorded after every frame too, and playback stops with `MovieError::Desync` at the
+//! first frame whose hash differs. To save time, `Movie::play` only checks the state every
+//! `hash_interval` frames, and on a mismatch plays the frames since the last check again to
+//! find the first that differs. A run that diverged and came back by the next check goes
+//! unnoticed, which an interval of 1 rules out.
+//!
+//! The file is a header followed by the start state, the input and the hashes, all integers
+//! little endian:
+//!
+//! | offset | size  | contents                                                    |
+//! | ------ | ----- | ----------------------------------------------------------- |
+//! | 0      | 4     | magic number `RGBM`                                         |
+//! | 4      | 2     | format version, `VERSION`                                   |
+//! | 6      | 8     | `savestate::rom_hash` of the cartridge ROM                  |
+//! | 14     | 1     | model, 0 for the DMG                                        |
+//! | 15     | 1     | renderer, 0 for `Scanline` and 1 for `PixelFifo`            |
+//! | 16     | 1     | CPU timing, 0 for `Instruction` and 1 for `MCycle`          |
+//! | 17     | 1     | bit 0: VRAM and OAM access locking, bit 1: serial CGB mode  |
+//! | 18     | 4     | hash interval `i`, frames between checks in playback        |
+//! | 22     | 4     | number of frames `n`                                        |
+//! | 26     | 4     | length `s` of the start state, 0 to start at power on       |
+//! | 30     | `s`   | start state                                                 |
+//! |        | `n`   | buttons held during each frame, as `JoypadState::bits`      |
+//! |        | 8 × `n` | `savestate::state_hash` after each frame                  |
+use crate::{
+    cpu::Timing,
+    gameboy::{CartridgeError, GameBoy},
+    joypad::JoypadState,
+    ppu::RendererKind,
+    savestate::{self, SaveStateError},
+};
+use std::{convert::TryFrom, error::Error, fmt};
+
+const MAGIC: [u8; 4] = *b"RGBM";
+/// Version of the format written by this build
+pub const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 30;
+/// The only model emulated so far
+const MODEL_DMG: u8 = 0;
+const ACCESS_LOCKING: u8 = 0b01;
+const SERIAL_CGB_MODE: u8 = 0b10;
+/// Frames between state hashes unless told otherwise, about a second
+pub const DEFAULT_HASH_INTERVAL: u32 = 60;
+
+/// Why a movie could not be read or played back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data isn't a movie, or is cut short
+    NotAMovie,
+    /// Saved in a version of the format this build can't read
+    UnsupportedVersion(u16),
+    /// Recorded on a console model that isn't emulated
+    UnsupportedModel(u8),
+    /// A setting holds a value this build doesn't know of
+    Corrupt,
+    /// Playing back with another cartridge than it was recorded with
+    WrongCartridge,
+    /// The cartridge can't be inserted
+    Cartridge(CartridgeError),
+    /// The start state can't be loaded
+    State(SaveStateError),
+    /// The console's state differs from the recording's after this frame, counted from 0,
+    /// and matched it after every frame before
+    Desync { frame: usize },
+}
+
+impl Error for MovieError {}
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAMovie => write!(f, "Not a movie"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Movie format version {version} is not supported, expected {VERSION}"
+            ),
+            Self::UnsupportedModel(model) => write!(f, "Movie console model {model} is unknown"),
+            Self::Corrupt => write!(f, "Movie settings are corrupt"),
+            Self::WrongCartridge => write!(f, "Movie was recorded with another cartridge"),
+            Self::Cartridge(error) => write!(f, "Movie cartridge can't be inserted: {error}"),
+            Self::State(error) => write!(f, "Movie start state can't be loaded: {error}"),
+            Self::Desync { frame } => write!(f, "Movie playback desynced at frame {frame}"),
+        }
+    }
+}
+
+impl From<CartridgeError> for MovieError {
+    fn from(error: CartridgeError) -> Self {
+        Self::Cartridge(error)
+    }
+}
+
+impl From<SaveStateError> for MovieError {
+    fn from(error: SaveStateError) -> Self {
+        Self::State(error)
+    }
+}
+
+/// Console settings that change how a run plays out, so playback has to use them too.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub renderer: RendererKind,
+    pub timing: Timing,
+    /// See `Mmu::set_access_locking`
+    pub access_locking: bool,
+    /// See `Serial::set_cgb_mode`
+    pub serial_cgb_mode: bool,
+}
+
+impl Settings {
+    /// The settings `gameboy` runs with
+    #[must_use]
+    pub fn of(gameboy: &GameBoy) -> Self {
+        Self {
+            renderer: gameboy.mmu().ppu().renderer_kind(),
+            timing: gameboy.cpu().timing(),
+            access_locking: gameboy.mmu().access_locking(),
+            serial_cgb_mode: gameboy.mmu().serial().cgb_mode(),
+        }
+    }
+
+    /// Sets up `gameboy` with these settings, but for the renderer which is chosen when
+    /// creating it.
+    pub const fn apply(self, gameboy: &mut GameBoy) {
+        gameboy.cpu_mut().set_timing(self.timing);
+        gameboy.mmu_mut().set_access_locking(self.access_locking);
+        gameboy
+            .mmu_mut()
+            .serial_mut()
+            .set_cgb_mode(self.serial_cgb_mode);
+    }
+}
+
+/// A recorded run, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    rom_hash: u64,
+    settings: Settings,
+    hash_interval: u32,
+    start_state: Option<Vec<u8>>,
+    inputs: Vec<JoypadState>,
+    hashes: Vec<u64>,
+}
+
+impl Movie {
+    /// Starts an empty movie of `gameboy`, played back checking its state every
+    /// `DEFAULT_HASH_INTERVAL` frames. Frames are added with `Movie::record_frame`.
+    #[must_use]
+    pub fn record(gameboy: &GameBoy) -> Self {
+        Self::record_with_hash_interval(gameboy, DEFAULT_HASH_INTERVAL)
+    }
+
+    /// Starts an empty movie of `gameboy`, played back checking its state every
+    /// `hash_interval` frames. A console that has yet to run a clock tick is taken to be at
+    /// power on, any other has its state saved in the movie to start from.
+    ///
+    /// # Panics
+    /// If `hash_interval` is 0.
+    #[must_use]
+    pub fn record_with_hash_interval(gameboy: &GameBoy, hash_interval: u32) -> Self {
+        assert!(hash_interval > 0, "Hash interval must be at least 1");
+        Self {
+            rom_hash: gameboy.rom_hash(),
+            settings: Settings::of(gameboy),
+            hash_interval,
+            start_state: match gameboy.clock() {
+                0 => None,
+                _ => Some(gameboy.save_state()),
+            },
+            inputs: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Runs a frame of `gameboy` with the buttons in `input` held, adding it to the movie.
+    ///
+    /// # Return value
+    /// Number of clock ticks run.
+    pub fn record_frame(&mut self, gameboy: &mut GameBoy, input: JoypadState) -> u64 {
+        gameboy.set_joypad(input);
+        let t_states = gameboy.run_frame();
+        self.inputs.push(input);
+        self.hashes
+            .push(savestate::state_hash(&gameboy.save_state()));
+        t_states
+    }
+
+    /// Number of frames recorded
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// `savestate::rom_hash` of the cartridge recorded with
+    #[must_use]
+    pub const fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    #[must_use]
+    pub const fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// Buttons held during each frame
+    #[must_use]
+    pub fn inputs(&self) -> &[JoypadState] {
+        &self.inputs
+    }
+
+    /// Whether `Movie::play` checks the state after `frame`, which it does after the last
+    const fn hash_due(&self, frame: usize) -> bool {
+        (frame + 1).is_multiple_of(self.hash_interval as usize) || frame + 1 == self.inputs.len()
+    }
+
+    /// A console with `rom` inserted as the movie starts, ready for `Movie::play`.
+    pub fn start(&self, rom: Vec<u8>) -> Result<GameBoy, MovieError> {
+        if savestate::rom_hash(&rom) != self.rom_hash {
+            return Err(MovieError::WrongCartridge);
+        }
+        let mut gameboy = GameBoy::with_renderer(rom, self.settings.renderer)?;
+        self.settings.apply(&mut gameboy);
+        if let Some(state) = &self.start_state {
+            gameboy.load_state(state)?;
+        }
+        Ok(gameboy)
+    }
+
+    /// Plays `frame` back on `gameboy` and checks its state.
+    ///
+    /// # Panics
+    /// If `frame` is past the end of the movie.
+    pub fn play_frame(&self, gameboy: &mut GameBoy, frame: usize) -> Result<(), MovieError> {
+        gameboy.set_joypad(self.inputs[frame]);
+        gameboy.run_frame();
+        match savestate::state_hash(&gameboy.save_state()) == self.hashes[frame] {
+            true => Ok(()),
+            false => Err(MovieError::Desync { frame }),
+        }
+    }
+
+    /// Plays the whole movie back on `gameboy`, as returned by `Movie::start`, checking its
+    /// state every `hash_interval` frames. After a mismatch, the state of the last check that
+    /// passed is loaded back and the frames since played again one by one, so the error
+    /// still names the first frame that differs.
+    pub fn play(&self, gameboy: &mut GameBoy) -> Result<(), MovieError> {
+        let (mut checked, mut checked_state) = (0, gameboy.save_state());
+        for (frame, &input) in self.inputs.iter().enumerate() {
+            gameboy.set_joypad(input);
+            gameboy.run_frame();
+            if !self.hash_due(frame) {
+                continue;
+            }
+            let state = gameboy.save_state();
+            if savestate::state_hash(&state) != self.hashes[frame] {
+                gameboy.load_state(&checked_state)?;
+                (checked..frame).try_for_each(|frame| self.play_frame(gameboy, frame))?;
+                return self.play_frame(gameboy, frame);
+            }
+            (checked, checked_state) = (frame + 1, state);
+        }
+        Ok(())
+    }
+
+    /// The movie as a file, see the module documentation for the format.
+    ///
+    /// # Panics
+    /// If the start state or the number of frames don't fit in 4 GiB.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let start_state = self.start_state.as_deref().unwrap_or_default();
+        let mut bytes = Vec::with_capacity(
+            HEADER_SIZE + start_state.len() + self.inputs.len() + 8 * self.hashes.len(),
+        );
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.rom_hash.to_le_bytes());
+
+        let settings = self.settings;
+        let mut flags = 0;
+        if settings.access_locking {
+            flags |= ACCESS_LOCKING;
+        }
+        if settings.serial_cgb_mode {
+            flags |= SERIAL_CGB_MODE;
+        }
+        bytes.extend_from_slice(&[
+            MODEL_DMG,
+            match settings.renderer {
+                RendererKind::Scanline => 0,
+                RendererKind::PixelFifo => 1,
+            },
+            match settings.timing {
+                Timing::Instruction => 0,
+                Timing::MCycle => 1,
+            },
+            flags,
+        ]);
+        bytes.extend_from_slice(&self.hash_interval.to_le_bytes());
+        let frames = u32::try_from(self.inputs.len()).expect("under 4 Gi frames");
+        bytes.extend_from_slice(&frames.to_le_bytes());
+        let state_length = u32::try_from(start_state.len()).expect("start state under 4 GiB");
+        bytes.extend_from_slice(&state_length.to_le_bytes());
+
+        bytes.extend_from_slice(start_state);
+        bytes.extend(self.inputs.iter().map(|input| input.bits()));
+        for hash in &self.hashes {
+            bytes.extend_from_slice(&hash.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Reads a movie written by `Movie::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        if bytes.len() < HEADER_SIZE || bytes[..4] != MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+        let u32_at = |offset: usize| {
+            let mut value = [0; 4];
+            value.copy_from_slice(&bytes[offset..offset + 4]);
+            usize::try_from(u32::from_le_bytes(value)).map_err(|_| MovieError::NotAMovie)
+        };
+
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let mut rom_hash = [0; 8];
+        rom_hash.copy_from_slice(&bytes[6..14]);
+        if bytes[14] != MODEL_DMG {
+            return Err(MovieError::UnsupportedModel(bytes[14]));
+        }
+        let settings = Settings {
+            renderer: match bytes[15] {
+                0 => RendererKind::Scanline,
+                1 => RendererKind::PixelFifo,
+                _ => return Err(MovieError::Corrupt),
+            },
+            timing: match bytes[16] {
+                0 => Timing::Instruction,
+                1 => Timing::MCycle,
+                _ => return Err(MovieError::Corrupt),
+            },
+            access_locking: bytes[17] & ACCESS_LOCKING != 0,
+            serial_cgb_mode: bytes[17] & SERIAL_CGB_MODE != 0,
+        };
+        let hash_interval = u32_at(18)?;
+        if hash_interval == 0 || bytes[17] & !(ACCESS_LOCKING | SERIAL_CGB_MODE) != 0 {
+            return Err(MovieError::Corrupt);
+        }
+
+        let frames = u32_at(22)?;
+        let state_length = u32_at(26)?;
+        let (start_state, rest) = bytes[HEADER_SIZE..]
+            .split_at_checked(state_length)
+            .ok_or(MovieError::NotAMovie)?;
+        let (inputs, rest) = rest.split_at_checked(frames).ok_or(MovieError::NotAMovie)?;
+        if rest.len() != 8 * frames {
+            return Err(MovieError::NotAMovie);
+        }
+
+        Ok(Self {
+            rom_hash: u64::from_le_bytes(rom_hash),
+            settings,
+            hash_interval: u32::try_from(hash_interval).map_err(|_| MovieError::NotAMovie)?,
+            start_state: match state_length {
+                0 => None,
+                _ => Some(start_state.to_vec()),
+            },
+            inputs: inputs
+                .iter()
+                .map(|&bits| JoypadState::from_bits(bits))
+                .collect(),
+            hashes: rest
+                .chunks_exact(8)
+                .map(|hash| {
+                    let mut bytes = [0; 8];
+                    bytes.copy_from_slice(hash);
+                    u64::from_le_bytes(bytes)
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
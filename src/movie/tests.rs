@@ -0,0 +1,119 @@
+use super::{Movie, MovieError, Settings};
+use crate::{
+    cpu::Timing,
+    gameboy::GameBoy,
+    joypad::{Button, JoypadState},
+    ppu::RendererKind,
+};
+
+/// Adds P1 up in WRAM and SCX, and copies the scanline to WRAM, so the input shows in the
+/// state from then on
+fn cartridge() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x10,       // 0x0100: LD A, 0x10
+        0xE0, 0x00,       // 0x0102: LDH (P1), A       ; select the action buttons
+        0xF0, 0x00,       // 0x0104: LDH A, (P1)
+        0x21, 0x01, 0xC0, // 0x0106: LD HL, 0xC001
+        0x86,             // 0x0109: ADD A, (HL)
+        0x77,             // 0x010A: LD (HL), A
+        0xE0, 0x43,       // 0x010B: LDH (SCX), A
+        0xF0, 0x44,       // 0x010D: LDH A, (LY)
+        0xEA, 0x00, 0xC0, // 0x010F: LD (0xC000), A
+        0x18, 0xF0,       // 0x0112: JR 0x0104
+    ];
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(&program);
+    rom
+}
+
+fn input(frame: usize) -> JoypadState {
+    match frame % 7 {
+        0 | 1 => JoypadState::new().with(Button::A),
+        4 => JoypadState::new().with(Button::Start).with(Button::B),
+        _ => JoypadState::new(),
+    }
+}
+
+#[test]
+fn movie_plays_back_exactly() {
+    let mut gameboy = GameBoy::with_renderer(cartridge(), RendererKind::PixelFifo).unwrap();
+    gameboy.cpu_mut().set_timing(Timing::MCycle);
+    gameboy.mmu_mut().set_access_locking(false);
+    let settings = Settings::of(&gameboy);
+
+    // from power on, then from a state partway through
+    for &warmup in &[0, 3] {
+        for _ in 0..warmup {
+            gameboy.run_cycles(1000);
+        }
+        let mut movie = Movie::record_with_hash_interval(&gameboy, 4);
+        for frame in 0..30 {
+            movie.record_frame(&mut gameboy, input(frame));
+        }
+        let bytes = movie.to_bytes();
+        let movie = Movie::from_bytes(&bytes).unwrap();
+        assert_eq!(settings, movie.settings());
+        assert_eq!(30, movie.len());
+
+        let mut replay = movie.start(cartridge()).unwrap();
+        assert_eq!(settings, Settings::of(&replay));
+        movie.play(&mut replay).unwrap();
+        assert!(gameboy.save_state() == replay.save_state());
+    }
+}
+
+#[test]
+fn movie_flags_desync() {
+    let mut gameboy = GameBoy::new(cartridge()).unwrap();
+    let mut movie = Movie::record_with_hash_interval(&gameboy, 8);
+    for frame in 0..20 {
+        movie.record_frame(&mut gameboy, input(frame));
+    }
+    let mut bytes = movie.to_bytes();
+    let first_input = bytes.len() - 20 - 8 * 20;
+    bytes[first_input + 12] = Button::Select as u8;
+
+    // checked after frame 15, but the run diverged on frame 12
+    let movie = Movie::from_bytes(&bytes).unwrap();
+    let mut replay = movie.start(cartridge()).unwrap();
+    assert_eq!(
+        Err(MovieError::Desync { frame: 12 }),
+        movie.play(&mut replay)
+    );
+
+    let mut replay = movie.start(cartridge()).unwrap();
+    for frame in 0..12 {
+        movie.play_frame(&mut replay, frame).unwrap();
+    }
+    assert_eq!(
+        Err(MovieError::Desync { frame: 12 }),
+        movie.play_frame(&mut replay, 12)
+    );
+}
+
+#[test]
+fn movie_errors() {
+    let mut gameboy = GameBoy::new(cartridge()).unwrap();
+    let mut movie = Movie::record(&gameboy);
+    movie.record_frame(&mut gameboy, JoypadState::new());
+    let bytes = movie.to_bytes();
+
+    assert_eq!(
+        Err(MovieError::WrongCartridge),
+        movie.start(vec![0; 0x8000]).map(|_| ())
+    );
+    assert_eq!(
+        Err(MovieError::NotAMovie),
+        Movie::from_bytes(&bytes[..bytes.len() - 1])
+    );
+    let mut model = bytes.clone();
+    model[14] = 1;
+    assert_eq!(
+        Err(MovieError::UnsupportedModel(1)),
+        Movie::from_bytes(&model)
+    );
+    let mut renderer = bytes;
+    renderer[15] = 2;
+    assert_eq!(Err(MovieError::Corrupt), Movie::from_bytes(&renderer));
+}
@@ -0,0 +1,675 @@
+//! Implements the picture processing unit (PPU).
+//!
+//! The PPU owns video RAM, object attribute memory (OAM) and the LCD registers at
+//! `0xFF40..=0xFF4B`. It steps through the four LCD modes one dot (T-state) at a time, while
+//! the pixels of each line are produced by one of two renderers chosen at construction:
+//!
+//! | renderer                    | mode 3 length        | mid-scanline register writes      |
+//! | --------------------------- | -------------------- | --------------------------------- |
+//! | `RendererKind::Scanline`    | fixed, 172 dots      | take effect on the next line      |
+//! | `RendererKind::PixelFifo`   | 172 to 289 dots      | take effect at the pixel they hit |
+use crate::{
+    interrupts::Interrupt,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+use std::convert::TryFrom;
+
+/// Models the background/object pixel FIFOs and the pixel fetcher
+mod fifo;
+/// Renders a whole line at once at the start of mode 3
+mod scanline;
+
+/// Width of the LCD in pixels
+pub const SCREEN_WIDTH: usize = 160;
+/// Height of the LCD in pixels
+pub const SCREEN_HEIGHT: usize = 144;
+
+const VRAM_SIZE: usize = 0x2000;
+const OAM_SIZE: usize = 0xA0;
+
+const DOTS_PER_LINE: u16 = 456;
+const OAM_SCAN_DOTS: u16 = 80;
+const VBLANK_START_LINE: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+// LCDC bits
+const LCDC_BG_ENABLE: u8 = 0b_0000_0001;
+const LCDC_OBJ_ENABLE: u8 = 0b_0000_0010;
+const LCDC_OBJ_SIZE: u8 = 0b_0000_0100;
+const LCDC_BG_MAP: u8 = 0b_0000_1000;
+const LCDC_TILE_DATA: u8 = 0b_0001_0000;
+const LCDC_WINDOW_ENABLE: u8 = 0b_0010_0000;
+const LCDC_WINDOW_MAP: u8 = 0b_0100_0000;
+const LCDC_LCD_ENABLE: u8 = 0b_1000_0000;
+
+// STAT interrupt source selects
+const STAT_HBLANK_SELECT: u8 = 0b_0000_1000;
+const STAT_VBLANK_SELECT: u8 = 0b_0001_0000;
+const STAT_OAM_SELECT: u8 = 0b_0010_0000;
+const STAT_LYC_SELECT: u8 = 0b_0100_0000;
+const STAT_LYC_EQUAL: u8 = 0b_0000_0100;
+const STAT_WRITABLE: u8 = 0b_0111_1000;
+
+// object attribute bits
+const OBJ_BG_PRIORITY: u8 = 0b_1000_0000;
+const OBJ_Y_FLIP: u8 = 0b_0100_0000;
+const OBJ_X_FLIP: u8 = 0b_0010_0000;
+const OBJ_PALETTE: u8 = 0b_0001_0000;
+
+/// The four LCD modes reported in the lower two bits of STAT.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+/// Selects how the pixels of a line are produced during mode 3.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RendererKind {
+    /// Renders each line in one go using the register values at the start of mode 3. Fast.
+    Scanline,
+    /// Models the pixel FIFOs and fetcher dot by dot. Mode 3 length varies with SCX, the window
+    /// and sprites, and register writes during mode 3 take effect at the right pixel.
+    PixelFifo,
+}
+
+/// LCD control and status registers, `0xFF40..=0xFF4B` except DMA at `0xFF46`.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LcdRegisters {
+    pub(crate) lcdc: u8,
+    pub(crate) stat: u8,
+    pub(crate) scy: u8,
+    pub(crate) scx: u8,
+    pub(crate) ly: u8,
+    pub(crate) lyc: u8,
+    pub(crate) bgp: u8,
+    pub(crate) obp0: u8,
+    pub(crate) obp1: u8,
+    pub(crate) wy: u8,
+    pub(crate) wx: u8,
+}
+
+impl LcdRegisters {
+    const fn flag(&self, mask: u8) -> bool {
+        self.lcdc & mask != 0
+    }
+}
+
+/// An OAM entry selected for the current line during mode 2.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Sprite {
+    pub(crate) y: u8,
+    pub(crate) x: u8,
+    pub(crate) tile: u8,
+    pub(crate) attributes: u8,
+}
+
+/// State shared with the renderer for every dot of mode 3.
+pub(crate) struct LineContext<'a> {
+    pub(crate) regs: &'a LcdRegisters,
+    pub(crate) vram: &'a [u8],
+    /// Sprites on this line, in OAM order.
+    pub(crate) sprites: &'a [Sprite],
+    pub(crate) window: WindowState,
+    /// Framebuffer row for this line.
+    pub(crate) line: &'a mut [u8],
+}
+
+impl<'a> LineContext<'a> {
+    fn new(
+        regs: &'a LcdRegisters,
+        vram: &'a [u8],
+        sprites: &'a [Sprite],
+        window: WindowState,
+        framebuffer: &'a mut [u8],
+    ) -> Self {
+        let row = usize::from(regs.ly) * SCREEN_WIDTH;
+        Self {
+            regs,
+            vram,
+            sprites,
+            window,
+            line: &mut framebuffer[row..row + SCREEN_WIDTH],
+        }
+    }
+}
+
+/// Per-frame window bookkeeping.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct WindowState {
+    /// Whether WY matched LY at some point this frame, so the window may be drawn.
+    pub(crate) y_triggered: bool,
+    /// Internal window line counter. Only advances on lines where the window was drawn.
+    pub(crate) line: u8,
+}
+
+/// Produces the pixels of a line while the PPU is in mode 3.
+trait Renderer {
+    /// Resets per-line state at the start of mode 3.
+    fn start_line(&mut self, ctx: &LineContext<'_>);
+
+    /// Advances one dot. Returns `true` once mode 3 is over for this line.
+    fn dot(&mut self, ctx: &mut LineContext<'_>) -> bool;
+
+    /// Whether the window was drawn on the line just finished.
+    fn window_drawn(&self) -> bool;
+
+    /// Dots until mode 3 is over, if nothing but counting is left to do until then.
+    fn remaining_dots(&self) -> Option<u16> {
+        None
+    }
+
+    /// Advances by `dots` dots, fewer than `remaining_dots`.
+    fn skip(&mut self, _dots: u16) {}
+
+    fn kind(&self) -> RendererKind;
+
+    /// Saves the state of the line being drawn.
+    fn save_state(&self, state: &mut StateWriter);
+
+    /// Restores what `Renderer::save_state` saved, `sprites` being selected for the line.
+    fn load_state(
+        &mut self,
+        state: &mut StateReader<'_>,
+        sprites: usize,
+    ) -> Result<(), SaveStateError>;
+}
+
+/// Picture processing unit
+pub struct Ppu {
+    regs: LcdRegisters,
+    vram: Vec<u8>,
+    oam: Vec<u8>,
+    /// Shades 0 (lightest) to 3 (darkest), `SCREEN_WIDTH * SCREEN_HEIGHT` pixels row by row
+    framebuffer: Vec<u8>,
+    renderer: Box<dyn Renderer>,
+    mode: Mode,
+    /// Dot within the current line, `0..DOTS_PER_LINE`
+    line_dot: u16,
+    /// Sprites selected by the OAM scan for the current line
+    sprites: Vec<Sprite>,
+    window: WindowState,
+    /// Previous state of the STAT interrupt line, which only fires on a rising edge
+    stat_line: bool,
+    /// Set by register writes, which can change the STAT line, until the next dot
+    registers_written: bool,
+}
+
+impl Ppu {
+    /// Creates a PPU with the LCD switched off, using the given renderer for mode 3.
+    #[must_use]
+    pub fn new(kind: RendererKind) -> Self {
+        let renderer: Box<dyn Renderer> = match kind {
+            RendererKind::Scanline => Box::new(scanline::ScanlineRenderer::new()),
+            RendererKind::PixelFifo => Box::new(fifo::FifoRenderer::new()),
+        };
+
+        Self {
+            regs: LcdRegisters::default(),
+            vram: vec![0; VRAM_SIZE],
+            oam: vec![0; OAM_SIZE],
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            renderer,
+            mode: Mode::HBlank,
+            line_dot: 0,
+            sprites: Vec::with_capacity(MAX_SPRITES_PER_LINE),
+            window: WindowState::default(),
+            stat_line: false,
+            registers_written: false,
+        }
+    }
+
+    /// Current LCD mode
+    #[must_use]
+    pub const fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Which renderer draws mode 3
+    #[must_use]
+    pub fn renderer_kind(&self) -> RendererKind {
+        self.renderer.kind()
+    }
+
+    /// The last rendered frame as shades 0 (lightest) to 3 (darkest), row by row.
+    #[must_use]
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Reads a byte of VRAM, `address` in `0x8000..=0x9FFF`
+    #[must_use]
+    pub fn read_vram(&self, address: u16) -> u8 {
+        self.vram[usize::from(address - 0x8000)]
+    }
+
+    /// Writes a byte of VRAM, `address` in `0x8000..=0x9FFF`
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        self.vram[usize::from(address - 0x8000)] = value;
+    }
+
+    /// Reads a byte of OAM, `address` in `0xFE00..=0xFE9F`
+    #[must_use]
+    pub fn read_oam(&self, address: u16) -> u8 {
+        self.oam[usize::from(address - 0xFE00)]
+    }
+
+    /// Writes a byte of OAM, `address` in `0xFE00..=0xFE9F`
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        self.oam[usize::from(address - 0xFE00)] = value;
+    }
+
+    /// Reads one of the LCD registers in `0xFF40..=0xFF4B`
+    #[must_use]
+    pub const fn read_register(&self, address: u16) -> u8 {
+        match address {
+            0xFF40 => self.regs.lcdc,
+            0xFF41 => {
+                let mode = match self.lcd_enabled() {
+                    true => self.mode as u8,
+                    false => 0,
+                };
+                0b_1000_0000 | self.regs.stat | mode
+            }
+            0xFF42 => self.regs.scy,
+            0xFF43 => self.regs.scx,
+            0xFF44 => self.regs.ly,
+            0xFF45 => self.regs.lyc,
+            0xFF47 => self.regs.bgp,
+            0xFF48 => self.regs.obp0,
+            0xFF49 => self.regs.obp1,
+            0xFF4A => self.regs.wy,
+            0xFF4B => self.regs.wx,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes one of the LCD registers in `0xFF40..=0xFF4B`
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        self.registers_written = true;
+        match address {
+            0xFF40 => {
+                let was_enabled = self.lcd_enabled();
+                self.regs.lcdc = value;
+                match (was_enabled, self.lcd_enabled()) {
+                    (true, false) => self.switch_off(),
+                    (false, true) => self.start_line(),
+                    _ => {}
+                }
+            }
+            0xFF41 => {
+                self.regs.stat = (self.regs.stat & !STAT_WRITABLE) | (value & STAT_WRITABLE);
+            }
+            0xFF42 => self.regs.scy = value,
+            0xFF43 => self.regs.scx = value,
+            0xFF45 => self.regs.lyc = value,
+            0xFF47 => self.regs.bgp = value,
+            0xFF48 => self.regs.obp0 = value,
+            0xFF49 => self.regs.obp1 = value,
+            0xFF4A => self.regs.wy = value,
+            0xFF4B => self.regs.wx = value,
+            _ => {} // LY is read only
+        }
+    }
+
+    /// Advances the PPU by `t_states` dots.
+    ///
+    /// # Return value
+    /// Bit mask of the interrupts requested in that time, to be OR-ed into IF.
+    pub fn tick(&mut self, t_states: u32) -> u8 {
+        let mut interrupts = 0;
+
+        if !self.lcd_enabled() {
+            return interrupts;
+        }
+
+        let mut remaining = t_states;
+        while remaining > 0 {
+            // dots before the next event only move along the line
+            let quiet = self
+                .next_event()
+                .map_or(remaining, |until| until - 1)
+                .min(remaining);
+            let dots = u16::try_from(quiet).unwrap_or(DOTS_PER_LINE);
+            self.line_dot += dots;
+            if self.mode == Mode::Drawing {
+                self.renderer.skip(dots);
+            }
+            remaining -= quiet;
+
+            if remaining > 0 {
+                interrupts |= self.dot();
+                remaining -= 1;
+            }
+        }
+
+        interrupts
+    }
+
+    /// Dots until the one in which the PPU changes mode or line, if the LCD is on. Every dot of
+    /// mode 3 the renderer does work in is one, as is the dot after a register write.
+    #[must_use]
+    pub fn next_event(&self) -> Option<u32> {
+        if !self.lcd_enabled() {
+            return None;
+        }
+        if self.registers_written {
+            return Some(1);
+        }
+
+        let until = match self.mode {
+            Mode::OamScan => OAM_SCAN_DOTS - self.line_dot,
+            Mode::Drawing => self.renderer.remaining_dots().unwrap_or(1),
+            Mode::HBlank | Mode::VBlank => DOTS_PER_LINE - self.line_dot,
+        };
+        Some(u32::from(until))
+    }
+
+    const fn lcd_enabled(&self) -> bool {
+        self.regs.flag(LCDC_LCD_ENABLE)
+    }
+
+    /// Turning the LCD off resets LY and leaves the PPU in mode 0 until it is turned back on.
+    fn switch_off(&mut self) {
+        self.regs.ly = 0;
+        self.line_dot = 0;
+        self.mode = Mode::HBlank;
+        self.window = WindowState::default();
+        self.stat_line = false;
+    }
+
+    /// Advances the PPU by a single dot.
+    fn dot(&mut self) -> u8 {
+        let mut interrupts = 0;
+        self.registers_written = false;
+
+        match self.mode {
+            Mode::OamScan => {
+                if self.line_dot == OAM_SCAN_DOTS - 1 {
+                    self.scan_oam();
+                    self.mode = Mode::Drawing;
+                    let ctx = LineContext::new(
+                        &self.regs,
+                        &self.vram,
+                        &self.sprites,
+                        self.window,
+                        &mut self.framebuffer,
+                    );
+                    self.renderer.start_line(&ctx);
+                }
+            }
+            Mode::Drawing => {
+                let mut ctx = LineContext::new(
+                    &self.regs,
+                    &self.vram,
+                    &self.sprites,
+                    self.window,
+                    &mut self.framebuffer,
+                );
+
+                if self.renderer.dot(&mut ctx) {
+                    if self.renderer.window_drawn() {
+                        self.window.line = self.window.line.wrapping_add(1);
+                    }
+                    self.mode = Mode::HBlank;
+                }
+            }
+            Mode::HBlank | Mode::VBlank => {}
+        }
+
+        self.line_dot += 1;
+        if self.line_dot == DOTS_PER_LINE {
+            self.line_dot = 0;
+            self.regs.ly += 1;
+
+            if self.regs.ly == VBLANK_START_LINE {
+                self.mode = Mode::VBlank;
+                interrupts |= Interrupt::VBlank.bit();
+            } else if self.regs.ly == LINES_PER_FRAME {
+                self.regs.ly = 0;
+                self.window = WindowState::default();
+                self.start_line();
+            } else if self.regs.ly < VBLANK_START_LINE {
+                self.start_line();
+            }
+        }
+
+        if self.update_stat_line() {
+            interrupts |= Interrupt::LcdStat.bit();
+        }
+
+        interrupts
+    }
+
+    /// Enters mode 2 at the start of a visible line.
+    const fn start_line(&mut self) {
+        self.mode = Mode::OamScan;
+        if self.regs.ly == self.regs.wy {
+            self.window.y_triggered = true;
+        }
+    }
+
+    /// Selects up to ten sprites overlapping the current line, in OAM order.
+    fn scan_oam(&mut self) {
+        let height = match self.regs.flag(LCDC_OBJ_SIZE) {
+            true => 16,
+            false => 8,
+        };
+        let line = u16::from(self.regs.ly) + 16;
+
+        self.sprites.clear();
+        for entry in self.oam.chunks_exact(4) {
+            let y = u16::from(entry[0]);
+            if y <= line && line < y + height {
+                self.sprites.push(Sprite {
+                    y: entry[0],
+                    x: entry[1],
+                    tile: entry[2],
+                    attributes: entry[3],
+                });
+                if self.sprites.len() == MAX_SPRITES_PER_LINE {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Saves the registers, memory, screen and where the PPU is in the frame, along with the
+    /// renderer's progress through the line.
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        let regs = &self.regs;
+        state.bytes(&[
+            regs.lcdc, regs.stat, regs.scy, regs.scx, regs.ly, regs.lyc, regs.bgp, regs.obp0,
+            regs.obp1, regs.wy, regs.wx,
+        ]);
+        state.bytes(&self.vram);
+        state.bytes(&self.oam);
+        state.bytes(&self.framebuffer);
+        state.u8(self.mode as u8);
+        state.u16(self.line_dot);
+        state.u8(u8::try_from(self.sprites.len()).expect("at most ten sprites"));
+        for sprite in &self.sprites {
+            state.bytes(&[sprite.y, sprite.x, sprite.tile, sprite.attributes]);
+        }
+        state.bool(self.window.y_triggered);
+        state.u8(self.window.line);
+        state.bool(self.stat_line);
+        state.bool(self.registers_written);
+
+        state.bool(self.renderer.kind() == RendererKind::PixelFifo);
+        self.renderer.save_state(state);
+    }
+
+    /// Restores what `Ppu::save_state` saved. A state saved with the other renderer can only
+    /// be loaded outside of mode 3, when there is no line in progress.
+    pub(crate) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        let mut regs = [0; 11];
+        state.read_into(&mut regs)?;
+        let [lcdc, stat, scy, scx, ly, lyc, bgp, obp0, obp1, wy, wx] = regs;
+        self.regs = LcdRegisters {
+            lcdc,
+            stat,
+            scy,
+            scx,
+            ly,
+            lyc,
+            bgp,
+            obp0,
+            obp1,
+            wy,
+            wx,
+        };
+        state.read_into(&mut self.vram)?;
+        state.read_into(&mut self.oam)?;
+        state.read_into(&mut self.framebuffer)?;
+        self.mode = match state.u8()? {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::OamScan,
+            3 => Mode::Drawing,
+            _ => return Err(state.corrupt()),
+        };
+        self.line_dot = state.u16()?;
+        let sprites = usize::from(state.u8()?);
+        let visible = self.regs.ly < VBLANK_START_LINE;
+        let mode_matches_dot = match self.mode {
+            Mode::OamScan => visible && self.line_dot < OAM_SCAN_DOTS,
+            Mode::Drawing => visible && self.line_dot >= OAM_SCAN_DOTS,
+            Mode::HBlank => visible,
+            Mode::VBlank => !visible,
+        };
+        if self.line_dot >= DOTS_PER_LINE
+            || self.regs.ly >= LINES_PER_FRAME
+            || !mode_matches_dot
+            || sprites > MAX_SPRITES_PER_LINE
+        {
+            return Err(state.corrupt());
+        }
+        self.sprites.clear();
+        for _ in 0..sprites {
+            let entry = state.bytes(4)?;
+            self.sprites.push(Sprite {
+                y: entry[0],
+                x: entry[1],
+                tile: entry[2],
+                attributes: entry[3],
+            });
+        }
+        self.window = WindowState {
+            y_triggered: state.bool()?,
+            line: state.u8()?,
+        };
+        self.stat_line = state.bool()?;
+        self.registers_written = state.bool()?;
+
+        let kind = match state.bool()? {
+            true => RendererKind::PixelFifo,
+            false => RendererKind::Scanline,
+        };
+        match (kind == self.renderer.kind(), self.mode) {
+            (true, Mode::Drawing) => {
+                self.renderer.load_state(state, self.sprites.len())?;
+                // a renderer done with mode 3 would have ended it
+                match self.renderer.remaining_dots() {
+                    Some(0) => Err(state.corrupt()),
+                    _ => Ok(()),
+                }
+            }
+            (true, _) => self.renderer.load_state(state, self.sprites.len()),
+            (false, Mode::Drawing) => Err(SaveStateError::Incompatible("renderer")),
+            // the renderer starts afresh on the next line
+            (false, _) => Ok(()),
+        }
+    }
+
+    /// Recomputes LY==LYC and the STAT interrupt line.
+    ///
+    /// # Return value
+    /// `true` on a rising edge of the STAT interrupt line.
+    const fn update_stat_line(&mut self) -> bool {
+        let lyc_equal = self.regs.ly == self.regs.lyc;
+        match lyc_equal {
+            true => self.regs.stat |= STAT_LYC_EQUAL,
+            false => self.regs.stat &= !STAT_LYC_EQUAL,
+        }
+
+        let stat = self.regs.stat;
+        let line = (lyc_equal && stat & STAT_LYC_SELECT != 0)
+            || match self.mode {
+                Mode::HBlank => stat & STAT_HBLANK_SELECT != 0,
+                Mode::VBlank => stat & (STAT_VBLANK_SELECT | STAT_OAM_SELECT) != 0,
+                Mode::OamScan => stat & STAT_OAM_SELECT != 0,
+                Mode::Drawing => false,
+            };
+
+        let rising_edge = line && !self.stat_line;
+        self.stat_line = line;
+        rising_edge
+    }
+}
+
+/// Reads the two bytes of one row of a tile.
+///
+/// # Arguments
+/// * `tile` - Tile number from the tile map or OAM
+/// * `row` - Row within the tile, `0..8` (or `0..16` for tall sprites)
+/// * `unsigned` - `true` for the `0x8000` addressing mode used by objects and `LCDC.4 == 1`
+fn tile_row(vram: &[u8], tile: u8, row: u8, unsigned: bool) -> (u8, u8) {
+    // in signed mode tiles 0..=127 come from 0x9000 and 128..=255 from 0x8800
+    let base = match unsigned || tile >= 0x80 {
+        true => usize::from(tile) * 16,
+        false => 0x1000 + usize::from(tile) * 16,
+    };
+    let offset = base + usize::from(row) * 2;
+    (vram[offset], vram[offset + 1])
+}
+
+/// Colour index `0..=3` of pixel `bit` (7 is leftmost) in a tile row.
+const fn color_index(low: u8, high: u8, bit: u8) -> u8 {
+    (((high >> bit) & 1) << 1) | ((low >> bit) & 1)
+}
+
+/// Maps a colour index through one of BGP, OBP0 or OBP1 to a shade.
+const fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
+
+/// Reads the tile number for map column `x` (`0..32`) and map row `y` (`0..32`).
+fn map_tile(vram: &[u8], high_map: bool, x: u8, y: u8) -> u8 {
+    let base = match high_map {
+        true => 0x1C00,
+        false => 0x1800,
+    };
+    vram[base + usize::from(y & 31) * 32 + usize::from(x & 31)]
+}
+
+/// Reads the row of a sprite that overlaps line `ly`, applying flips and 8x16 mode.
+fn sprite_row(vram: &[u8], regs: &LcdRegisters, sprite: Sprite) -> (u8, u8) {
+    let tall = regs.flag(LCDC_OBJ_SIZE);
+    let height: u8 = match tall {
+        true => 16,
+        false => 8,
+    };
+    // the size can change after OAM scan picked the sprite, so the row is wrapped to it
+    let mut row = (regs.ly + 16).wrapping_sub(sprite.y) & (height - 1);
+    if sprite.attributes & OBJ_Y_FLIP != 0 {
+        row = height - 1 - row;
+    }
+    let tile = match tall {
+        true => sprite.tile & 0xFE,
+        false => sprite.tile,
+    };
+
+    let (low, high) = tile_row(vram, tile, row, true);
+    match sprite.attributes & OBJ_X_FLIP != 0 {
+        true => (low.reverse_bits(), high.reverse_bits()),
+        false => (low, high),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,443 @@
+use super::{
+    apply_palette, color_index, map_tile, sprite_row, tile_row, LineContext, Renderer,
+    RendererKind, LCDC_BG_ENABLE, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_TILE_DATA, LCDC_WINDOW_ENABLE,
+    LCDC_WINDOW_MAP, MAX_SPRITES_PER_LINE, OBJ_BG_PRIORITY, OBJ_PALETTE, SCREEN_WIDTH,
+};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use std::{collections::VecDeque, convert::TryFrom};
+
+/// The first tile fetched on every line is thrown away, delaying the first pixel by six dots.
+const STARTUP_DOTS: u8 = 6;
+/// Dots spent fetching a sprite's tile row, during which no pixels are shifted out.
+const SPRITE_FETCH_DOTS: u8 = 6;
+
+/// The background fetcher's steps. Each of the first three takes two dots, `Push` is retried
+/// every dot until the background FIFO is empty.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FetchStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+/// A pixel waiting in the object FIFO.
+#[derive(Debug, Default, Copy, Clone)]
+struct ObjPixel {
+    color: u8,
+    attributes: u8,
+}
+
+struct Fetcher {
+    step: FetchStep,
+    /// Dots spent in the current step
+    ticks: u8,
+    /// Tile column, counted from the left edge of the background or window
+    x: u8,
+    /// Fetching from the window rather than the background
+    window: bool,
+    tile: u8,
+    low: u8,
+    high: u8,
+}
+
+impl Fetcher {
+    const fn new(window: bool) -> Self {
+        Self {
+            step: FetchStep::Tile,
+            ticks: 0,
+            x: 0,
+            window,
+            tile: 0,
+            low: 0,
+            high: 0,
+        }
+    }
+
+    /// Tile map coordinates and map select for the tile being fetched.
+    const fn map_position(&self, ctx: &LineContext<'_>) -> (u8, u8, bool) {
+        let regs = ctx.regs;
+        match self.window {
+            true => (self.x, ctx.window.line, regs.flag(LCDC_WINDOW_MAP)),
+            false => (
+                (regs.scx / 8).wrapping_add(self.x),
+                regs.ly.wrapping_add(regs.scy),
+                regs.flag(LCDC_BG_MAP),
+            ),
+        }
+    }
+
+    /// Advances the fetcher by one dot, pushing a row of 8 pixels into `fifo` when it is empty.
+    fn step(&mut self, ctx: &LineContext<'_>, fifo: &mut VecDeque<u8>) {
+        if self.step == FetchStep::Push {
+            if fifo.is_empty() {
+                fifo.extend(
+                    (0..8)
+                        .rev()
+                        .map(|bit| color_index(self.low, self.high, bit)),
+                );
+                self.x = self.x.wrapping_add(1);
+                self.step = FetchStep::Tile;
+            }
+            return;
+        }
+
+        self.ticks += 1;
+        if self.ticks < 2 {
+            return;
+        }
+        self.ticks = 0;
+
+        let (map_x, map_y, high_map) = self.map_position(ctx);
+        let unsigned = ctx.regs.flag(LCDC_TILE_DATA);
+        self.step = match self.step {
+            FetchStep::Tile => {
+                self.tile = map_tile(ctx.vram, high_map, map_x, map_y / 8);
+                FetchStep::DataLow
+            }
+            FetchStep::DataLow => {
+                self.low = tile_row(ctx.vram, self.tile, map_y % 8, unsigned).0;
+                FetchStep::DataHigh
+            }
+            FetchStep::DataHigh => {
+                self.high = tile_row(ctx.vram, self.tile, map_y % 8, unsigned).1;
+                FetchStep::Push
+            }
+            FetchStep::Push => FetchStep::Push,
+        };
+    }
+}
+
+/// A sprite fetch in progress.
+struct SpriteFetch {
+    /// Index into `LineContext::sprites`
+    sprite: usize,
+    /// Dots left waiting for the background fetcher, which keeps running
+    wait: u8,
+    /// Dots left fetching the sprite, with the background fetcher paused
+    remaining: u8,
+}
+
+/// Shifts pixels out of the background and object FIFOs one dot at a time.
+///
+/// Mode 3 lasts 172 dots plus:
+/// * `SCX % 8` dots discarding the fine-scrolled pixels of the first tile
+/// * 6 dots for the window to restart the fetcher
+/// * 6 to 11 dots for every sprite fetched
+pub(super) struct FifoRenderer {
+    bg_fifo: VecDeque<u8>,
+    obj_fifo: VecDeque<ObjPixel>,
+    fetcher: Fetcher,
+    /// Pixels shifted out to the LCD so far
+    lx: u8,
+    /// Fine scroll pixels still to be thrown away
+    discard: u8,
+    /// Dots left before the fetcher starts
+    delay: u8,
+    window_drawn: bool,
+    sprite_fetch: Option<SpriteFetch>,
+    /// Sprites already fetched on this line, indexed like `LineContext::sprites`
+    fetched: [bool; MAX_SPRITES_PER_LINE],
+    /// Background tile (in the scrolled 256 pixel line) that last delayed a sprite fetch
+    penalised_tile: Option<u16>,
+}
+
+impl FifoRenderer {
+    pub(super) fn new() -> Self {
+        Self {
+            bg_fifo: VecDeque::with_capacity(16),
+            obj_fifo: VecDeque::with_capacity(16),
+            fetcher: Fetcher::new(false),
+            lx: 0,
+            discard: 0,
+            delay: 0,
+            window_drawn: false,
+            sprite_fetch: None,
+            fetched: [false; MAX_SPRITES_PER_LINE],
+            penalised_tile: None,
+        }
+    }
+
+    fn window_reached(&self, ctx: &LineContext<'_>) -> bool {
+        let regs = ctx.regs;
+        !self.fetcher.window
+            && regs.flag(LCDC_WINDOW_ENABLE)
+            && ctx.window.y_triggered
+            && u16::from(self.lx) + 7 >= u16::from(regs.wx)
+    }
+
+    /// Finds an unfetched sprite starting at the current pixel. The lowest X wins, then the
+    /// earlier OAM entry.
+    fn sprite_hit(&self, ctx: &LineContext<'_>) -> Option<usize> {
+        if !ctx.regs.flag(LCDC_OBJ_ENABLE) {
+            return None;
+        }
+
+        ctx.sprites
+            .iter()
+            .enumerate()
+            .filter(|(i, sprite)| {
+                let start = sprite.x.saturating_sub(8);
+                !self.fetched[*i] && sprite.x < 168 && start == self.lx
+            })
+            .min_by_key(|(_, sprite)| sprite.x)
+            .map(|(i, _)| i)
+    }
+
+    /// Starts fetching sprite `index`. The first sprite in a background tile also waits for the
+    /// background fetcher, for up to 5 dots, fewer the further into the tile it starts.
+    fn start_sprite_fetch(&mut self, ctx: &LineContext<'_>, index: usize) {
+        self.fetched[index] = true;
+
+        let position = u16::from(ctx.sprites[index].x) + u16::from(ctx.regs.scx);
+        let tile = position / 8;
+        let wait = if self.penalised_tile == Some(tile) {
+            0
+        } else {
+            self.penalised_tile = Some(tile);
+            // pixels of the tile strictly right of the sprite's leftmost pixel
+            let right = 7 - position % 8;
+            right.saturating_sub(2)
+        };
+
+        // this dot is the first of the fetch
+        let mut fetch = SpriteFetch {
+            sprite: index,
+            wait: wait.to_le_bytes()[0],
+            remaining: SPRITE_FETCH_DOTS,
+        };
+        match fetch.wait {
+            0 => fetch.remaining -= 1,
+            _ => fetch.wait -= 1,
+        }
+        self.sprite_fetch = Some(fetch);
+    }
+
+    /// Mixes the fetched sprite row into the object FIFO. Pixels already held by a sprite with
+    /// higher priority are kept.
+    fn merge_sprite(&mut self, ctx: &LineContext<'_>, index: usize) {
+        let sprite = ctx.sprites[index];
+        let (low, high) = sprite_row(ctx.vram, ctx.regs, sprite);
+        let skip = 8u8.saturating_sub(sprite.x);
+
+        while self.obj_fifo.len() < 8 {
+            self.obj_fifo.push_back(ObjPixel::default());
+        }
+
+        // pixels left of the screen edge are dropped
+        for (slot, column) in (skip..8).enumerate() {
+            let color = color_index(low, high, 7 - column);
+            let pixel = &mut self.obj_fifo[slot];
+            if pixel.color == 0 && color != 0 {
+                *pixel = ObjPixel {
+                    color,
+                    attributes: sprite.attributes,
+                };
+            }
+        }
+    }
+
+    /// Mixes the next background and object pixels and writes the shade to the line.
+    fn output_pixel(&mut self, ctx: &mut LineContext<'_>, bg: u8) {
+        let regs = ctx.regs;
+        let bg = match regs.flag(LCDC_BG_ENABLE) {
+            true => bg,
+            false => 0,
+        };
+        let obj = self.obj_fifo.pop_front().unwrap_or_default();
+
+        let shade = match obj.color != 0
+            && regs.flag(LCDC_OBJ_ENABLE)
+            && (obj.attributes & OBJ_BG_PRIORITY == 0 || bg == 0)
+        {
+            true => {
+                let palette = match obj.attributes & OBJ_PALETTE != 0 {
+                    true => regs.obp1,
+                    false => regs.obp0,
+                };
+                apply_palette(palette, obj.color)
+            }
+            false => apply_palette(regs.bgp, bg),
+        };
+
+        ctx.line[usize::from(self.lx)] = shade;
+        self.lx += 1;
+    }
+}
+
+impl Renderer for FifoRenderer {
+    fn start_line(&mut self, ctx: &LineContext<'_>) {
+        self.bg_fifo.clear();
+        self.obj_fifo.clear();
+        self.fetcher = Fetcher::new(false);
+        self.lx = 0;
+        self.discard = ctx.regs.scx % 8;
+        self.delay = STARTUP_DOTS;
+        self.window_drawn = false;
+        self.sprite_fetch = None;
+        self.fetched = [false; MAX_SPRITES_PER_LINE];
+        self.penalised_tile = None;
+    }
+
+    fn dot(&mut self, ctx: &mut LineContext<'_>) -> bool {
+        if let Some(fetch) = &mut self.sprite_fetch {
+            if fetch.wait > 0 {
+                fetch.wait -= 1;
+                self.fetcher.step(ctx, &mut self.bg_fifo);
+            } else {
+                fetch.remaining -= 1;
+                if fetch.remaining == 0 {
+                    let index = fetch.sprite;
+                    self.sprite_fetch = None;
+                    self.merge_sprite(ctx, index);
+                }
+            }
+            return false;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return false;
+        }
+
+        if self.discard == 0 && self.window_reached(ctx) {
+            self.bg_fifo.clear();
+            self.fetcher = Fetcher::new(true);
+            self.window_drawn = true;
+        }
+
+        self.fetcher.step(ctx, &mut self.bg_fifo);
+
+        if self.bg_fifo.is_empty() {
+            return false;
+        }
+
+        if self.discard > 0 {
+            self.bg_fifo.pop_front();
+            self.discard -= 1;
+            return false;
+        }
+
+        if let Some(index) = self.sprite_hit(ctx) {
+            self.start_sprite_fetch(ctx, index);
+            return false;
+        }
+
+        if let Some(bg) = self.bg_fifo.pop_front() {
+            self.output_pixel(ctx, bg);
+        }
+
+        usize::from(self.lx) == SCREEN_WIDTH
+    }
+
+    fn window_drawn(&self) -> bool {
+        self.window_drawn
+    }
+
+    fn kind(&self) -> RendererKind {
+        RendererKind::PixelFifo
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(u8::try_from(self.bg_fifo.len()).expect("FIFO holds 16 pixels at most"));
+        for &color in &self.bg_fifo {
+            state.u8(color);
+        }
+        state.u8(u8::try_from(self.obj_fifo.len()).expect("FIFO holds 16 pixels at most"));
+        for pixel in &self.obj_fifo {
+            state.u8(pixel.color);
+            state.u8(pixel.attributes);
+        }
+
+        let fetcher = &self.fetcher;
+        let step = match fetcher.step {
+            FetchStep::Tile => 0,
+            FetchStep::DataLow => 1,
+            FetchStep::DataHigh => 2,
+            FetchStep::Push => 3,
+        };
+        state.bytes(&[step, fetcher.ticks, fetcher.x]);
+        state.bool(fetcher.window);
+        state.bytes(&[fetcher.tile, fetcher.low, fetcher.high]);
+
+        state.bytes(&[self.lx, self.discard, self.delay]);
+        state.bool(self.window_drawn);
+        state.bool(self.sprite_fetch.is_some());
+        if let Some(fetch) = &self.sprite_fetch {
+            state.u8(u8::try_from(fetch.sprite).expect("at most ten sprites"));
+            state.u8(fetch.wait);
+            state.u8(fetch.remaining);
+        }
+        for &fetched in &self.fetched {
+            state.bool(fetched);
+        }
+        state.bool(self.penalised_tile.is_some());
+        state.u16(self.penalised_tile.unwrap_or_default());
+    }
+
+    fn load_state(
+        &mut self,
+        state: &mut StateReader<'_>,
+        sprites: usize,
+    ) -> Result<(), SaveStateError> {
+        let pixels = state.u8()?;
+        self.bg_fifo.clear();
+        for _ in 0..pixels {
+            self.bg_fifo.push_back(state.u8()?);
+        }
+        let pixels = state.u8()?;
+        self.obj_fifo.clear();
+        for _ in 0..pixels {
+            self.obj_fifo.push_back(ObjPixel {
+                color: state.u8()?,
+                attributes: state.u8()?,
+            });
+        }
+
+        let step = match state.u8()? {
+            0 => FetchStep::Tile,
+            1 => FetchStep::DataLow,
+            2 => FetchStep::DataHigh,
+            3 => FetchStep::Push,
+            _ => return Err(state.corrupt()),
+        };
+        self.fetcher = Fetcher {
+            step,
+            ticks: state.u8()?,
+            x: state.u8()?,
+            window: state.bool()?,
+            tile: state.u8()?,
+            low: state.u8()?,
+            high: state.u8()?,
+        };
+
+        self.lx = state.u8()?;
+        self.discard = state.u8()?;
+        self.delay = state.u8()?;
+        self.window_drawn = state.bool()?;
+        self.sprite_fetch = match state.bool()? {
+            true => Some(SpriteFetch {
+                sprite: usize::from(state.u8()?),
+                wait: state.u8()?,
+                remaining: state.u8()?,
+            }),
+            false => None,
+        };
+        for fetched in &mut self.fetched {
+            *fetched = state.bool()?;
+        }
+        let (penalised, tile) = (state.bool()?, state.u16()?);
+        self.penalised_tile = match penalised {
+            true => Some(tile),
+            false => None,
+        };
+
+        let sprite_in_range = self.sprite_fetch.as_ref().is_none_or(|fetch| {
+            fetch.sprite < sprites && (1..=SPRITE_FETCH_DOTS).contains(&fetch.remaining)
+        });
+        match sprite_in_range && usize::from(self.lx) <= SCREEN_WIDTH {
+            true => Ok(()),
+            false => Err(state.corrupt()),
+        }
+    }
+}
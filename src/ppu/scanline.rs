@@ -0,0 +1,152 @@
+use super::{
+    apply_palette, color_index, map_tile, sprite_row, tile_row, LineContext, Renderer,
+    RendererKind, Sprite, LCDC_BG_ENABLE, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_TILE_DATA,
+    LCDC_WINDOW_ENABLE, LCDC_WINDOW_MAP, OBJ_BG_PRIORITY, OBJ_PALETTE, SCREEN_WIDTH,
+};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+/// Mode 3 always lasts the minimum 172 dots with this renderer.
+const MODE_3_DOTS: u16 = 172;
+
+/// Renders the whole line on the first dot of mode 3, then idles until the end of the mode.
+pub(super) struct ScanlineRenderer {
+    dot: u16,
+    window_drawn: bool,
+}
+
+impl ScanlineRenderer {
+    pub(super) const fn new() -> Self {
+        Self {
+            dot: 0,
+            window_drawn: false,
+        }
+    }
+
+    fn render_line(&mut self, ctx: &mut LineContext<'_>) {
+        let regs = ctx.regs;
+        let mut bg_colors = [0u8; SCREEN_WIDTH];
+
+        if regs.flag(LCDC_BG_ENABLE) {
+            let window_x = match Self::window_visible(ctx) {
+                true => Some(regs.wx.saturating_sub(7)),
+                false => None,
+            };
+            let unsigned = regs.flag(LCDC_TILE_DATA);
+
+            for (x, color) in (0u8..).zip(bg_colors.iter_mut()) {
+                let (map_x, map_y, high_map) = match window_x {
+                    Some(wx) if x >= wx => (x - wx, ctx.window.line, regs.flag(LCDC_WINDOW_MAP)),
+                    _ => (
+                        x.wrapping_add(regs.scx),
+                        regs.ly.wrapping_add(regs.scy),
+                        regs.flag(LCDC_BG_MAP),
+                    ),
+                };
+
+                let tile = map_tile(ctx.vram, high_map, map_x / 8, map_y / 8);
+                let (low, high) = tile_row(ctx.vram, tile, map_y % 8, unsigned);
+                *color = color_index(low, high, 7 - map_x % 8);
+            }
+
+            self.window_drawn = window_x.is_some();
+        }
+
+        for (pixel, color) in ctx.line.iter_mut().zip(bg_colors.iter()) {
+            *pixel = apply_palette(regs.bgp, *color);
+        }
+
+        if regs.flag(LCDC_OBJ_ENABLE) {
+            Self::render_sprites(ctx, &bg_colors);
+        }
+    }
+
+    const fn window_visible(ctx: &LineContext<'_>) -> bool {
+        ctx.regs.flag(LCDC_WINDOW_ENABLE) && ctx.window.y_triggered && ctx.regs.wx < 167
+    }
+
+    /// Draws sprites over the background. On DMG the sprite with the lowest X wins, ties going to
+    /// the earlier OAM entry, and a transparent pixel lets the next sprite show through.
+    fn render_sprites(ctx: &mut LineContext<'_>, bg_colors: &[u8; SCREEN_WIDTH]) {
+        let mut order: Vec<&Sprite> = ctx.sprites.iter().collect();
+        order.sort_by_key(|sprite| sprite.x); // stable, so OAM order breaks ties
+
+        for (x, bg_color) in (0u8..).zip(bg_colors.iter()) {
+            let screen_x = u16::from(x) + 8;
+            for sprite in &order {
+                let sprite_x = u16::from(sprite.x);
+                if screen_x < sprite_x || screen_x >= sprite_x + 8 {
+                    continue;
+                }
+
+                let (low, high) = sprite_row(ctx.vram, ctx.regs, **sprite);
+                let column = (screen_x - sprite_x).to_le_bytes()[0];
+                let color = color_index(low, high, 7 - column);
+                if color == 0 {
+                    continue;
+                }
+
+                if sprite.attributes & OBJ_BG_PRIORITY == 0 || *bg_color == 0 {
+                    let palette = match sprite.attributes & OBJ_PALETTE != 0 {
+                        true => ctx.regs.obp1,
+                        false => ctx.regs.obp0,
+                    };
+                    ctx.line[usize::from(x)] = apply_palette(palette, color);
+                }
+                break;
+            }
+        }
+    }
+}
+
+impl Renderer for ScanlineRenderer {
+    fn start_line(&mut self, _ctx: &LineContext<'_>) {
+        self.dot = 0;
+        self.window_drawn = false;
+    }
+
+    fn dot(&mut self, ctx: &mut LineContext<'_>) -> bool {
+        if self.dot == 0 {
+            self.render_line(ctx);
+        }
+        self.dot += 1;
+        self.dot == MODE_3_DOTS
+    }
+
+    fn window_drawn(&self) -> bool {
+        self.window_drawn
+    }
+
+    fn remaining_dots(&self) -> Option<u16> {
+        match self.dot {
+            0 => None, // the line is yet to be rendered
+            dot => Some(MODE_3_DOTS - dot),
+        }
+    }
+
+    fn skip(&mut self, dots: u16) {
+        self.dot += dots;
+    }
+
+    fn kind(&self) -> RendererKind {
+        RendererKind::Scanline
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.dot);
+        state.bool(self.window_drawn);
+    }
+
+    fn load_state(
+        &mut self,
+        state: &mut StateReader<'_>,
+        _sprites: usize,
+    ) -> Result<(), SaveStateError> {
+        self.dot = state.u16()?;
+        self.window_drawn = state.bool()?;
+        // the dot stays at the end of mode 3 until the next line starts
+        match self.dot <= MODE_3_DOTS {
+            true => Ok(()),
+            false => Err(state.corrupt()),
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use super::{
+    Interrupt, Mode, Ppu, RendererKind, DOTS_PER_LINE, LCDC_BG_ENABLE, LCDC_LCD_ENABLE,
+    LCDC_OBJ_ENABLE, LCDC_OBJ_SIZE, LCDC_TILE_DATA, LCDC_WINDOW_ENABLE, LCDC_WINDOW_MAP, OAM_SIZE,
+    OBJ_Y_FLIP, SCREEN_HEIGHT, SCREEN_WIDTH, STAT_LYC_EQUAL, STAT_LYC_SELECT, VRAM_SIZE,
+};
+use crate::savestate::{SaveState, SaveStateError, StateWriter};
+
+const LCDC_ON: u8 = LCDC_LCD_ENABLE | LCDC_TILE_DATA | LCDC_BG_ENABLE;
+const LINE: u32 = 456;
+const DOTS_PER_FRAME: u32 = LINE * 154;
+
+/// PPU with tile 0 filled with colour 3, tile 1 with colour 1 and an identity palette
+fn setup(kind: RendererKind) -> Ppu {
+    let mut ppu = Ppu::new(kind);
+    for address in 0x8000..0x8010 {
+        ppu.write_vram(address, 0xFF);
+    }
+    for address in (0x8010..0x8020).step_by(2) {
+        ppu.write_vram(address, 0xFF);
+    }
+    ppu.write_register(0xFF47, 0b_11_10_01_00);
+    ppu.write_register(0xFF48, 0b_11_10_01_00);
+    ppu
+}
+
+/// Runs to the start of line 1 and returns how many dots mode 3 lasted on line 0.
+fn mode_3_length(ppu: &mut Ppu) -> u32 {
+    let mut length = 0;
+    for _ in 0..DOTS_PER_LINE {
+        if ppu.mode() == Mode::Drawing {
+            length += 1;
+        }
+        ppu.tick(1);
+    }
+    length
+}
+
+fn place_sprite(ppu: &mut Ppu, index: u16, x: u8, y: u8, tile: u8) {
+    let base = 0xFE00 + index * 4;
+    ppu.write_oam(base, y);
+    ppu.write_oam(base + 1, x);
+    ppu.write_oam(base + 2, tile);
+    ppu.write_oam(base + 3, 0);
+}
+
+#[test]
+fn ppu_lcd_off_does_not_advance() {
+    let mut ppu = setup(RendererKind::Scanline);
+
+    assert_eq!(0, ppu.tick(DOTS_PER_FRAME));
+    assert_eq!(0, ppu.read_register(0xFF44));
+    assert_eq!(Mode::HBlank, ppu.mode());
+}
+
+#[test]
+fn ppu_frame_timing() {
+    for kind in &[RendererKind::Scanline, RendererKind::PixelFifo] {
+        let mut ppu = setup(*kind);
+        ppu.write_register(0xFF40, LCDC_ON);
+
+        assert_eq!(Mode::OamScan, ppu.mode());
+        assert_eq!(0, ppu.tick(LINE * 144 - 1));
+        assert_eq!(143, ppu.read_register(0xFF44));
+
+        assert_eq!(Interrupt::VBlank.bit(), ppu.tick(1));
+        assert_eq!(144, ppu.read_register(0xFF44));
+        assert_eq!(Mode::VBlank, ppu.mode());
+
+        ppu.tick(LINE * 10);
+        assert_eq!(0, ppu.read_register(0xFF44));
+        assert_eq!(Mode::OamScan, ppu.mode());
+    }
+}
+
+#[test]
+fn ppu_scanline_mode_3_fixed_length() {
+    let mut ppu = setup(RendererKind::Scanline);
+    ppu.write_register(0xFF43, 5);
+    ppu.write_register(0xFF40, LCDC_ON | LCDC_OBJ_ENABLE);
+    place_sprite(&mut ppu, 0, 20, 16, 0);
+
+    assert_eq!(172, mode_3_length(&mut ppu));
+}
+
+#[test]
+fn ppu_fifo_mode_3_length_scx() {
+    for scx in 0..16 {
+        let mut ppu = setup(RendererKind::PixelFifo);
+        ppu.write_register(0xFF43, scx);
+        ppu.write_register(0xFF40, LCDC_ON);
+
+        assert_eq!(
+            172 + u32::from(scx % 8),
+            mode_3_length(&mut ppu),
+            "SCX {scx}"
+        );
+    }
+}
+
+#[test]
+fn ppu_fifo_mode_3_length_window() {
+    let mut ppu = setup(RendererKind::PixelFifo);
+    ppu.write_register(0xFF4A, 0);
+    ppu.write_register(0xFF4B, 87);
+    ppu.write_register(0xFF40, LCDC_ON | LCDC_WINDOW_ENABLE);
+
+    assert_eq!(178, mode_3_length(&mut ppu));
+}
+
+#[test]
+fn ppu_fifo_mode_3_length_sprites() {
+    let test_cases: &[(&[u8], u32)] = &[
+        // sprite X positions, expected length
+        (&[8], 183),
+        (&[15], 178),
+        (&[8, 8], 189),
+        (&[20, 60, 100], 172 + 3 * 6 + 3),
+    ];
+
+    for (xs, expected) in test_cases {
+        let mut ppu = setup(RendererKind::PixelFifo);
+        ppu.write_register(0xFF40, LCDC_ON | LCDC_OBJ_ENABLE);
+        for (i, x) in (0..).zip(xs.iter()) {
+            place_sprite(&mut ppu, i, *x, 16, 1);
+        }
+
+        assert_eq!(*expected, mode_3_length(&mut ppu), "sprites at {xs:?}");
+    }
+}
+
+#[test]
+fn ppu_fifo_load_state_checks_sprite_fetch() {
+    // header, section tag and length, then registers, VRAM, OAM, framebuffer, mode and dot
+    const SPRITE_COUNT: usize =
+        14 + 8 + 11 + VRAM_SIZE + OAM_SIZE + SCREEN_WIDTH * SCREEN_HEIGHT + 1 + 2;
+
+    let mut ppu = setup(RendererKind::PixelFifo);
+    ppu.write_register(0xFF40, LCDC_ON | LCDC_OBJ_ENABLE);
+    place_sprite(&mut ppu, 0, 8, 16, 1);
+    while ppu.mode() != Mode::Drawing {
+        ppu.tick(1);
+    }
+
+    // the line's sprite taken out of states saved through mode 3, some fetching it
+    let mut rejected = 0;
+    while ppu.mode() == Mode::Drawing {
+        let mut writer = StateWriter::new(0);
+        writer.section("PPU ", |state| ppu.save_state(state));
+        let mut bytes = writer.finish();
+        assert_eq!(1, bytes[SPRITE_COUNT]);
+        bytes[SPRITE_COUNT] = 0;
+        bytes.drain(SPRITE_COUNT + 1..SPRITE_COUNT + 5);
+        let length = u32::from_le_bytes([bytes[18], bytes[19], bytes[20], bytes[21]]) - 4;
+        bytes[18..22].copy_from_slice(&length.to_le_bytes());
+
+        let state = SaveState::parse(&bytes).unwrap();
+        let mut other = Ppu::new(RendererKind::PixelFifo);
+        match other.load_state(&mut state.section("PPU ").unwrap()) {
+            Ok(()) => {
+                other.tick(LINE);
+            }
+            Err(error) => {
+                assert_eq!(SaveStateError::Corrupt("PPU "), error);
+                rejected += 1;
+            }
+        }
+        ppu.tick(1);
+    }
+    assert!(rejected > 0);
+}
+
+#[test]
+fn ppu_sprite_size_change_during_mode_3() {
+    for kind in &[RendererKind::Scanline, RendererKind::PixelFifo] {
+        let mut ppu = setup(*kind);
+        ppu.write_register(0xFF40, LCDC_ON | LCDC_OBJ_ENABLE | LCDC_OBJ_SIZE);
+        // an 8x16 sprite flipped vertically, row 10 of which is on line 0
+        place_sprite(&mut ppu, 0, 8, 6, 0);
+        ppu.write_oam(0xFE03, OBJ_Y_FLIP);
+        while ppu.mode() != Mode::Drawing {
+            ppu.tick(1);
+        }
+
+        // back to 8x8 after OAM scan picked the sprite
+        ppu.write_register(0xFF40, LCDC_ON | LCDC_OBJ_ENABLE);
+        ppu.tick(LINE);
+        assert_eq!(1, ppu.read_register(0xFF44), "{kind:?}");
+    }
+}
+
+/// Offset of the mode in a state holding only the PPU section
+const MODE_OFFSET: usize = 14 + 8 + 11 + VRAM_SIZE + OAM_SIZE + SCREEN_WIDTH * SCREEN_HEIGHT;
+
+fn saved(ppu: &Ppu) -> Vec<u8> {
+    let mut writer = StateWriter::new(0);
+    writer.section("PPU ", |state| ppu.save_state(state));
+    writer.finish()
+}
+
+fn load(kind: RendererKind, bytes: &[u8]) -> Result<Ppu, SaveStateError> {
+    let state = SaveState::parse(bytes).unwrap();
+    let mut ppu = Ppu::new(kind);
+    ppu.load_state(&mut state.section("PPU ").unwrap())?;
+    Ok(ppu)
+}
+
+#[test]
+fn ppu_load_state_through_a_frame() {
+    for kind in &[RendererKind::Scanline, RendererKind::PixelFifo] {
+        let mut ppu = setup(*kind);
+        ppu.write_register(0xFF40, LCDC_ON | LCDC_OBJ_ENABLE);
+        place_sprite(&mut ppu, 0, 8, 16, 1);
+        for _ in (0..DOTS_PER_FRAME).step_by(3) {
+            let mut other = load(*kind, &saved(&ppu)).unwrap();
+            other.tick(LINE);
+            ppu.tick(3);
+        }
+    }
+}
+
+#[test]
+fn ppu_load_state_checks_mode_and_renderer() {
+    let corrupt = Err(SaveStateError::Corrupt("PPU "));
+
+    // OAM scan past its 80 dots
+    let mut ppu = setup(RendererKind::Scanline);
+    ppu.write_register(0xFF40, LCDC_ON);
+    ppu.tick(10);
+    let mut bytes = saved(&ppu);
+    bytes[MODE_OFFSET + 1..MODE_OFFSET + 3].copy_from_slice(&300u16.to_le_bytes());
+    assert_eq!(corrupt, load(RendererKind::Scanline, &bytes).map(|_| ()));
+
+    // the scanline renderer done with mode 3 while still in it
+    ppu.tick(100);
+    assert_eq!(Mode::Drawing, ppu.mode());
+    let mut bytes = saved(&ppu);
+    let dot = bytes.len() - 3;
+    bytes[dot..dot + 2].copy_from_slice(&172u16.to_le_bytes());
+    assert_eq!(corrupt, load(RendererKind::Scanline, &bytes).map(|_| ()));
+
+    // a sprite fetch with no dots left; its remaining dots come 14 bytes from the end, where
+    // a state without a fetch holds 0
+    let mut ppu = setup(RendererKind::PixelFifo);
+    ppu.write_register(0xFF40, LCDC_ON | LCDC_OBJ_ENABLE);
+    place_sprite(&mut ppu, 0, 8, 16, 1);
+    let mut bytes = saved(&ppu);
+    while bytes[bytes.len() - 14] == 0 {
+        ppu.tick(1);
+        bytes = saved(&ppu);
+    }
+    let remaining = bytes.len() - 14;
+    bytes[remaining] = 0;
+    assert_eq!(corrupt, load(RendererKind::PixelFifo, &bytes).map(|_| ()));
+}
+
+#[test]
+fn ppu_renderers_agree_on_static_scene() {
+    let mut frames = Vec::new();
+    for kind in &[RendererKind::Scanline, RendererKind::PixelFifo] {
+        let mut ppu = setup(*kind);
+        // checkerboard of tiles 0 and 1 across the map
+        for i in 0..0x400u16 {
+            ppu.write_vram(0x9800 + i, ((i + i / 32) % 2) as u8);
+        }
+        ppu.write_register(0xFF42, 3);
+        ppu.write_register(0xFF43, 5);
+        ppu.write_register(0xFF4A, 100);
+        ppu.write_register(0xFF4B, 60);
+        place_sprite(&mut ppu, 0, 30, 40, 1);
+        place_sprite(&mut ppu, 1, 4, 60, 0);
+        ppu.write_register(
+            0xFF40,
+            LCDC_ON | LCDC_OBJ_ENABLE | LCDC_WINDOW_ENABLE | LCDC_WINDOW_MAP,
+        );
+
+        ppu.tick(DOTS_PER_FRAME);
+        frames.push(ppu.framebuffer().to_vec());
+    }
+
+    assert_eq!(frames[0], frames[1]);
+    assert_eq!(3, frames[0][0]);
+}
+
+#[test]
+fn ppu_fifo_mid_scanline_palette_write() {
+    // the first pixel of line 0 is shifted out on dot 92
+    const FIRST_PIXEL_DOT: u32 = 92;
+
+    for (kind, expected) in &[(RendererKind::Scanline, 3), (RendererKind::PixelFifo, 0)] {
+        let mut ppu = setup(*kind);
+        ppu.write_register(0xFF40, LCDC_ON);
+
+        ppu.tick(FIRST_PIXEL_DOT + 50);
+        ppu.write_register(0xFF47, 0);
+        ppu.tick(LINE);
+
+        let line = &ppu.framebuffer()[..SCREEN_WIDTH];
+        assert!(line[..50].iter().all(|shade| *shade == 3));
+        assert!(line[50..].iter().all(|shade| shade == expected));
+    }
+}
+
+#[test]
+fn ppu_stat_lyc_interrupt() {
+    let mut ppu = setup(RendererKind::Scanline);
+    ppu.write_register(0xFF45, 2);
+    ppu.write_register(0xFF41, STAT_LYC_SELECT);
+    ppu.write_register(0xFF40, LCDC_ON);
+
+    assert_eq!(0, ppu.tick(LINE * 2 - 1));
+    assert_eq!(0, ppu.read_register(0xFF41) & STAT_LYC_EQUAL);
+    assert_eq!(Interrupt::LcdStat.bit(), ppu.tick(1));
+    assert_eq!(STAT_LYC_EQUAL, ppu.read_register(0xFF41) & STAT_LYC_EQUAL);
+    // no new rising edge while LY stays equal to LYC
+    assert_eq!(0, ppu.tick(LINE - 1));
+}
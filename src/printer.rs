@@ -0,0 +1,393 @@
+//! Emulates the Game Boy Printer, plugged into the serial port as a `SerialDevice`.
+//!
+//! The console talks to the printer in packets:
+//!
+//! | bytes | contents                                                        |
+//! | ----- | --------------------------------------------------------------- |
+//! | 2     | magic bytes `0x88 0x33`                                         |
+//! | 1     | command: INIT `0x01`, PRINT `0x02`, DATA `0x04`, STATUS `0x0F`  |
+//! | 1     | 1 if the data is RLE compressed, else 0                         |
+//! | 2     | data length, little-endian                                      |
+//! | n     | data                                                            |
+//! | 2     | sum of the command, compression, length and data bytes          |
+//! | 2     | `0x00` sent while the printer answers `0x81`, then its status   |
+//!
+//! DATA packets carry tiles in the 2 bits per pixel format of VRAM, 20 tiles to a row of the
+//! 160 pixel wide paper. PRINT renders everything buffered since the last print as a
+//! `PrintJob`, using the palette and margins in its data.
+use crate::utils::write_pgm;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+/// First byte the printer answers after a packet, identifying it
+const ALIVE: u8 = 0x81;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_BREAK: u8 = 0x08;
+const COMMAND_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0b_0000_0001;
+const STATUS_PRINTING: u8 = 0b_0000_0010;
+const STATUS_IMAGE_FULL: u8 = 0b_0000_0100;
+const STATUS_UNPROCESSED: u8 = 0b_0000_1000;
+const STATUS_PACKET_ERROR: u8 = 0b_0001_0000;
+
+/// Width of the paper in pixels
+pub const PAPER_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = PAPER_WIDTH / 8;
+const BYTES_PER_TILE_ROW: usize = TILES_PER_ROW * 16;
+/// Image RAM of the printer
+const BUFFER_SIZE: usize = 0x2000;
+/// A full screen of tiles, 160 by 144 pixels
+const IMAGE_FULL_SIZE: usize = 18 * BYTES_PER_TILE_ROW;
+/// Blank pixel rows fed per unit of margin
+const MARGIN_ROWS: usize = 8;
+/// STATUS packets answered as busy after a print, as the printer takes a while
+const BUSY_POLLS: u8 = 2;
+
+/// Position in the packet being received
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+/// A printed image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintJob {
+    /// Shades 0 (lightest) to 3 (darkest), `PAPER_WIDTH` pixels a row, margins included
+    pixels: Vec<u8>,
+    sheets: u8,
+    /// Units of margin before and after the image
+    margins: (u8, u8),
+    palette: u8,
+    exposure: u8,
+}
+
+impl PrintJob {
+    /// Height of the image in pixels, margins included
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.pixels.len() / PAPER_WIDTH
+    }
+
+    /// The image as shades 0 (lightest) to 3 (darkest), row by row.
+    #[must_use]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Number of copies asked for
+    #[must_use]
+    pub const fn sheets(&self) -> u8 {
+        self.sheets
+    }
+
+    /// Units of margin fed before and after the image
+    #[must_use]
+    pub const fn margins(&self) -> (u8, u8) {
+        self.margins
+    }
+
+    /// Palette the tiles were printed with, in the format of BGP
+    #[must_use]
+    pub const fn palette(&self) -> u8 {
+        self.palette
+    }
+
+    /// Darkness asked for, `0x00` to `0x7F`. The image isn't affected.
+    #[must_use]
+    pub const fn exposure(&self) -> u8 {
+        self.exposure
+    }
+
+    /// Writes the image as a binary PGM file.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        write_pgm(writer, PAPER_WIDTH, self.height(), &self.pixels)
+    }
+
+    /// Saves the image to a PGM file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Game Boy Printer
+#[derive(Debug)]
+pub struct Printer {
+    stage: Stage,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    /// Sum of the packet bytes so far
+    checksum: u16,
+    received_checksum: u16,
+    /// Error bits of the status
+    errors: u8,
+    /// Decompressed tile data waiting to be printed
+    buffer: Vec<u8>,
+    busy_polls: u8,
+    jobs: Vec<PrintJob>,
+    /// Number of jobs printed so far, numbering the saved files
+    printed: usize,
+    output_dir: Option<PathBuf>,
+    save_error: Option<io::Error>,
+}
+
+impl Printer {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            stage: Stage::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            errors: 0,
+            buffer: Vec::new(),
+            busy_polls: 0,
+            jobs: Vec::new(),
+            printed: 0,
+            output_dir: None,
+            save_error: None,
+        }
+    }
+
+    /// Saves every job printed from now on to `dir` as `print_0000.pgm`, `print_0001.pgm` and so
+    /// on, or stops saving them if `dir` is `None`.
+    pub fn set_output_dir(&mut self, dir: Option<PathBuf>) {
+        self.output_dir = dir;
+    }
+
+    /// The jobs printed so far
+    #[must_use]
+    pub fn jobs(&self) -> &[PrintJob] {
+        &self.jobs
+    }
+
+    /// Takes the jobs printed so far out of the printer.
+    pub fn take_jobs(&mut self) -> Vec<PrintJob> {
+        std::mem::take(&mut self.jobs)
+    }
+
+    /// Takes the last error saving a job to the output directory, if any.
+    pub const fn take_save_error(&mut self) -> Option<io::Error> {
+        self.save_error.take()
+    }
+
+    /// Status byte sent after every packet
+    #[must_use]
+    pub const fn status(&self) -> u8 {
+        let mut status = self.errors;
+        if self.busy_polls > 0 {
+            status |= STATUS_PRINTING;
+        }
+        if self.buffer.len() >= IMAGE_FULL_SIZE {
+            status |= STATUS_IMAGE_FULL;
+        }
+        if !self.buffer.is_empty() {
+            status |= STATUS_UNPROCESSED;
+        }
+        status
+    }
+
+    /// Acts on a packet whose checksum arrived.
+    fn process_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.errors |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.errors &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            COMMAND_INIT => {
+                self.buffer.clear();
+                self.errors = 0;
+                self.busy_polls = 0;
+            }
+            COMMAND_PRINT if self.data.len() >= 4 => {
+                let parameters = [self.data[0], self.data[1], self.data[2], self.data[3]];
+                self.print(parameters);
+            }
+            COMMAND_DATA => {
+                let data = std::mem::take(&mut self.data);
+                match self.compressed {
+                    true => self.decompress(&data),
+                    false => self.buffer.extend_from_slice(&data),
+                }
+                self.buffer.truncate(BUFFER_SIZE);
+                self.data = data;
+            }
+            COMMAND_BREAK => {
+                self.buffer.clear();
+                self.busy_polls = 0;
+            }
+            COMMAND_STATUS => {}
+            _ => self.errors |= STATUS_PACKET_ERROR,
+        }
+    }
+
+    /// Appends RLE compressed `data` to the buffer. A control byte with bit 7 set repeats the
+    /// next byte its low bits plus 2 times, otherwise as many bytes as the control byte plus 1 are
+    /// copied as they are.
+    fn decompress(&mut self, data: &[u8]) {
+        let mut rest = data;
+        while let Some((&control, tail)) = rest.split_first() {
+            if control & 0x80 == 0 {
+                let count = (usize::from(control) + 1).min(tail.len());
+                self.buffer.extend_from_slice(&tail[..count]);
+                rest = &tail[count..];
+            } else {
+                let count = usize::from(control & 0x7F) + 2;
+                if let Some(&value) = tail.first() {
+                    self.buffer.resize(self.buffer.len() + count, value);
+                }
+                rest = tail.get(1..).unwrap_or_default();
+            }
+        }
+    }
+
+    /// Renders the buffered tiles as a new job and clears the buffer.
+    ///
+    /// # Arguments
+    /// * `parameters` - Number of sheets, margins before and after in the high and low
+    ///   nibbles, palette and exposure
+    fn print(&mut self, parameters: [u8; 4]) {
+        let [sheets, margins, palette, exposure] = parameters;
+        // games commonly send 0 for the usual palette
+        let palette = match palette {
+            0 => 0b_1110_0100,
+            _ => palette,
+        };
+        let (before, after) = (margins >> 4, margins & 0x0F);
+
+        let tile_rows = self.buffer.len() / BYTES_PER_TILE_ROW;
+        let mut pixels = vec![0; usize::from(before) * MARGIN_ROWS * PAPER_WIDTH];
+        for tile_row in self.buffer.chunks_exact(BYTES_PER_TILE_ROW).take(tile_rows) {
+            for line in 0..8 {
+                for tile in tile_row.chunks_exact(16) {
+                    let (low, high) = (tile[line * 2], tile[line * 2 + 1]);
+                    for bit in (0..8).rev() {
+                        let color = (((high >> bit) & 1) << 1) | ((low >> bit) & 1);
+                        pixels.push((palette >> (color * 2)) & 0b11);
+                    }
+                }
+            }
+        }
+        pixels.resize(
+            pixels.len() + usize::from(after) * MARGIN_ROWS * PAPER_WIDTH,
+            0,
+        );
+
+        let job = PrintJob {
+            pixels,
+            sheets,
+            margins: (before, after),
+            palette,
+            exposure,
+        };
+        if let Some(dir) = &self.output_dir {
+            let path = dir.join(format!("print_{:04}.pgm", self.printed));
+            if let Err(error) = job.save(path) {
+                self.save_error = Some(error);
+            }
+        }
+        self.printed += 1;
+        self.jobs.push(job);
+        self.buffer.clear();
+        self.busy_polls = BUSY_POLLS;
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl crate::serial::SerialDevice for Printer {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        let mut reply = 0x00;
+        self.stage = match self.stage {
+            Stage::Magic(index) => match (sent == MAGIC[index], index) {
+                (true, 0) => Stage::Magic(1),
+                (true, _) => Stage::Command,
+                (false, _) if sent == MAGIC[0] => Stage::Magic(1),
+                (false, _) => Stage::Magic(0),
+            },
+            Stage::Command => {
+                self.command = sent;
+                self.checksum = u16::from(sent);
+                Stage::Compression
+            }
+            Stage::Compression => {
+                self.compressed = sent & 1 != 0;
+                self.checksum = self.checksum.wrapping_add(u16::from(sent));
+                Stage::LengthLow
+            }
+            Stage::LengthLow => {
+                self.length = u16::from(sent);
+                self.checksum = self.checksum.wrapping_add(u16::from(sent));
+                Stage::LengthHigh
+            }
+            Stage::LengthHigh => {
+                self.length |= u16::from(sent) << 8;
+                self.checksum = self.checksum.wrapping_add(u16::from(sent));
+                self.data.clear();
+                match self.length {
+                    0 => Stage::ChecksumLow,
+                    _ => Stage::Data,
+                }
+            }
+            Stage::Data => {
+                self.data.push(sent);
+                self.checksum = self.checksum.wrapping_add(u16::from(sent));
+                match self.data.len() == usize::from(self.length) {
+                    true => Stage::ChecksumLow,
+                    false => Stage::Data,
+                }
+            }
+            Stage::ChecksumLow => {
+                self.received_checksum = u16::from(sent);
+                Stage::ChecksumHigh
+            }
+            Stage::ChecksumHigh => {
+                self.received_checksum |= u16::from(sent) << 8;
+                self.process_packet();
+                Stage::Alive
+            }
+            Stage::Alive => {
+                reply = ALIVE;
+                Stage::Status
+            }
+            Stage::Status => {
+                reply = self.status();
+                if self.command == COMMAND_STATUS {
+                    self.busy_polls = self.busy_polls.saturating_sub(1);
+                }
+                Stage::Magic(0)
+            }
+        };
+        reply
+    }
+}
+
+#[cfg(test)]
+mod tests;
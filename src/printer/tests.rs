@@ -0,0 +1,129 @@
+use super::{Printer, PAPER_WIDTH, STATUS_CHECKSUM_ERROR, STATUS_PRINTING, STATUS_UNPROCESSED};
+use crate::serial::{Serial, SerialDevice, SerialLink};
+use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+
+/// Sends a packet as the console would.
+///
+/// # Return value
+/// The bytes answered to the two trailing `0x00`s, the alive byte and the status.
+fn send(device: &mut dyn SerialDevice, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+    let length = u16::try_from(data.len()).unwrap().to_le_bytes();
+    let mut body = vec![command, u8::from(compressed), length[0], length[1]];
+    body.extend_from_slice(data);
+    let checksum = body
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(u16::from(byte)));
+
+    let mut packet = vec![0x88, 0x33];
+    packet.extend_from_slice(&body);
+    packet.extend_from_slice(&checksum.to_le_bytes());
+    for byte in packet {
+        assert_eq!(0x00, device.exchange(byte));
+    }
+    (device.exchange(0x00), device.exchange(0x00))
+}
+
+/// A tile with every pixel of colour `color`
+fn solid_tile(color: u8) -> [u8; 16] {
+    let low = match color & 1 {
+        1 => 0xFF,
+        _ => 0x00,
+    };
+    let high = match color & 2 {
+        2 => 0xFF,
+        _ => 0x00,
+    };
+    let mut tile = [0; 16];
+    for line in tile.chunks_exact_mut(2) {
+        line.copy_from_slice(&[low, high]);
+    }
+    tile
+}
+
+#[test]
+fn printer_packets_and_status() {
+    let mut printer = Printer::new();
+    assert_eq!((0x81, 0x00), send(&mut printer, 0x01, false, &[]));
+
+    // a row of tiles of colour 1, uncompressed
+    let row: Vec<u8> = (0..20).flat_map(|_| solid_tile(1)).collect();
+    assert_eq!(
+        (0x81, STATUS_UNPROCESSED),
+        send(&mut printer, 0x04, false, &row)
+    );
+
+    // a row of colour 3 tiles, compressed as 320 bytes of 0xFF
+    let compressed = [0xFF, 0xFF, 0xFF, 0xFF, 0xBC, 0xFF];
+    send(&mut printer, 0x04, true, &compressed);
+    // the empty DATA packet ending the data
+    send(&mut printer, 0x04, false, &[]);
+
+    // one sheet, one unit of margin after, palette inverting the colours
+    let (_, status) = send(&mut printer, 0x02, false, &[0x01, 0x01, 0b_0001_1011, 0x40]);
+    assert_eq!(STATUS_PRINTING, status);
+    assert_eq!(STATUS_PRINTING, send(&mut printer, 0x0F, false, &[]).1);
+    assert_eq!(STATUS_PRINTING, send(&mut printer, 0x0F, false, &[]).1);
+    assert_eq!(0x00, send(&mut printer, 0x0F, false, &[]).1);
+
+    let jobs = printer.take_jobs();
+    assert_eq!(1, jobs.len());
+    let job = &jobs[0];
+    assert_eq!(((0, 1), 0x40), (job.margins(), job.exposure()));
+    assert_eq!(8 + 8 + 8, job.height());
+    assert!(job.pixels()[..8 * PAPER_WIDTH]
+        .iter()
+        .all(|&shade| shade == 2));
+    assert!(job.pixels()[8 * PAPER_WIDTH..16 * PAPER_WIDTH]
+        .iter()
+        .all(|&shade| shade == 0));
+    assert!(job.pixels()[16 * PAPER_WIDTH..]
+        .iter()
+        .all(|&shade| shade == 0));
+}
+
+#[test]
+fn printer_checksum_error() {
+    let mut printer = Printer::new();
+    for byte in [0x88, 0x33, 0x04, 0x00, 0x01, 0x00, 0xAA, 0x00, 0x00] {
+        printer.exchange(byte);
+    }
+    assert_eq!(0x81, printer.exchange(0x00));
+    assert_eq!(STATUS_CHECKSUM_ERROR, printer.exchange(0x00));
+
+    // a good packet clears the error, and the bad one buffered nothing
+    assert_eq!(0x00, send(&mut printer, 0x0F, false, &[]).1);
+}
+
+#[test]
+fn printer_on_serial_port() {
+    let printer = Rc::new(RefCell::new(Printer::new()));
+    let dir = std::env::temp_dir().join(format!("rusty-gb-printer-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    printer.borrow_mut().set_output_dir(Some(dir.clone()));
+
+    let mut serial = Serial::new();
+    serial.set_link(SerialLink::Device(Box::new(Rc::clone(&printer))));
+    let mut port = SerialPort(&mut serial);
+    let row: Vec<u8> = (0..20).flat_map(|_| solid_tile(2)).collect();
+    send(&mut port, 0x04, false, &row);
+    send(&mut port, 0x02, false, &[0x01, 0x00, 0xE4, 0x40]);
+
+    assert_eq!(1, printer.borrow().jobs().len());
+    assert!(printer.borrow_mut().take_save_error().is_none());
+    let file = std::fs::read(dir.join("print_0000.pgm")).unwrap();
+    assert!(file.starts_with(b"P5\n160 8\n255\n"));
+    assert_eq!(0x55, file[file.len() - 1]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Clocks transfers out of a serial port, as a program writing SB and SC would.
+struct SerialPort<'a>(&'a mut Serial);
+
+impl SerialDevice for SerialPort<'_> {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        self.0.write_register(0xFF01, sent);
+        self.0.write_register(0xFF02, 0x81);
+        self.0.tick(8 * 512);
+        self.0.read_register(0xFF01)
+    }
+}
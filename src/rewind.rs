@@ -0,0 +1,236 @@
+//! Rewinding: the last few seconds of save states, one per frame, to step back through.
+//!
+//! Consecutive states differ in few bytes, so only every `keyframe_interval`th is kept whole.
+//! The others are stored as their XOR with the keyframe before them, run-length encoded, which
+//! is mostly runs of zeroes. A minute of a game usually costs a few tens of MiB this way,
+//! against 250 for whole states. A ROM redrawing all of VRAM and the screen every frame still
+//! takes around 100, and `memory_usage` tells what it really takes.
+use crate::{gameboy::GameBoy, savestate::SaveStateError};
+use std::{collections::VecDeque, rc::Rc};
+
+/// Frames the console shows a second, near enough
+pub const FRAMES_PER_SECOND: usize = 60;
+/// Whole states kept a second
+const DEFAULT_KEYFRAME_INTERVAL: usize = FRAMES_PER_SECOND;
+
+/// A state in the buffer.
+struct Snapshot {
+    /// The state this one is stored against, or the state itself if there's no delta
+    keyframe: Rc<Vec<u8>>,
+    /// The state XOR-ed with the keyframe, as encoded by `encode_delta`
+    delta: Option<Vec<u8>>,
+}
+
+impl Snapshot {
+    fn state(&self) -> Vec<u8> {
+        match &self.delta {
+            Some(delta) => decode_delta(&self.keyframe, delta),
+            None => self.keyframe.to_vec(),
+        }
+    }
+}
+
+/// The most recent save states, oldest first, up to a fixed number of them.
+pub struct Rewind {
+    capacity: usize,
+    keyframe_interval: usize,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl Rewind {
+    /// Keeps up to `seconds` seconds of frames, with a keyframe every second.
+    #[must_use]
+    pub fn new(seconds: usize) -> Self {
+        Self::with_capacity(seconds * FRAMES_PER_SECOND, DEFAULT_KEYFRAME_INTERVAL)
+    }
+
+    /// Keeps up to `capacity` states, every `keyframe_interval`th of them whole.
+    ///
+    /// # Panics
+    /// If `keyframe_interval` is 0.
+    #[must_use]
+    pub fn with_capacity(capacity: usize, keyframe_interval: usize) -> Self {
+        assert!(
+            keyframe_interval > 0,
+            "Keyframe interval must be at least 1"
+        );
+        Self {
+            capacity,
+            keyframe_interval,
+            snapshots: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Number of states that can be stepped back through
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Bytes taken by the states, counting each keyframe once
+    #[must_use]
+    pub fn memory_usage(&self) -> usize {
+        self.snapshots
+            .iter()
+            .map(|snapshot| match &snapshot.delta {
+                Some(delta) => delta.len(),
+                None => snapshot.keyframe.len(),
+            })
+            .sum::<usize>()
+            + self.orphaned_keyframe().map_or(0, Vec::len)
+    }
+
+    /// The keyframe of the oldest states once its own snapshot was dropped, if it was
+    fn orphaned_keyframe(&self) -> Option<&Vec<u8>> {
+        self.snapshots
+            .front()
+            .filter(|snapshot| snapshot.delta.is_some())
+            .map(|snapshot| snapshot.keyframe.as_ref())
+    }
+
+    /// Saves the state of `gameboy`, meant to be called once a frame. The oldest state is
+    /// dropped once the buffer is full.
+    pub fn record(&mut self, gameboy: &GameBoy) {
+        self.push(gameboy.save_state());
+    }
+
+    /// Adds `state` as the newest one.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+
+        let since_keyframe = self
+            .snapshots
+            .iter()
+            .rev()
+            .take_while(|snapshot| snapshot.delta.is_some())
+            .count();
+        let snapshot = match self.snapshots.back() {
+            Some(newest) if since_keyframe + 1 < self.keyframe_interval => Snapshot {
+                keyframe: Rc::clone(&newest.keyframe),
+                delta: Some(encode_delta(&newest.keyframe, &state)),
+            },
+            _ => Snapshot {
+                keyframe: Rc::new(state),
+                delta: None,
+            },
+        };
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Removes the newest state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.snapshots.pop_back().map(|snapshot| snapshot.state())
+    }
+
+    /// Steps `gameboy` back a frame: the newest state, that of the frame being shown, is
+    /// dropped and the one before it loaded. It stays in the buffer, so the next call goes
+    /// back one more frame.
+    ///
+    /// # Return value
+    /// `false`, leaving `gameboy` alone, if there's no earlier state.
+    pub fn step_back(&mut self, gameboy: &mut GameBoy) -> Result<bool, SaveStateError> {
+        let previous = match self.snapshots.len() {
+            0 | 1 => return Ok(false),
+            len => self.snapshots[len - 2].state(),
+        };
+        gameboy.load_state(&previous)?;
+        self.snapshots.pop_back();
+        Ok(true)
+    }
+
+    /// Drops every state.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+/// Appends `value` as an unsigned LEB128 number.
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push((value & 0x7F).to_le_bytes()[0] | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value.to_le_bytes()[0]);
+}
+
+/// Reads an unsigned LEB128 number at `*position`, moving past it.
+fn read_varint(bytes: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some(&byte) = bytes.get(*position) {
+        *position += 1;
+        value |= usize::from(byte & 0x7F) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
+
+/// XORs `state` with `keyframe`, the shorter one padded with zeroes, and run-length encodes
+/// the result: the length of `state`, then pairs of a count of zero bytes to skip and a count
+/// of bytes that follow as they are.
+fn encode_delta(keyframe: &[u8], state: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = state
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| byte ^ keyframe.get(i).copied().unwrap_or_default())
+        .collect();
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, state.len());
+    let mut position = 0;
+    while position < xor.len() {
+        let zeroes = xor[position..]
+            .iter()
+            .take_while(|&&byte| byte == 0)
+            .count();
+        position += zeroes;
+        let literals = xor[position..]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .count();
+        write_varint(&mut delta, zeroes);
+        write_varint(&mut delta, literals);
+        delta.extend_from_slice(&xor[position..position + literals]);
+        position += literals;
+    }
+    delta
+}
+
+/// The state `encode_delta` encoded against `keyframe`.
+fn decode_delta(keyframe: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let length = read_varint(delta, &mut position);
+    let mut state = keyframe.to_vec();
+    state.resize(length, 0);
+
+    let mut offset = 0;
+    while position < delta.len() {
+        offset += read_varint(delta, &mut position);
+        let literals = read_varint(delta, &mut position);
+        for (byte, xor) in state[offset..offset + literals]
+            .iter_mut()
+            .zip(&delta[position..position + literals])
+        {
+            *byte ^= xor;
+        }
+        offset += literals;
+        position += literals;
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests;
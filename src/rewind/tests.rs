@@ -0,0 +1,57 @@
+use super::{decode_delta, encode_delta, Rewind};
+use crate::gameboy::GameBoy;
+
+#[test]
+fn rewind_delta_encoding() {
+    let keyframe: Vec<u8> = (0..=255).collect();
+    let mut state = keyframe.clone();
+    state[3] = 0;
+    state[200..210].copy_from_slice(&[0x55; 10]);
+    state.extend_from_slice(&[0, 1, 2]);
+
+    let delta = encode_delta(&keyframe, &state);
+    assert!(delta.len() < 30);
+    assert_eq!(state, decode_delta(&keyframe, &delta));
+    assert_eq!(
+        keyframe[..100],
+        decode_delta(&state, &encode_delta(&state, &keyframe[..100]))
+    );
+    assert_eq!(
+        keyframe,
+        decode_delta(&keyframe, &encode_delta(&keyframe, &keyframe))
+    );
+}
+
+#[test]
+fn rewind_steps_back_frame_by_frame() {
+    #[rustfmt::skip]
+    let program = [
+        0xF0, 0x04, // 0x0100: LDH A, (DIV)
+        0xE0, 0x43, // 0x0102: LDH (SCX), A
+        0x18, 0xFA, // 0x0104: JR 0x0100
+    ];
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(&program);
+    let mut gameboy = GameBoy::new(rom).unwrap();
+
+    let mut rewind = Rewind::with_capacity(25, 10);
+    assert!(!rewind.step_back(&mut gameboy).unwrap());
+    let mut states = Vec::new();
+    for _ in 0..40 {
+        gameboy.run_frame();
+        rewind.record(&gameboy);
+        states.push(gameboy.save_state());
+    }
+    assert_eq!(25, rewind.len());
+    let whole = states.iter().rev().take(25).map(Vec::len).sum::<usize>();
+    assert!(rewind.memory_usage() < whole / 3);
+
+    // back through the frames recorded, evicted keyframes included
+    for expected in states.iter().rev().skip(1).take(24) {
+        assert!(rewind.step_back(&mut gameboy).unwrap());
+        assert!(*expected == gameboy.save_state());
+    }
+    assert!(!rewind.step_back(&mut gameboy).unwrap());
+    assert_eq!(Some(states[15].clone()), rewind.pop());
+    assert!(rewind.is_empty());
+}
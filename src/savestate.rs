@@ -0,0 +1,271 @@
+//! Save states: a snapshot of the whole console that restores it exactly.
+//!
+//! A state is a header followed by one section per component, all integers little endian:
+//!
+//! | offset | size | contents                                    |
+//! | ------ | ---- | ------------------------------------------- |
+//! | 0      | 4    | magic number `RGBS`                         |
+//! | 4      | 2    | format version, `VERSION`                   |
+//! | 6      | 8    | `rom_hash` of the cartridge ROM             |
+//! | 14     |      | sections, until the end                     |
+//!
+//! A section is a 4 byte tag, such as `CPU ` or `PPU `, the length of its payload as a `u32`,
+//! then the payload itself. Components read their own sections, so one can grow without
+//! moving the others, and sections a version doesn't know of are skipped. A console saves
+//! `GB  `, `CPU `, `MMU `, `CART`, `PPU `, `DMA `, `TIMR`, `JOYP`, `APU `, `SERL` and `SCHD`.
+//!
+//! Only emulated state is saved. What the host set up, such as audio outputs, recordings or
+//! what's plugged into the serial port, stays as it is across a load.
+use std::{borrow::Cow, convert::TryFrom, error::Error, fmt};
+
+const MAGIC: [u8; 4] = *b"RGBS";
+/// Version of the format written by this build
+pub const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 14;
+const TAG_SIZE: usize = 4;
+
+/// Why a save state could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    /// The data doesn't start with a save state header
+    NotASaveState,
+    /// Saved in a version of the format this build can't read
+    UnsupportedVersion(u16),
+    /// Saved with another cartridge inserted
+    WrongCartridge,
+    /// Saved with a setting the state depends on that differs from this console's
+    Incompatible(&'static str),
+    /// A section the version requires is absent
+    MissingSection(&'static str),
+    /// A section ends early or holds an impossible value
+    Corrupt(&'static str),
+}
+
+impl Error for SaveStateError {}
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::NotASaveState => write!(f, "Not a save state"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Save state format version {version} is not supported, expected {VERSION} or older"
+            ),
+            Self::WrongCartridge => write!(f, "Save state is of another cartridge"),
+            Self::Incompatible(setting) => {
+                write!(f, "Save state was made with a different {setting}")
+            }
+            Self::MissingSection(tag) => write!(f, "Save state has no {} section", tag.trim()),
+            Self::Corrupt(tag) => write!(f, "Save state {} section is corrupt", tag.trim()),
+        }
+    }
+}
+
+/// FNV-1a hash of a cartridge ROM, telling which game a save state or recording belongs to.
+#[must_use]
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    fnv1a(rom)
+}
+
+/// FNV-1a hash of a save state, to tell two runs apart without keeping their states.
+#[must_use]
+pub fn state_hash(state: &[u8]) -> u64 {
+    fnv1a(state)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01B3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Builds a save state, section by section.
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a state with its header.
+    pub(crate) fn new(rom_hash: u64) -> Self {
+        let mut bytes = Vec::with_capacity(0x10000);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&rom_hash.to_le_bytes());
+        Self { bytes }
+    }
+
+    /// Writes a section tagged `tag` holding whatever `write` puts in it.
+    pub(crate) fn section(&mut self, tag: &'static str, write: impl FnOnce(&mut Self)) {
+        debug_assert_eq!(TAG_SIZE, tag.len());
+        self.bytes.extend_from_slice(tag.as_bytes());
+        let length_at = self.bytes.len();
+        self.bytes.extend_from_slice(&[0; 4]);
+        write(self);
+
+        let length = u32::try_from(self.bytes.len() - length_at - 4).expect("section under 4 GiB");
+        self.bytes[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(u8::from(value));
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes `bytes` as they are, their length being known to the reader.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// The finished state
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Tags and payloads of the sections of a state, owned only once a migration rewrote them
+type Sections<'a> = Vec<(&'a [u8], Cow<'a, [u8]>)>;
+
+/// The header and sections of a save state.
+pub(crate) struct SaveState<'a> {
+    rom_hash: u64,
+    sections: Sections<'a>,
+}
+
+impl<'a> SaveState<'a> {
+    /// Splits `bytes` into sections, checking the header.
+    pub(crate) fn parse(bytes: &'a [u8]) -> Result<Self, SaveStateError> {
+        if bytes.len() < HEADER_SIZE || bytes[..4] != MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        let mut hash = [0; 8];
+        hash.copy_from_slice(&bytes[6..HEADER_SIZE]);
+
+        let mut sections = Vec::new();
+        let mut rest = &bytes[HEADER_SIZE..];
+        while !rest.is_empty() {
+            if rest.len() < TAG_SIZE + 4 {
+                return Err(SaveStateError::NotASaveState);
+            }
+            let (tag, after_tag) = rest.split_at(TAG_SIZE);
+            let mut length = [0; 4];
+            length.copy_from_slice(&after_tag[..4]);
+            let length = usize::try_from(u32::from_le_bytes(length))
+                .map_err(|_| SaveStateError::NotASaveState)?;
+            let payload = &after_tag[4..];
+            if payload.len() < length {
+                return Err(SaveStateError::NotASaveState);
+            }
+            sections.push((tag, Cow::Borrowed(&payload[..length])));
+            rest = &payload[length..];
+        }
+
+        Self::migrate(&mut sections, version)?;
+        Ok(Self {
+            rom_hash: u64::from_le_bytes(hash),
+            sections,
+        })
+    }
+
+    /// Brings the sections of a state saved in `version` up to `VERSION`. There's only been
+    /// one version so far: when the layout of a section changes, bump `VERSION` and convert
+    /// the older payloads here.
+    const fn migrate(_sections: &mut Sections<'a>, version: u16) -> Result<(), SaveStateError> {
+        match version {
+            VERSION => Ok(()),
+            _ => Err(SaveStateError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Hash of the ROM of the cartridge the state was saved with
+    pub(crate) const fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Reader over the section tagged `tag`
+    pub(crate) fn section(&self, tag: &'static str) -> Result<StateReader<'_>, SaveStateError> {
+        self.sections
+            .iter()
+            .find(|(section, _)| *section == tag.as_bytes())
+            .map(|(_, bytes)| StateReader { tag, bytes })
+            .ok_or(SaveStateError::MissingSection(tag))
+    }
+}
+
+/// Reads the fields of a section back, in the order they were written.
+pub(crate) struct StateReader<'a> {
+    tag: &'static str,
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// The error for an impossible value in this section
+    pub(crate) const fn corrupt(&self) -> SaveStateError {
+        SaveStateError::Corrupt(self.tag)
+    }
+
+    /// The next `length` bytes
+    pub(crate) const fn bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.bytes.len() < length {
+            return Err(self.corrupt());
+        }
+        let (bytes, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    /// Fills `buffer` with the next bytes.
+    pub(crate) fn read_into(&mut self, buffer: &mut [u8]) -> Result<(), SaveStateError> {
+        buffer.copy_from_slice(self.bytes(buffer.len())?);
+        Ok(())
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.corrupt()),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut bytes = [0; 2];
+        self.read_into(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0; 4];
+        self.read_into(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0; 8];
+        self.read_into(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests;
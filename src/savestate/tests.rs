@@ -0,0 +1,43 @@
+use super::{SaveState, SaveStateError, StateWriter, VERSION};
+
+#[test]
+fn savestate_sections() {
+    let mut writer = StateWriter::new(0x1234);
+    writer.section("ONE ", |w| {
+        w.u8(7);
+        w.bool(true);
+        w.u64(u64::MAX - 1);
+    });
+    writer.section("TWO ", |w| w.bytes(&[1, 2, 3]));
+    let bytes = writer.finish();
+
+    let state = SaveState::parse(&bytes).unwrap();
+    assert_eq!(0x1234, state.rom_hash());
+    let mut two = state.section("TWO ").unwrap();
+    assert_eq!(&[1, 2, 3], two.bytes(3).unwrap());
+    assert_eq!(Err(SaveStateError::Corrupt("TWO ")), two.u8());
+
+    let mut one = state.section("ONE ").unwrap();
+    assert_eq!(Ok(7), one.u8());
+    assert_eq!(Ok(true), one.bool());
+    assert_eq!(Ok(u64::MAX - 1), one.u64());
+    assert!(matches!(
+        state.section("SIX "),
+        Err(SaveStateError::MissingSection("SIX "))
+    ));
+
+    let mut newer = bytes.clone();
+    newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        SaveState::parse(&newer),
+        Err(SaveStateError::UnsupportedVersion(version)) if version == VERSION + 1
+    ));
+    assert!(matches!(
+        SaveState::parse(&bytes[..bytes.len() - 1]),
+        Err(SaveStateError::NotASaveState)
+    ));
+    assert!(matches!(
+        SaveState::parse(b"not a save state"),
+        Err(SaveStateError::NotASaveState)
+    ));
+}
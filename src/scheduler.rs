@@ -0,0 +1,135 @@
+//! Keeps emulated time and the timestamped events the peripherals are waiting for.
+//!
+//! Between two events every peripheral only counts time, so the bus advances them over the gap
+//! in one go instead of T-state by T-state. An event is due in the T-state in which its
+//! peripheral next does something that the rest of the system can see or that has to happen in
+//! order with the others:
+//!
+//! | event                     | due when                                                        |
+//! | ------------------------- | --------------------------------------------------------------- |
+//! | `Event::Timer`            | TIMA increments or goes through its reload                      |
+//! | `Event::Ppu`              | the PPU changes mode or line, or its renderer works in mode 3   |
+//! | `Event::FrameSequencer`   | DIV bit 4 falls, stepping the APU frame sequencer               |
+//! | `Event::Serial`           | a transfer clocked by the console completes                     |
+//! | `Event::Dma`              | every T-state of an OAM DMA transfer, until it ends             |
+//!
+//! A halted CPU sleeps until the next event, since only an event can wake it.
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+/// Something a peripheral will do at a known time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    Timer,
+    Ppu,
+    FrameSequencer,
+    Serial,
+    Dma,
+}
+
+impl Event {
+    pub const ALL: [Self; 5] = [
+        Self::Timer,
+        Self::Ppu,
+        Self::FrameSequencer,
+        Self::Serial,
+        Self::Dma,
+    ];
+
+    const fn index(self) -> usize {
+        match self {
+            Self::Timer => 0,
+            Self::Ppu => 1,
+            Self::FrameSequencer => 2,
+            Self::Serial => 3,
+            Self::Dma => 4,
+        }
+    }
+}
+
+/// Emulated time and the pending events, at most one of each kind.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    /// T-states since power on
+    now: u64,
+    /// When each event is due, indexed by `Event::index`
+    deadlines: [Option<u64>; 5],
+}
+
+impl Scheduler {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            now: 0,
+            deadlines: [None; 5],
+        }
+    }
+
+    /// T-states since power on
+    #[must_use]
+    pub const fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Moves emulated time forward by `t_states`.
+    pub const fn advance(&mut self, t_states: u64) {
+        self.now += t_states;
+    }
+
+    /// Makes `event` due in T-state `at`, replacing its previous deadline.
+    pub const fn schedule(&mut self, event: Event, at: u64) {
+        self.deadlines[event.index()] = Some(at);
+    }
+
+    /// Drops `event` until it's scheduled again.
+    pub const fn cancel(&mut self, event: Event) {
+        self.deadlines[event.index()] = None;
+    }
+
+    /// When `event` is due, if it's scheduled
+    #[must_use]
+    pub const fn deadline(&self, event: Event) -> Option<u64> {
+        self.deadlines[event.index()]
+    }
+
+    /// The event due first and when, the earlier one in `Event::ALL` winning ties.
+    #[must_use]
+    pub fn next(&self) -> Option<(Event, u64)> {
+        Event::ALL
+            .iter()
+            .filter_map(|&event| self.deadline(event).map(|at| (event, at)))
+            .min_by_key(|&(_, at)| at)
+    }
+
+    /// T-states from now until the next event is due, if any is scheduled
+    #[must_use]
+    pub fn until_next(&self) -> Option<u64> {
+        self.next().map(|(_, at)| at.saturating_sub(self.now))
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u64(self.now);
+        for deadline in &self.deadlines {
+            state.bool(deadline.is_some());
+            state.u64(deadline.unwrap_or_default());
+        }
+    }
+
+    /// Restores what `Scheduler::save_state` saved, no event being due before the time saved.
+    pub(crate) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.now = state.u64()?;
+        for deadline in &mut self.deadlines {
+            let (scheduled, at) = (state.bool()?, state.u64()?);
+            *deadline = match scheduled {
+                true => Some(at),
+                false => None,
+            };
+        }
+        match self.deadlines.iter().flatten().all(|&at| at >= self.now) {
+            true => Ok(()),
+            false => Err(state.corrupt()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
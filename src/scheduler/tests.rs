@@ -0,0 +1,40 @@
+use super::{Event, Scheduler};
+use crate::savestate::{SaveState, SaveStateError, StateWriter};
+
+#[test]
+fn scheduler_orders_events() {
+    let mut scheduler = Scheduler::new();
+    assert_eq!(None, scheduler.next());
+
+    scheduler.schedule(Event::Serial, 4096);
+    scheduler.schedule(Event::Ppu, 80);
+    scheduler.schedule(Event::Timer, 80);
+    assert_eq!(Some((Event::Timer, 80)), scheduler.next());
+
+    scheduler.advance(50);
+    assert_eq!(Some(30), scheduler.until_next());
+
+    scheduler.cancel(Event::Timer);
+    scheduler.schedule(Event::Ppu, 5000);
+    assert_eq!(Some((Event::Serial, 4096)), scheduler.next());
+    assert_eq!(Some(5000), scheduler.deadline(Event::Ppu));
+    assert_eq!(None, scheduler.deadline(Event::Timer));
+}
+
+#[test]
+fn scheduler_load_state_rejects_past_deadlines() {
+    let mut scheduler = Scheduler::new();
+    scheduler.advance(100);
+    scheduler.schedule(Event::Ppu, 100);
+    let load = |scheduler: &Scheduler| {
+        let mut writer = StateWriter::new(0);
+        writer.section("SCHD", |state| scheduler.save_state(state));
+        let bytes = writer.finish();
+        let state = SaveState::parse(&bytes).unwrap();
+        Scheduler::new().load_state(&mut state.section("SCHD").unwrap())
+    };
+    assert_eq!(Ok(()), load(&scheduler));
+
+    scheduler.schedule(Event::Timer, 50);
+    assert_eq!(Err(SaveStateError::Corrupt("SCHD")), load(&scheduler));
+}
@@ -0,0 +1,326 @@
+//! Implements the serial port and its registers SB (`0xFF01`) and SC (`0xFF02`).
+//!
+//! Writing SC with bit 7 set starts a transfer of the byte in SB. With bit 0 set this console
+//! clocks the transfer itself, shifting the eight bits out at 8192 Hz, or at 262144 Hz in the
+//! CGB's fast mode (SC bit 1). With bit 0 clear it waits for the other end to clock one. Once
+//! the eight bits are through, SB holds the byte shifted in, SC bit 7 is cleared and the serial
+//! interrupt is requested.
+//!
+//! Transfers are modelled a byte at a time. A transfer clocked by this console takes the full
+//! eight bit times from the SC write, then exchanges its byte with whatever `SerialLink` is
+//! plugged in.
+//!
+//! The bytes shifted out can also be captured, with nothing plugged in, which is how test ROMs
+//! report their results.
+use crate::{
+    interrupts::Interrupt,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+
+const SC_TRANSFER: u8 = 0b_1000_0000;
+const SC_FAST_CLOCK: u8 = 0b_0000_0010;
+const SC_INTERNAL_CLOCK: u8 = 0b_0000_0001;
+/// T-states per bit at 8192 Hz
+const BIT_PERIOD: u32 = 512;
+/// T-states per bit at 262144 Hz
+const FAST_BIT_PERIOD: u32 = 16;
+/// Shifted in from an unconnected port
+const DISCONNECTED: u8 = 0xFF;
+
+/// A peripheral that answers transfers right away, such as a printer.
+pub trait SerialDevice {
+    /// Exchanges a byte with the device, for a transfer clocked by the console.
+    ///
+    /// # Arguments
+    /// * `sent` - Byte shifted out of SB
+    ///
+    /// # Return value
+    /// Byte shifted into SB.
+    fn exchange(&mut self, sent: u8) -> u8;
+}
+
+/// Lets a device be plugged in while keeping a handle to it, to read what it collected.
+impl<D: SerialDevice> SerialDevice for Rc<RefCell<D>> {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        self.borrow_mut().exchange(sent)
+    }
+}
+
+/// What is plugged into the serial port.
+pub enum SerialLink {
+    /// Nothing, transfers clocked by the console shift in `0xFF`
+    Disconnected,
+    /// A device answering every transfer clocked by the console
+    Device(Box<dyn SerialDevice>),
+    /// A cable to another console, driven from outside. Transfers clocked by this console wait
+    /// in `Serial::clocked_transfer` until `Serial::complete_transfer` gives them the other
+    /// end's byte, and the other end's transfers come in through `Serial::clock_external`.
+    Cable,
+}
+
+/// Where the port is in a transfer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Transfer {
+    Idle,
+    /// Clocking the bits out, done in this many T-states
+    Shifting(u32),
+    /// Clocked out this byte, waiting for the other end of a cable
+    Clocked(u8),
+    /// Waiting for the other end to clock a transfer
+    External,
+}
+
+/// Serial port
+pub struct Serial {
+    /// SB
+    data: u8,
+    /// SC
+    control: u8,
+    transfer: Transfer,
+    link: SerialLink,
+    /// Whether SC bit 1 selects the fast clock, as on the CGB
+    cgb_mode: bool,
+    /// A transfer completed since the last tick
+    interrupt: bool,
+    /// Bytes shifted out, if capturing them
+    captured: Option<Vec<u8>>,
+    /// Called with every byte shifted out
+    output_callback: Option<Box<dyn FnMut(u8)>>,
+}
+
+impl Serial {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            data: 0,
+            control: 0,
+            transfer: Transfer::Idle,
+            link: SerialLink::Disconnected,
+            cgb_mode: false,
+            interrupt: false,
+            captured: None,
+            output_callback: None,
+        }
+    }
+
+    /// Enables the CGB's fast clock, selected by SC bit 1. Off by default, as on the DMG.
+    pub const fn set_cgb_mode(&mut self, enabled: bool) {
+        self.cgb_mode = enabled;
+    }
+
+    /// Whether the CGB's fast clock can be selected
+    #[must_use]
+    pub const fn cgb_mode(&self) -> bool {
+        self.cgb_mode
+    }
+
+    /// Plugs `link` into the port.
+    ///
+    /// # Return value
+    /// What was plugged in before.
+    pub const fn set_link(&mut self, link: SerialLink) -> SerialLink {
+        std::mem::replace(&mut self.link, link)
+    }
+
+    /// Starts or stops collecting the bytes shifted out, clocked by either end. Stopping
+    /// drops what was collected.
+    pub fn set_capture(&mut self, enabled: bool) {
+        self.captured = match enabled {
+            true => self.captured.take().or_else(|| Some(Vec::new())),
+            false => None,
+        };
+    }
+
+    /// Bytes shifted out since the capture started, or since they were last taken
+    #[must_use]
+    pub fn captured(&self) -> &[u8] {
+        self.captured.as_deref().unwrap_or_default()
+    }
+
+    /// Takes the bytes collected so far, carrying on capturing.
+    pub fn take_captured(&mut self) -> Vec<u8> {
+        self.captured
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Calls `callback` with every byte shifted out from now on, or stops calling the previous
+    /// one if `callback` is `None`.
+    pub fn set_output_callback(&mut self, callback: Option<Box<dyn FnMut(u8)>>) {
+        self.output_callback = callback;
+    }
+
+    fn shift_out(&mut self, byte: u8) {
+        if let Some(captured) = &mut self.captured {
+            captured.push(byte);
+        }
+        if let Some(callback) = &mut self.output_callback {
+            callback(byte);
+        }
+    }
+
+    /// Reads SB or SC
+    #[must_use]
+    pub const fn read_register(&self, address: u16) -> u8 {
+        match address {
+            0xFF01 => self.data,
+            0xFF02 => match self.cgb_mode {
+                true => 0b_0111_1100 | self.control,
+                false => 0b_0111_1110 | self.control,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes SB or SC, starting or aborting a transfer on writes to SC.
+    pub const fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF01 => self.data = value,
+            0xFF02 => {
+                let mask = match self.cgb_mode {
+                    true => SC_TRANSFER | SC_FAST_CLOCK | SC_INTERNAL_CLOCK,
+                    false => SC_TRANSFER | SC_INTERNAL_CLOCK,
+                };
+                self.control = value & mask;
+                self.transfer = match (value & SC_TRANSFER != 0, value & SC_INTERNAL_CLOCK != 0) {
+                    (false, _) => Transfer::Idle,
+                    (true, true) => Transfer::Shifting(8 * self.bit_period()),
+                    (true, false) => Transfer::External,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    const fn bit_period(&self) -> u32 {
+        match self.control & SC_FAST_CLOCK != 0 {
+            true => FAST_BIT_PERIOD,
+            false => BIT_PERIOD,
+        }
+    }
+
+    /// Advances the port by `t_states` clock ticks.
+    ///
+    /// # Return value
+    /// Bit mask of the interrupts requested in that time, to be OR-ed into IF.
+    pub fn tick(&mut self, t_states: u32) -> u8 {
+        if let Transfer::Shifting(remaining) = self.transfer {
+            match remaining.checked_sub(t_states) {
+                Some(remaining) if remaining > 0 => self.transfer = Transfer::Shifting(remaining),
+                _ => {
+                    let sent = self.data;
+                    self.shift_out(sent);
+                    match &mut self.link {
+                        SerialLink::Disconnected => self.complete_transfer(DISCONNECTED),
+                        SerialLink::Device(device) => {
+                            let received = device.exchange(sent);
+                            self.complete_transfer(received);
+                        }
+                        SerialLink::Cable => self.transfer = Transfer::Clocked(sent),
+                    }
+                }
+            }
+        }
+
+        match std::mem::take(&mut self.interrupt) {
+            true => Interrupt::Serial.bit(),
+            false => 0,
+        }
+    }
+
+    /// Clock ticks until the one in which a transfer clocked by this console completes, or the
+    /// next one if an interrupt is waiting to be requested.
+    #[must_use]
+    pub const fn next_event(&self) -> Option<u32> {
+        if self.interrupt {
+            return Some(1);
+        }
+        match self.transfer {
+            Transfer::Shifting(remaining) => Some(remaining),
+            _ => None,
+        }
+    }
+
+    /// Byte clocked out by this console that is waiting for the other end of the cable.
+    #[must_use]
+    pub const fn clocked_transfer(&self) -> Option<u8> {
+        match self.transfer {
+            Transfer::Clocked(sent) => Some(sent),
+            _ => None,
+        }
+    }
+
+    /// Whether the port waits for the other end to clock a transfer
+    #[must_use]
+    pub fn is_waiting_for_clock(&self) -> bool {
+        self.transfer == Transfer::External
+    }
+
+    /// Ends the transfer in progress with `received` shifted in, requesting the serial
+    /// interrupt on the next tick.
+    pub const fn complete_transfer(&mut self, received: u8) {
+        self.data = received;
+        self.control &= !SC_TRANSFER;
+        self.transfer = Transfer::Idle;
+        self.interrupt = true;
+    }
+
+    /// The other end clocked a transfer of `sent`. If the port is waiting for one, it's
+    /// completed.
+    ///
+    /// # Return value
+    /// The byte shifted out of this port, or `None` if it wasn't waiting.
+    pub fn clock_external(&mut self, sent: u8) -> Option<u8> {
+        match self.transfer {
+            Transfer::External => {
+                let own = self.data;
+                self.shift_out(own);
+                self.complete_transfer(sent);
+                Some(own)
+            }
+            _ => None,
+        }
+    }
+
+    /// Saves the registers and the transfer in progress. What's plugged in and what was
+    /// captured belong to the host and aren't saved.
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.data);
+        state.u8(self.control);
+        let (kind, value) = match self.transfer {
+            Transfer::Idle => (0, 0),
+            Transfer::Shifting(t_states) => (1, t_states),
+            Transfer::Clocked(sent) => (2, u32::from(sent)),
+            Transfer::External => (3, 0),
+        };
+        state.u8(kind);
+        state.u32(value);
+        state.bool(self.interrupt);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.data = state.u8()?;
+        self.control = state.u8()?;
+        let (kind, value) = (state.u8()?, state.u32()?);
+        self.transfer = match kind {
+            0 => Transfer::Idle,
+            1 => Transfer::Shifting(value),
+            2 => Transfer::Clocked(u8::try_from(value).map_err(|_| state.corrupt())?),
+            3 => Transfer::External,
+            _ => return Err(state.corrupt()),
+        };
+        self.interrupt = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,102 @@
+use super::{Interrupt, Serial, SerialDevice, SerialLink};
+use std::{cell::RefCell, rc::Rc};
+
+/// Answers with the complement of what it's sent
+struct Inverter;
+
+impl SerialDevice for Inverter {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        !sent
+    }
+}
+
+#[test]
+fn serial_internal_clock_timing() {
+    let mut serial = Serial::new();
+    serial.write_register(0xFF01, 0x42);
+    serial.write_register(0xFF02, 0x81);
+    assert_eq!(0xFF, serial.read_register(0xFF02));
+
+    // eight bits at 8192 Hz
+    assert_eq!(0, serial.tick(8 * 512 - 1));
+    assert_eq!(Interrupt::Serial.bit(), serial.tick(1));
+    assert_eq!(0xFF, serial.read_register(0xFF01));
+    assert_eq!(0x7F, serial.read_register(0xFF02));
+    assert_eq!(0, serial.tick(10_000));
+}
+
+#[test]
+fn serial_cgb_fast_clock() {
+    let mut serial = Serial::new();
+    serial.set_link(SerialLink::Device(Box::new(Inverter)));
+
+    // SC bit 1 is unused on the DMG
+    serial.write_register(0xFF01, 0x0F);
+    serial.write_register(0xFF02, 0x83);
+    assert_eq!(0xFF, serial.read_register(0xFF02));
+    assert_eq!(0, serial.tick(8 * 16));
+
+    serial.set_cgb_mode(true);
+    serial.write_register(0xFF02, 0x83);
+    assert_eq!(0xFF, serial.read_register(0xFF02));
+    assert_eq!(0, serial.tick(8 * 16 - 1));
+    assert_eq!(Interrupt::Serial.bit(), serial.tick(1));
+    assert_eq!(0xF0, serial.read_register(0xFF01));
+    assert_eq!(0x7F, serial.read_register(0xFF02));
+}
+
+#[test]
+fn serial_output_capture() {
+    let mut serial = Serial::new();
+    let echoed = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&echoed);
+    serial.set_output_callback(Some(Box::new(move |byte| sink.borrow_mut().push(byte))));
+
+    let send = |serial: &mut Serial, byte: u8| {
+        serial.write_register(0xFF01, byte);
+        serial.write_register(0xFF02, 0x81);
+        serial.tick(8 * 512);
+    };
+    send(&mut serial, b'-');
+    serial.set_capture(true);
+    for &byte in b"ok" {
+        send(&mut serial, byte);
+    }
+    assert_eq!(b"ok", serial.captured());
+    assert_eq!(b"ok".to_vec(), serial.take_captured());
+    send(&mut serial, b'!');
+    assert_eq!(b"!", serial.captured());
+    assert_eq!(b"-ok!".to_vec(), *echoed.borrow());
+
+    serial.set_capture(false);
+    assert!(serial.captured().is_empty());
+}
+
+#[test]
+fn serial_cable_transfers() {
+    let mut master = Serial::new();
+    let mut slave = Serial::new();
+    master.set_link(SerialLink::Cable);
+    slave.set_link(SerialLink::Cable);
+
+    // nothing comes in before the slave waits
+    assert_eq!(None, slave.clock_external(0x12));
+
+    slave.write_register(0xFF01, 0x34);
+    slave.write_register(0xFF02, 0x80);
+    assert!(slave.is_waiting_for_clock());
+    master.write_register(0xFF01, 0x12);
+    master.write_register(0xFF02, 0x81);
+    master.tick(8 * 512);
+    assert_eq!(Some(0x12), master.clocked_transfer());
+    assert_eq!(0xFF, master.read_register(0xFF02));
+
+    let received = slave.clock_external(0x12).unwrap();
+    master.complete_transfer(received);
+    assert_eq!(Interrupt::Serial.bit(), master.tick(1));
+    assert_eq!(Interrupt::Serial.bit(), slave.tick(1));
+    assert_eq!(0x34, master.read_register(0xFF01));
+    assert_eq!(0x12, slave.read_register(0xFF01));
+    assert_eq!(None, master.clocked_transfer());
+    assert!(!slave.is_waiting_for_clock());
+}
@@ -0,0 +1,235 @@
+//! Implements the timer registers DIV, TIMA, TMA and TAC at `0xFF04..=0xFF07`.
+//!
+//! Everything is driven by a 16-bit counter incremented every T-state, DIV being its upper
+//! byte. TIMA increments on the falling edge of the counter bit selected by TAC (AND-ed with the
+//! enable bit), so writes to DIV or TAC that pull that signal low also increment TIMA.
+//!
+//! When TIMA overflows it reads `0x00` for one M-cycle before being reloaded from TMA, at which
+//! point the timer interrupt is requested:
+//!
+//! | M-cycle     | TIMA    | write to TIMA                | write to TMA          |
+//! | ----------- | ------- | ---------------------------- | --------------------- |
+//! | overflow    | `0x00`  | cancels reload and interrupt | -                     |
+//! | reload      | TMA     | ignored                      | also written to TIMA  |
+use crate::{
+    interrupts::Interrupt,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const TAC_ENABLE: u8 = 0b_0000_0100;
+const TAC_SELECT: u8 = 0b_0000_0011;
+/// The reload happens one M-cycle after the overflow, and lasts one M-cycle
+const RELOAD_DELAY_T_STATES: u8 = 4;
+
+/// Where TIMA is in its overflow and reload sequence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Reload {
+    Idle,
+    /// TIMA overflowed this many T-states ago and reads `0x00`
+    Overflowed(u8),
+    /// TIMA was reloaded from TMA this many T-states ago
+    Reloading(u8),
+}
+
+/// Timer and divider
+#[derive(Debug)]
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    reload: Reload,
+}
+
+impl Timer {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            reload: Reload::Idle,
+        }
+    }
+
+    /// The internal 16-bit counter. DIV is its upper byte.
+    #[must_use]
+    pub const fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    /// Reads one of the timer registers in `0xFF04..=0xFF07`
+    #[must_use]
+    pub const fn read_register(&self, address: u16) -> u8 {
+        match address {
+            0xFF04 => self.counter.to_be_bytes()[0],
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            0xFF07 => 0b_1111_1000 | self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes one of the timer registers in `0xFF04..=0xFF07`
+    pub const fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF04 => {
+                // resetting the counter is a falling edge if the selected bit was set
+                let before = self.signal();
+                self.counter = 0;
+                self.detect_falling_edge(before);
+            }
+            0xFF05 => match self.reload {
+                Reload::Overflowed(_) => {
+                    self.tima = value;
+                    self.reload = Reload::Idle;
+                }
+                Reload::Reloading(_) => {}
+                Reload::Idle => self.tima = value,
+            },
+            0xFF06 => {
+                self.tma = value;
+                if let Reload::Reloading(_) = self.reload {
+                    self.tima = value;
+                }
+            }
+            0xFF07 => {
+                let before = self.signal();
+                self.tac = value & (TAC_ENABLE | TAC_SELECT);
+                self.detect_falling_edge(before);
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances the timer by `t_states` clock ticks.
+    ///
+    /// # Return value
+    /// Bit mask of the interrupts requested in that time, to be OR-ed into IF.
+    pub fn tick(&mut self, t_states: u32) -> u8 {
+        let mut interrupts = 0;
+        let mut remaining = t_states;
+
+        while remaining > 0 {
+            // up to the next event the counter is all that changes
+            let quiet = self
+                .next_event()
+                .map_or(remaining, |until| until - 1)
+                .min(remaining);
+            let [low, high, ..] = quiet.to_le_bytes();
+            self.counter = self.counter.wrapping_add(u16::from_le_bytes([low, high]));
+            remaining -= quiet;
+
+            if remaining > 0 {
+                interrupts |= self.tick_once();
+                remaining -= 1;
+            }
+        }
+
+        interrupts
+    }
+
+    /// Clock ticks until the one in which TIMA increments or goes through its reload, if the
+    /// timer is enabled or reloading.
+    #[must_use]
+    pub fn next_event(&self) -> Option<u32> {
+        if !matches!(self.reload, Reload::Idle) {
+            return Some(1);
+        }
+        if self.tac & TAC_ENABLE == 0 {
+            return None;
+        }
+
+        // TIMA increments when the counter wraps around to a multiple of twice the selected bit
+        let period = 2 << self.selected_bit();
+        Some(period - (u32::from(self.counter) & (period - 1)))
+    }
+
+    const fn tick_once(&mut self) -> u8 {
+        let mut interrupts = 0;
+        self.reload = match self.reload {
+            Reload::Overflowed(ticks) if ticks + 1 == RELOAD_DELAY_T_STATES => {
+                self.tima = self.tma;
+                interrupts |= Interrupt::Timer.bit();
+                Reload::Reloading(0)
+            }
+            Reload::Overflowed(ticks) => Reload::Overflowed(ticks + 1),
+            Reload::Reloading(ticks) if ticks + 1 == RELOAD_DELAY_T_STATES => Reload::Idle,
+            Reload::Reloading(ticks) => Reload::Reloading(ticks + 1),
+            Reload::Idle => Reload::Idle,
+        };
+
+        let before = self.signal();
+        self.counter = self.counter.wrapping_add(1);
+        self.detect_falling_edge(before);
+        interrupts
+    }
+
+    /// The counter bit TAC selects
+    const fn selected_bit(&self) -> u32 {
+        match self.tac & TAC_SELECT {
+            0b00 => 9, // 4096 Hz
+            0b01 => 3, // 262144 Hz
+            0b10 => 5, // 65536 Hz
+            _ => 7,    // 16384 Hz
+        }
+    }
+
+    /// The counter bit selected by TAC, AND-ed with the timer enable bit.
+    const fn signal(&self) -> bool {
+        self.tac & TAC_ENABLE != 0 && self.counter & (1 << self.selected_bit()) != 0
+    }
+
+    const fn detect_falling_edge(&mut self, before: bool) {
+        if before && !self.signal() {
+            self.increment_tima();
+        }
+    }
+
+    const fn increment_tima(&mut self) {
+        let (value, overflow) = self.tima.overflowing_add(1);
+        self.tima = value;
+        if overflow {
+            self.reload = Reload::Overflowed(0);
+        }
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.counter);
+        state.u8(self.tima);
+        state.u8(self.tma);
+        state.u8(self.tac);
+        let (kind, ticks) = match self.reload {
+            Reload::Idle => (0, 0),
+            Reload::Overflowed(ticks) => (1, ticks),
+            Reload::Reloading(ticks) => (2, ticks),
+        };
+        state.u8(kind);
+        state.u8(ticks);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader<'_>) -> Result<(), SaveStateError> {
+        self.counter = state.u16()?;
+        self.tima = state.u8()?;
+        self.tma = state.u8()?;
+        self.tac = state.u8()? & (TAC_ENABLE | TAC_SELECT);
+        let (kind, ticks) = (state.u8()?, state.u8()?);
+        self.reload = match kind {
+            0 => Reload::Idle,
+            1 => Reload::Overflowed(ticks),
+            2 => Reload::Reloading(ticks),
+            _ => return Err(state.corrupt()),
+        };
+        Ok(())
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests;
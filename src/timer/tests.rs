@@ -0,0 +1,121 @@
+use super::{Interrupt, Timer};
+
+/// Timer enabled at 262144 Hz, incrementing TIMA every 16 T-states
+fn setup(counter: u8, modulo: u8) -> Timer {
+    let mut timer = Timer::new();
+    timer.write_register(0xFF05, counter);
+    timer.write_register(0xFF06, modulo);
+    timer.write_register(0xFF07, 0b101);
+    timer
+}
+
+#[test]
+fn timer_div_increments() {
+    let mut timer = Timer::new();
+
+    timer.tick(255);
+    assert_eq!(0, timer.read_register(0xFF04));
+    timer.tick(1);
+    assert_eq!(1, timer.read_register(0xFF04));
+
+    timer.write_register(0xFF04, 0x50);
+    assert_eq!(0, timer.read_register(0xFF04));
+    assert_eq!(0, timer.counter());
+}
+
+#[test]
+fn timer_tima_frequencies() {
+    let test_cases: &[(u8, u32)] = &[(0b100, 1024), (0b101, 16), (0b110, 64), (0b111, 256)];
+
+    for (tac, period) in test_cases {
+        let mut timer = Timer::new();
+        timer.write_register(0xFF07, *tac);
+
+        timer.tick(period - 1);
+        assert_eq!(0, timer.read_register(0xFF05), "TAC {tac:03b}");
+        timer.tick(1);
+        assert_eq!(1, timer.read_register(0xFF05), "TAC {tac:03b}");
+        timer.tick(period * 9);
+        assert_eq!(10, timer.read_register(0xFF05), "TAC {tac:03b}");
+    }
+}
+
+#[test]
+fn timer_disabled() {
+    let mut timer = Timer::new();
+    timer.write_register(0xFF07, 0b001);
+
+    timer.tick(1000);
+    assert_eq!(0, timer.read_register(0xFF05));
+    assert_eq!(0xF9, timer.read_register(0xFF07));
+}
+
+#[test]
+fn timer_overflow_delayed_reload() {
+    let mut timer = setup(0xFF, 0xAB);
+
+    assert_eq!(0, timer.tick(16));
+    // TIMA reads 0 for a whole M-cycle before the reload and interrupt
+    assert_eq!(0x00, timer.read_register(0xFF05));
+    assert_eq!(0, timer.tick(3));
+    assert_eq!(0x00, timer.read_register(0xFF05));
+    assert_eq!(Interrupt::Timer.bit(), timer.tick(1));
+    assert_eq!(0xAB, timer.read_register(0xFF05));
+}
+
+#[test]
+fn timer_tima_write_during_overflow_cancels_reload() {
+    let mut timer = setup(0xFF, 0xAB);
+
+    timer.tick(16 + 2);
+    timer.write_register(0xFF05, 0x42);
+    assert_eq!(0, timer.tick(4));
+    assert_eq!(0x42, timer.read_register(0xFF05));
+}
+
+#[test]
+fn timer_writes_during_reload_cycle() {
+    let mut timer = setup(0xFF, 0xAB);
+
+    timer.tick(16 + 4 + 1);
+    // writes to TIMA are ignored while it is being reloaded
+    timer.write_register(0xFF05, 0x42);
+    assert_eq!(0xAB, timer.read_register(0xFF05));
+    // while writes to TMA go through to TIMA
+    timer.write_register(0xFF06, 0x37);
+    assert_eq!(0x37, timer.read_register(0xFF05));
+
+    timer.tick(4);
+    timer.write_register(0xFF05, 0x42);
+    assert_eq!(0x42, timer.read_register(0xFF05));
+}
+
+#[test]
+fn timer_div_write_falling_edge() {
+    let mut timer = setup(0, 0);
+
+    // bit 3 of the counter is set, so clearing it is a falling edge
+    timer.tick(8);
+    timer.write_register(0xFF04, 0);
+    assert_eq!(1, timer.read_register(0xFF05));
+
+    // bit 3 clear, no increment
+    timer.tick(4);
+    timer.write_register(0xFF04, 0);
+    assert_eq!(1, timer.read_register(0xFF05));
+}
+
+#[test]
+fn timer_tac_write_falling_edge() {
+    let mut timer = setup(0, 0);
+    timer.tick(8);
+
+    // disabling the timer while the selected bit is set increments TIMA
+    timer.write_register(0xFF07, 0b001);
+    assert_eq!(1, timer.read_register(0xFF05));
+
+    // switching to a bit that is clear does too
+    timer.write_register(0xFF07, 0b101);
+    timer.write_register(0xFF07, 0b100);
+    assert_eq!(2, timer.read_register(0xFF05));
+}
@@ -1,4 +1,5 @@
 //! Contains common utility functions used across the project.
+use std::io::{self, Write};
 
 /// Splits a 16-bit word into two 8-bit bytes
 ///
@@ -24,3 +25,21 @@ pub(crate) fn bytes_to_word(high_byte: u8, low_byte: u8) -> u16 {
     let high: u16 = (high_byte as u16) << 8;
     high + low_byte as u16
 }
+
+/// Writes shades 0 (lightest) to 3 (darkest), row by row, as a binary PGM image.
+pub fn write_pgm<W: Write>(
+    mut writer: W,
+    width: usize,
+    height: usize,
+    shades: &[u8],
+) -> io::Result<()> {
+    const GRAYS: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+    write!(writer, "P5\n{width} {height}\n255\n")?;
+    let pixels: Vec<u8> = shades
+        .iter()
+        .map(|&shade| GRAYS[usize::from(shade & 0b11)])
+        .collect();
+    writer.write_all(&pixels)?;
+    writer.flush()
+}
@@ -0,0 +1,279 @@
+//! Golden screenshot tests: run a ROM for a number of frames or until it hits an `LD B, B`
+//! breakpoint, then compare the screen pixel by pixel against a reference PNG.
+//!
+//! On a mismatch the screenshot and a diff image, with the differing pixels in red over a faded
+//! copy of the reference, are saved under the target directory and the test fails with their
+//! paths. Run with `UPDATE_SCREENSHOTS` set to write the screenshots over the references
+//! instead.
+//!
+//! The references of the ROMs built here are in `tests/screenshots`. Visual test ROMs such as
+//! dmg-acid2 aren't part of this repository: point `SCREENSHOT_ROMS_DIR` at a directory holding
+//! each ROM next to its reference, as `<name>.gb` and `<name>.png`, to check them too.
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+use rusty_gb::{
+    gameboy::{GameBoy, T_STATES_PER_FRAME},
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+use std::{
+    convert::TryFrom,
+    env, fs,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+const ROMS_DIR_VAR: &str = "SCREENSHOT_ROMS_DIR";
+const UPDATE_VAR: &str = "UPDATE_SCREENSHOTS";
+/// Frames a ROM gets to reach its breakpoint, about ten seconds
+const MAX_BREAKPOINT_FRAMES: u32 = 600;
+/// Grey level between two adjacent shades
+const SHADE_STEP: u8 = 85;
+const DIFF_COLOR: [u8; 3] = [0xFF, 0x00, 0x00];
+
+/// When to take the screenshot
+#[derive(Debug, Copy, Clone)]
+enum Stop {
+    /// After running this many frames
+    Frames(u32),
+    /// Once the ROM executes `LD B, B`, failing if it doesn't within `MAX_BREAKPOINT_FRAMES`
+    Breakpoint,
+}
+
+/// Runs `rom` until `stop` and returns its screen, as shades 0 (lightest) to 3 (darkest).
+fn capture(rom: Vec<u8>, stop: Stop) -> Vec<u8> {
+    let mut gameboy = GameBoy::new(rom).expect("ROM loads");
+    match stop {
+        Stop::Frames(frames) => {
+            for _ in 0..frames {
+                gameboy.run_frame();
+            }
+        }
+        Stop::Breakpoint => {
+            let max_t_states = u64::from(MAX_BREAKPOINT_FRAMES) * T_STATES_PER_FRAME;
+            assert!(
+                gameboy.run_until_breakpoint(max_t_states),
+                "No breakpoint within {} frames",
+                MAX_BREAKPOINT_FRAMES
+            );
+        }
+    }
+    gameboy.framebuffer().to_vec()
+}
+
+const fn grey(shade: u8) -> u8 {
+    0xFF - shade * SHADE_STEP
+}
+
+/// Closest shade to a grey level
+fn shade(grey: u8) -> u8 {
+    let darkness = 0xFF - grey;
+    darkness / SHADE_STEP + u8::from(darkness % SHADE_STEP > SHADE_STEP / 2)
+}
+
+/// Reads a reference screenshot as shades, whichever greys or colours it's saved with.
+fn read_reference(path: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|error| format!("{:?}: {}", path, error))?;
+    let mut decoder = Decoder::new(file);
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|error| format!("{:?}: {}", path, error))?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut pixels)
+        .map_err(|error| format!("{:?}: {}", path, error))?;
+
+    let size = (info.width as usize, info.height as usize);
+    if size != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+        return Err(format!("{:?} is {}x{}", path, size.0, size.1));
+    }
+    let channels = info.color_type.samples();
+    Ok(pixels[..info.buffer_size()]
+        .chunks(channels)
+        .map(|pixel| {
+            // alpha, if any, is ignored
+            let colours = &pixel[..channels.min(3)];
+            let sum: usize = colours.iter().map(|&colour| usize::from(colour)).sum();
+            shade(u8::try_from(sum / colours.len()).expect("average of bytes is a byte"))
+        })
+        .collect())
+}
+
+fn write_png(path: &Path, color_type: ColorType, pixels: &[u8]) {
+    let file = File::create(path).unwrap_or_else(|error| panic!("{:?}: {}", path, error));
+    let width = u32::try_from(SCREEN_WIDTH).expect("screen width fits");
+    let height = u32::try_from(SCREEN_HEIGHT).expect("screen height fits");
+    let mut encoder = Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .unwrap_or_else(|error| panic!("{:?}: {}", path, error));
+}
+
+fn write_screenshot(path: &Path, shades: &[u8]) {
+    let pixels: Vec<u8> = shades.iter().map(|&shade| grey(shade)).collect();
+    write_png(path, ColorType::Grayscale, &pixels);
+}
+
+/// Differing pixels in red over a faded reference
+fn write_diff(path: &Path, actual: &[u8], expected: &[u8]) {
+    let pixels: Vec<u8> = actual
+        .iter()
+        .zip(expected)
+        .flat_map(|(&actual, &expected)| match actual == expected {
+            true => [0xC0 + grey(expected) / 4; 3],
+            false => DIFF_COLOR,
+        })
+        .collect();
+    write_png(path, ColorType::Rgb, &pixels);
+}
+
+/// Checks the screen of `rom` at `stop` against the PNG at `reference`.
+///
+/// # Return value
+/// What went wrong, naming the screenshot and diff images saved for a mismatch.
+fn check_screenshot(name: &str, rom: Vec<u8>, stop: Stop, reference: &Path) -> Result<(), String> {
+    let actual = capture(rom, stop);
+    if env::var_os(UPDATE_VAR).is_some() {
+        write_screenshot(reference, &actual);
+        return Ok(());
+    }
+
+    let expected = read_reference(reference)?;
+    let mismatches = actual
+        .iter()
+        .zip(&expected)
+        .filter(|(actual, expected)| actual != expected)
+        .count();
+    if mismatches == 0 {
+        return Ok(());
+    }
+
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("screenshots");
+    fs::create_dir_all(&dir).unwrap_or_else(|error| panic!("{:?}: {}", dir, error));
+    let screenshot = dir.join(format!("{name}.png"));
+    let diff = dir.join(format!("{name}.diff.png"));
+    write_screenshot(&screenshot, &actual);
+    write_diff(&diff, &actual, &expected);
+    Err(format!(
+        "{name}: {mismatches} pixels differ from {}, see {} and {}",
+        reference.display(),
+        screenshot.display(),
+        diff.display()
+    ))
+}
+
+fn reference(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/screenshots")
+        .join(format!("{name}.png"))
+}
+
+/// Fills the top half of the background with a tile striped in the four shades, scrolled
+/// 3 pixels left, then executes `LD B, B` after two frames and loops.
+fn stripes_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0xF3,             // 0x0150: DI
+        0xAF,             // 0x0151: XOR A
+        0xE0, 0x40,       // 0x0152: LDH (0x40), A     ; LCD off
+        0x21, 0x10, 0x80, // 0x0154: LD HL, 0x8010     ; tile 1
+        0x06, 0x08,       // 0x0157: LD B, 8
+        0x3E, 0x55,       // 0x0159: LD A, 0x55
+        0x22,             // 0x015B: LD (HL+), A
+        0x3E, 0x33,       // 0x015C: LD A, 0x33
+        0x22,             // 0x015E: LD (HL+), A
+        0x05,             // 0x015F: DEC B
+        0x20, 0xF7,       // 0x0160: JR NZ, 0x0159
+        0x21, 0x00, 0x98, // 0x0162: LD HL, 0x9800     ; 9 rows of the map
+        0x01, 0x20, 0x01, // 0x0165: LD BC, 0x0120
+        0x3E, 0x01,       // 0x0168: LD A, 1
+        0x22,             // 0x016A: LD (HL+), A
+        0x0B,             // 0x016B: DEC BC
+        0x78,             // 0x016C: LD A, B
+        0xB1,             // 0x016D: OR C
+        0x20, 0xF8,       // 0x016E: JR NZ, 0x0168
+        0x3E, 0xE4,       // 0x0170: LD A, 0xE4
+        0xE0, 0x47,       // 0x0172: LDH (0x47), A     ; BGP
+        0x3E, 0x03,       // 0x0174: LD A, 3
+        0xE0, 0x43,       // 0x0176: LDH (0x43), A     ; SCX
+        0x3E, 0x91,       // 0x0178: LD A, 0x91
+        0xE0, 0x40,       // 0x017A: LDH (0x40), A     ; LCD on
+        0x16, 0x02,       // 0x017C: LD D, 2
+        0xF0, 0x44,       // 0x017E: LDH A, (0x44)     ; wait for VBlank
+        0xFE, 0x90,       // 0x0180: CP 144
+        0x20, 0xFA,       // 0x0182: JR NZ, 0x017E
+        0xF0, 0x44,       // 0x0184: LDH A, (0x44)     ; wait for the next line
+        0xFE, 0x90,       // 0x0186: CP 144
+        0x28, 0xFA,       // 0x0188: JR Z, 0x0184
+        0x15,             // 0x018A: DEC D
+        0x20, 0xF1,       // 0x018B: JR NZ, 0x017E
+        0x40,             // 0x018D: LD B, B
+        0x18, 0xFE,       // 0x018E: JR 0x018E
+    ];
+
+    let mut rom = vec![0; 0x8000];
+    // NOP, JP 0x0150 past the header
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x0150..0x0150 + program.len()].copy_from_slice(&program);
+    rom
+}
+
+#[test]
+fn screenshots_stop_at_breakpoint() {
+    let result = check_screenshot(
+        "stripes",
+        stripes_rom(),
+        Stop::Breakpoint,
+        &reference("stripes"),
+    );
+    result.unwrap_or_else(|message| panic!("{}", message));
+}
+
+#[test]
+fn screenshots_stop_after_frames() {
+    let result = check_screenshot(
+        "stripes_frames",
+        stripes_rom(),
+        Stop::Frames(10),
+        &reference("stripes"),
+    );
+    result.unwrap_or_else(|message| panic!("{}", message));
+}
+
+#[test]
+fn screenshots_test_roms() {
+    let dir = match env::var_os(ROMS_DIR_VAR) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("{ROMS_DIR_VAR} isn't set, skipping the visual test ROMs");
+            return;
+        }
+    };
+
+    let mut roms: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|error| panic!("{:?}: {}", dir, error))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "gb"))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty(), "No test ROMs in {:?}", dir);
+
+    let failures: Vec<String> = roms
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            let rom = fs::read(path).unwrap_or_else(|error| panic!("{:?}: {}", path, error));
+            check_screenshot(&name, rom, Stop::Breakpoint, &path.with_extension("png")).err()
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} screenshots differ:\n{}",
+        failures.len(),
+        roms.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,302 @@
+//! Runs the community SM83 single-step tests, one JSON file of vectors per opcode, against the
+//! CPU on a flat 64 KiB memory.
+//!
+//! Each vector gives the registers and the memory before and after one instruction, along with
+//! the bus activity of each of its M-cycles. The vectors aren't part of this repository: point
+//! `SM83_TESTS_DIR` at a checkout of their `v1` directory to run them, for instance
+//!
+//! ```text
+//! SM83_TESTS_DIR=../sm83/v1 cargo test --release --test lib sm83 -- --nocapture
+//! ```
+//!
+//! STOP is left out, as the vectors don't model the joypad it waits for.
+use rusty_gb::{
+    bus::{Bus, FlatMemory},
+    cpu::{
+        registers::{Register16b, Register8b},
+        Cpu, Timing,
+    },
+    interrupts::Interrupt,
+};
+use serde_json::{json, Value};
+use std::{convert::TryFrom, env, fs, path::Path};
+
+const TESTS_DIR_VAR: &str = "SM83_TESTS_DIR";
+const SKIPPED: &[&str] = &["10"];
+
+/// A memory access the CPU made in an M-cycle
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+/// Flat memory recording what the CPU does in each M-cycle, `None` for internal ones
+struct RecordingBus {
+    memory: FlatMemory,
+    cycles: Vec<Option<Access>>,
+    access: Option<Access>,
+}
+
+impl Bus for RecordingBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.memory.read(address);
+        self.access = Some(Access::Read(address, value));
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory.write(address, value);
+        self.access = Some(Access::Write(address, value));
+    }
+
+    fn tick(&mut self, t_states: u32) {
+        for _ in 0..t_states / 4 {
+            self.cycles.push(self.access.take());
+        }
+    }
+
+    // the vectors don't model interrupts, and these checks aren't bus cycles
+    fn pending_interrupts(&mut self) -> u8 {
+        0
+    }
+
+    fn acknowledge_interrupt(&mut self, _interrupt: Interrupt) {}
+}
+
+const REGISTERS: &[(&str, Register8b)] = &[
+    ("a", Register8b::A),
+    ("b", Register8b::B),
+    ("c", Register8b::C),
+    ("d", Register8b::D),
+    ("e", Register8b::E),
+    ("f", Register8b::F),
+    ("h", Register8b::H),
+    ("l", Register8b::L),
+];
+
+fn number<T: TryFrom<u64>>(value: &Value) -> T {
+    value
+        .as_u64()
+        .and_then(|number| T::try_from(number).ok())
+        .unwrap_or_else(|| panic!("Invalid number {}", value))
+}
+
+/// Memory entries of a state, as address and value pairs
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .expect("ram is an array")
+        .iter()
+        .map(|entry| (number(&entry[0]), number(&entry[1])))
+        .collect()
+}
+
+/// Bus activity of an M-cycle, from `[address, value, pins]` where pins reads `r-m` for a read
+/// and `-wm` for a write
+fn access(cycle: &Value) -> Option<Access> {
+    let pins = cycle.get(2).and_then(Value::as_str)?;
+    let address = cycle.get(0).and_then(Value::as_u64)?;
+    let value = cycle.get(1).and_then(Value::as_u64)?;
+    let (address, value) = (u16::try_from(address).ok()?, u8::try_from(value).ok()?);
+    match (pins.contains('r'), pins.contains('w')) {
+        (true, _) => Some(Access::Read(address, value)),
+        (_, true) => Some(Access::Write(address, value)),
+        _ => None,
+    }
+}
+
+fn setup(initial: &Value) -> Cpu<RecordingBus> {
+    let mut memory = FlatMemory::new();
+    for (address, value) in ram(initial) {
+        memory.write(address, value);
+    }
+    if initial.get("ie").is_some() {
+        memory.write(0xFFFF, number(&initial["ie"]));
+    }
+
+    let mut cpu = Cpu::with_bus(RecordingBus {
+        memory,
+        cycles: Vec::new(),
+        access: None,
+    });
+    cpu.set_timing(Timing::MCycle);
+    cpu.set_interrupt_master_enable(number::<u8>(&initial["ime"]) != 0);
+
+    let registers = cpu.registers_mut();
+    for &(name, register) in REGISTERS {
+        registers.set_r8(register, number(&initial[name]));
+    }
+    registers.set_r16(Register16b::SP, number(&initial["sp"]));
+    registers.pc = number(&initial["pc"]);
+    cpu
+}
+
+/// Runs one vector.
+///
+/// # Return value
+/// What differed from the expected state, if anything.
+fn run_vector(vector: &Value) -> Result<(), String> {
+    let mut cpu = setup(&vector["initial"]);
+    cpu.step();
+
+    let expected = &vector["final"];
+    let mut mismatches = Vec::new();
+    let registers = cpu.registers();
+    let mut actual: Vec<(&str, u16)> = REGISTERS
+        .iter()
+        .map(|&(name, register)| (name, u16::from(registers.get_r8(register))))
+        .collect();
+    actual.push(("sp", registers.sp));
+    actual.push(("pc", registers.pc));
+    if expected.get("ime").is_some() {
+        actual.push(("ime", u16::from(cpu.interrupt_master_enable())));
+    }
+    for (name, value) in actual {
+        let wanted: u16 = number(&expected[name]);
+        if wanted != value {
+            mismatches.push(format!("{name} {value:#X}, expected {wanted:#X}"));
+        }
+    }
+
+    let bus = cpu.bus_mut();
+    for (address, wanted) in ram(expected) {
+        let value = bus.memory.read(address);
+        if wanted != value {
+            mismatches.push(format!(
+                "({address:#06X}) {value:#04X}, expected {wanted:#04X}"
+            ));
+        }
+    }
+
+    let cycles: Vec<Option<Access>> = vector["cycles"]
+        .as_array()
+        .expect("cycles is an array")
+        .iter()
+        .map(access)
+        .collect();
+    if cycles != bus.cycles {
+        mismatches.push(format!("cycles {:?}, expected {cycles:?}", bus.cycles));
+    }
+
+    match mismatches.is_empty() {
+        true => Ok(()),
+        false => Err(mismatches.join(", ")),
+    }
+}
+
+/// Outcome of the vectors of one opcode
+struct OpcodeReport {
+    opcode: String,
+    passed: usize,
+    failed: usize,
+    first_failure: Option<String>,
+}
+
+fn run_file(path: &Path) -> OpcodeReport {
+    let opcode = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let text = fs::read_to_string(path).unwrap_or_else(|error| panic!("{:?}: {}", path, error));
+    let vectors: Vec<Value> =
+        serde_json::from_str(&text).unwrap_or_else(|error| panic!("{:?}: {}", path, error));
+
+    let mut report = OpcodeReport {
+        opcode,
+        passed: 0,
+        failed: 0,
+        first_failure: None,
+    };
+    for vector in &vectors {
+        match run_vector(vector) {
+            Ok(()) => report.passed += 1,
+            Err(mismatch) => {
+                report.failed += 1;
+                if report.first_failure.is_none() {
+                    report.first_failure = Some(format!("{}: {mismatch}", vector["name"]));
+                }
+            }
+        }
+    }
+    report
+}
+
+#[test]
+fn sm83_single_step_tests() {
+    let dir = match env::var_os(TESTS_DIR_VAR) {
+        Some(dir) => dir,
+        None => {
+            eprintln!("{TESTS_DIR_VAR} isn't set, skipping the SM83 single-step tests");
+            return;
+        }
+    };
+
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap_or_else(|error| panic!("{:?}: {}", dir, error))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter(|path| {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            !SKIPPED.contains(&stem.as_ref())
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No test vectors in {:?}", dir);
+
+    let reports: Vec<OpcodeReport> = paths.iter().map(|path| run_file(path)).collect();
+    println!("{:<8} {:>7} {:>7}", "opcode", "passed", "failed");
+    for report in &reports {
+        println!(
+            "{:<8} {:>7} {:>7}",
+            report.opcode, report.passed, report.failed
+        );
+    }
+
+    let failures: Vec<String> = reports
+        .iter()
+        .filter_map(|report| {
+            let failure = report.first_failure.as_ref()?;
+            Some(format!(
+                "{} ({} failed): {failure}",
+                report.opcode, report.failed
+            ))
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} opcodes failed:\n{}",
+        failures.len(),
+        reports.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn sm83_high_page_load_cycles() {
+    // LD (C), A and LD A, (C) have no operand to fetch, a cycle less than LDH
+    #[rustfmt::skip]
+    let programs: &[(&[u8], &[Option<Access>])] = &[
+        (&[0xE0, 0x80], &[Some(Access::Read(0x0100, 0xE0)), Some(Access::Read(0x0101, 0x80)), Some(Access::Write(0xFF80, 0x42))]),
+        (&[0xF0, 0x80], &[Some(Access::Read(0x0100, 0xF0)), Some(Access::Read(0x0101, 0x80)), Some(Access::Read(0xFF80, 0x00))]),
+        (&[0xE2], &[Some(Access::Read(0x0100, 0xE2)), Some(Access::Write(0xFF80, 0x42))]),
+        (&[0xF2], &[Some(Access::Read(0x0100, 0xF2)), Some(Access::Read(0xFF80, 0x00))]),
+    ];
+
+    for &(program, expected) in programs {
+        let ram: Vec<Value> = (0x0100..)
+            .zip(program)
+            .map(|(address, byte)| json!([address, byte]))
+            .collect();
+        let mut cpu = setup(&json!({
+            "a": 0x42, "b": 0, "c": 0x80, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0,
+            "sp": 0xDFF0, "pc": 0x0100, "ime": 0, "ram": ram,
+        }));
+        let t_states = cpu.step();
+        assert_eq!(expected, cpu.bus_mut().cycles.as_slice(), "{program:02X?}");
+        assert_eq!(expected.len() * 4, t_states as usize, "{program:02X?}");
+    }
+}
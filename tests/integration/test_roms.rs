@@ -0,0 +1,263 @@
+//! Runs a directory of test ROMs and prints which of them pass, as an accuracy scoreboard.
+//!
+//! Each ROM runs until it reports a result in one of the ways the common suites do:
+//!
+//! * Blargg's print `Passed` or `Failed` to the serial port, and also keep their status in
+//!   cartridge RAM: once `0xA001..=0xA003` holds the signature `DE B0 61`, `0xA000` reads `0x80`
+//!   while the test runs and then its result code, `0` for a pass, with the text output from
+//!   `0xA004` on.
+//! * Mooneye's execute `LD B, B` when done, with B, C, D, E, H and L holding the Fibonacci
+//!   numbers 3, 5, 8, 13, 21 and 34 for a pass, or all holding `0x42` for a failure.
+//!
+//! The ROMs aren't part of this repository: point `TEST_ROMS_DIR` at a directory of them, which
+//! is searched recursively for `.gb` files, for instance
+//!
+//! ```text
+//! TEST_ROMS_DIR=../gb-test-roms cargo test --release --test lib test_roms -- --nocapture
+//! ```
+//!
+//! Set `TEST_ROMS_REPORT` to a path to also save the table there. A failing ROM doesn't fail
+//! the test, as the point is to track how many pass from one release to the next. ROMs the
+//! console can't load yet, such as those needing a memory bank controller, are listed as
+//! unsupported, and those making the emulator panic, say on an invalid opcode, as crashed.
+use rusty_gb::{
+    cpu::registers::Register8b,
+    gameboy::{GameBoy, T_STATES_PER_FRAME},
+};
+use std::{
+    any::Any,
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+const ROMS_DIR_VAR: &str = "TEST_ROMS_DIR";
+const REPORT_VAR: &str = "TEST_ROMS_REPORT";
+/// Two minutes, which the slowest of Blargg's ROMs finish well within
+const TIMEOUT_T_STATES: u64 = 120 * 4_194_304;
+
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const STATUS_RUNNING: u8 = 0x80;
+const FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAILED: u8 = 0x42;
+
+/// How a ROM ended
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    Failed(String),
+    TimedOut,
+    Unsupported(String),
+    /// The emulator panicked, with this message
+    Crashed(String),
+}
+
+impl Outcome {
+    fn label(&self) -> &'static str {
+        match self {
+            Outcome::Passed => "pass",
+            Outcome::Failed(_) => "FAIL",
+            Outcome::TimedOut => "timeout",
+            Outcome::Unsupported(_) => "unsupported",
+            Outcome::Crashed(_) => "CRASH",
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            Outcome::Failed(detail) | Outcome::Unsupported(detail) | Outcome::Crashed(detail) => {
+                detail
+            }
+            Outcome::Passed | Outcome::TimedOut => "",
+        }
+    }
+}
+
+/// Text of a Blargg ROM, made single line for the table
+fn summarize(text: &[u8]) -> String {
+    String::from_utf8_lossy(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Result of a Mooneye ROM that just hit its breakpoint
+fn mooneye_outcome(gameboy: &GameBoy) -> Outcome {
+    let registers = gameboy.cpu().registers();
+    let values = [
+        Register8b::B,
+        Register8b::C,
+        Register8b::D,
+        Register8b::E,
+        Register8b::H,
+        Register8b::L,
+    ]
+    .map(|register| registers.get_r8(register));
+    match values {
+        FIBONACCI => Outcome::Passed,
+        _ if values.iter().all(|&value| value == MOONEYE_FAILED) => {
+            Outcome::Failed(String::from("registers hold 0x42"))
+        }
+        _ => Outcome::Failed(format!("breakpoint with B-L {values:02X?}")),
+    }
+}
+
+/// Result a Blargg ROM left in cartridge RAM, if it's done
+fn memory_outcome(gameboy: &GameBoy) -> Option<Outcome> {
+    let mmu = gameboy.mmu();
+    let signature = [
+        mmu.read_byte(0xA001),
+        mmu.read_byte(0xA002),
+        mmu.read_byte(0xA003),
+    ];
+    let status = mmu.read_byte(0xA000);
+    if signature != SIGNATURE || status == STATUS_RUNNING {
+        return None;
+    }
+
+    let text: Vec<u8> = (0xA004..0xC000)
+        .map(|address| mmu.read_byte(address))
+        .take_while(|&byte| byte != 0)
+        .collect();
+    Some(match status {
+        0 => Outcome::Passed,
+        code => Outcome::Failed(format!("code {code}: {}", summarize(&text))),
+    })
+}
+
+/// Result a Blargg ROM printed to the serial port, if it's done
+fn serial_outcome(gameboy: &GameBoy) -> Option<Outcome> {
+    let output = gameboy.mmu().serial().captured();
+    let contains = |pattern: &[u8]| output.windows(pattern.len()).any(|w| w == pattern);
+    match (contains(b"Passed"), contains(b"Failed")) {
+        (_, true) => Some(Outcome::Failed(summarize(output))),
+        (true, false) => Some(Outcome::Passed),
+        (false, false) => None,
+    }
+}
+
+/// Message a panic was raised with
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => (*message).to_string(),
+        (_, Some(message)) => message.clone(),
+        (None, None) => String::from("panicked"),
+    }
+}
+
+/// Runs `rom`, a panic of the emulator ending it like any other result.
+fn run_rom(rom: Vec<u8>) -> Outcome {
+    panic::catch_unwind(AssertUnwindSafe(|| run_to_result(rom)))
+        .unwrap_or_else(|payload| Outcome::Crashed(summarize(panic_message(&*payload).as_bytes())))
+}
+
+fn run_to_result(rom: Vec<u8>) -> Outcome {
+    let mut gameboy = match GameBoy::new(rom) {
+        Ok(gameboy) => gameboy,
+        Err(error) => return Outcome::Unsupported(error.to_string()),
+    };
+    gameboy.mmu_mut().serial_mut().set_capture(true);
+
+    let mut checked_output = 0;
+    let mut next_memory_check = T_STATES_PER_FRAME;
+    while gameboy.clock() < TIMEOUT_T_STATES {
+        if gameboy.at_breakpoint() {
+            gameboy.step();
+            return mooneye_outcome(&gameboy);
+        }
+        gameboy.step();
+
+        let output_length = gameboy.mmu().serial().captured().len();
+        if output_length != checked_output {
+            checked_output = output_length;
+            if let Some(outcome) = serial_outcome(&gameboy) {
+                return outcome;
+            }
+        }
+        // the status in RAM only needs checking now and then
+        if gameboy.clock() >= next_memory_check {
+            next_memory_check += T_STATES_PER_FRAME;
+            if let Some(outcome) = memory_outcome(&gameboy) {
+                return outcome;
+            }
+        }
+    }
+    Outcome::TimedOut
+}
+
+/// `.gb` files under `dir`, at any depth
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|error| panic!("{:?}: {}", dir, error));
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_roms_scoreboard() {
+    let dir = match env::var_os(ROMS_DIR_VAR) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("{ROMS_DIR_VAR} isn't set, skipping the test ROMs");
+            return;
+        }
+    };
+
+    let mut roms = Vec::new();
+    find_roms(&dir, &mut roms);
+    roms.sort();
+    assert!(!roms.is_empty(), "No test ROMs in {:?}", dir);
+
+    let results: Vec<(String, Outcome)> = roms
+        .iter()
+        .map(|path| {
+            let name = path.strip_prefix(&dir).unwrap_or(path);
+            let rom = fs::read(path).unwrap_or_else(|error| panic!("{:?}: {}", path, error));
+            (name.display().to_string(), run_rom(rom))
+        })
+        .collect();
+
+    let width = results
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+    let mut table = String::new();
+    for (name, outcome) in &results {
+        let line = format!(
+            "{name:<width$} {:<11} {}",
+            outcome.label(),
+            outcome.detail()
+        );
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    let passed = results
+        .iter()
+        .filter(|(_, outcome)| *outcome == Outcome::Passed)
+        .count();
+    table.push_str(&format!("\n{passed} of {} passed\n", results.len()));
+
+    print!("{table}");
+    if let Some(report) = env::var_os(REPORT_VAR) {
+        fs::write(&report, &table).unwrap_or_else(|error| panic!("{:?}: {}", report, error));
+    }
+}
+
+#[test]
+fn test_roms_invalid_opcode_crashes() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100] = 0xD3;
+
+    match run_rom(rom) {
+        Outcome::Crashed(message) => assert!(message.contains("invalid instruction")),
+        outcome => panic!("{:?}", outcome),
+    }
+}
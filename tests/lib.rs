@@ -1 +1,5 @@
-mod integration {}
+mod integration {
+    mod screenshots;
+    mod sm83;
+    mod test_roms;
+}